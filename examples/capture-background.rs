@@ -0,0 +1,48 @@
+//! Captures video on a background thread, while the main thread processes only the newest frame
+//! every 200 ms (printing its timestamp and the number of frames that were skipped).
+//!
+//! Uses [`linuxvideo::stream::BackgroundCapture`] to capture from the
+//! [`linuxvideo::stream::ReadStream`], and a [`linuxvideo::stream::FrameReceiver`] to wait for new
+//! frames.
+
+use std::{env, path::Path, thread, time::Duration};
+
+use anyhow::anyhow;
+use linuxvideo::{format::Format, stream::BackgroundCapture, BufType, Device};
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let path = env::args_os()
+        .nth(1)
+        .ok_or_else(|| anyhow!("usage: capture-background <device>"))?;
+
+    let device = Device::open(Path::new(&path))?;
+    let Format::VideoCapture(fmt) = device.format(BufType::VIDEO_CAPTURE)? else {
+        unreachable!()
+    };
+    let capture = device.video_capture(fmt)?;
+    println!("negotiated format: {:?}", capture.format());
+
+    let background = BackgroundCapture::spawn(capture.into_stream()?)?;
+    let mut receiver = background.receiver();
+    println!("capture thread started, waiting for data");
+    for _ in 0..25 {
+        let frame = receiver.recv_timeout(Duration::from_secs(5))?;
+        println!(
+            "frame #{} at {:?}, {} bytes, {} frames dropped so far",
+            frame.sequence(),
+            frame.timestamp(),
+            frame.len(),
+            receiver.dropped_frames(),
+        );
+
+        // Simulate slow processing. The capture thread keeps dequeuing frames in the meantime.
+        thread::sleep(Duration::from_millis(200));
+    }
+
+    background.stop()?;
+    println!("capture thread stopped");
+
+    Ok(())
+}
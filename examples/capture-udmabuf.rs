@@ -0,0 +1,103 @@
+//! Captures frames into dma-bufs allocated via `/dev/udmabuf`, printing a checksum of every frame.
+//!
+//! `udmabuf` turns `memfd` memory into dma-bufs, which makes it possible to test dma-buf import
+//! with virtual drivers like `vivid` (load the `udmabuf` module first, and make sure the user can
+//! open `/dev/udmabuf`).
+//!
+//! Uses the [`linuxvideo::stream::ReadStream`] returned by
+//! [`linuxvideo::VideoCaptureDevice::into_dmabuf_stream`].
+
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::Path,
+};
+
+use anyhow::anyhow;
+use linuxvideo::{format::Format, BufType, Device};
+use uoctl::{Ioctl, _IOW};
+
+const BUFFER_COUNT: usize = 4;
+
+#[repr(C)]
+struct UdmabufCreate {
+    memfd: u32,
+    flags: u32,
+    offset: u64,
+    size: u64,
+}
+
+const UDMABUF_FLAGS_CLOEXEC: u32 = 0x01;
+const UDMABUF_CREATE: Ioctl<*const UdmabufCreate> = _IOW(b'u', 0x42);
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut args = env::args_os().skip(1);
+
+    let path = args
+        .next()
+        .ok_or_else(|| anyhow!("usage: capture-udmabuf <device>"))?;
+
+    let device = Device::open(Path::new(&path))?;
+    let Format::VideoCapture(fmt) = device.format(BufType::VIDEO_CAPTURE)? else {
+        unreachable!()
+    };
+    let capture = device.video_capture(fmt)?;
+    println!("negotiated format: {:?}", capture.format());
+
+    let udmabuf = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/udmabuf")?;
+    let size = capture.format().size_image() as usize;
+    let dmabufs = (0..BUFFER_COUNT)
+        .map(|_| alloc_udmabuf(&udmabuf, size))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut stream = capture.into_dmabuf_stream(dmabufs)?;
+    for frame in 0..30 {
+        stream.dequeue(|buf| {
+            let checksum = buf
+                .iter()
+                .fold(0u32, |acc, &b| acc.rotate_left(1) ^ b as u32);
+            println!(
+                "frame {frame}: dma-buf {}, {} bytes, checksum {checksum:08x}",
+                buf.index(),
+                buf.len(),
+            );
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Allocates a dma-buf of at least `size` bytes, backed by a sealed `memfd`.
+fn alloc_udmabuf(udmabuf: &File, size: usize) -> io::Result<OwnedFd> {
+    // udmabuf requires whole pages.
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let size = size.div_ceil(page_size) * page_size;
+
+    let memfd = unsafe { libc::memfd_create(c"linuxvideo".as_ptr(), libc::MFD_ALLOW_SEALING) };
+    if memfd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let memfd = unsafe { OwnedFd::from_raw_fd(memfd) };
+    File::from(memfd.try_clone()?).set_len(size as u64)?;
+    // The memfd must not be able to shrink while the dma-buf exists.
+    if unsafe { libc::fcntl(memfd.as_raw_fd(), libc::F_ADD_SEALS, libc::F_SEAL_SHRINK) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let create = UdmabufCreate {
+        memfd: memfd.as_raw_fd() as u32,
+        flags: UDMABUF_FLAGS_CLOEXEC,
+        offset: 0,
+        size: size as u64,
+    };
+    let fd = unsafe { UDMABUF_CREATE.ioctl(udmabuf, &create)? };
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
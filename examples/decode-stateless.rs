@@ -0,0 +1,122 @@
+//! Decodes an FWHT bitstream file with a stateless decoder (like the stateless decoder of
+//! `vicodec`), printing the size of every decoded frame.
+//!
+//! The frame headers of the bitstream are parsed here and passed to the decoder via the
+//! `FwhtParams` control. A suitable bitstream can be produced with the `encode` example.
+//!
+//! Uses the [`linuxvideo::codec::stateless::StatelessDecoder`] returned by
+//! [`linuxvideo::codec::stateless::StatelessDecoderDevice::into_decoder`].
+
+use std::{env, fs, time::Duration};
+
+use anyhow::{anyhow, bail};
+use linuxvideo::{
+    codec::stateless::{reference_timestamp, FwhtFlags, FwhtParams},
+    controls::ExtControls,
+    format::{PixFormat, PixelFormat},
+    media::MediaDevice,
+    Device,
+};
+
+/// Size of the header that precedes every FWHT frame.
+const HEADER_SIZE: usize = 44;
+const MAGIC: [u8; 8] = [0x4f, 0x4f, 0x4f, 0x4f, 0xff, 0xff, 0xff, 0xff];
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut args = env::args_os().skip(1);
+
+    let (Some(device), Some(media), Some(input)) = (args.next(), args.next(), args.next()) else {
+        return Err(anyhow!(
+            "usage: decode-stateless <device> <media-device> <input-file>"
+        ));
+    };
+
+    let input = fs::read(input)?;
+    let mut frames = Frames { data: &input };
+    let Some((first, _)) = frames.clone().next().transpose()? else {
+        bail!("input file contains no frames");
+    };
+
+    let device = Device::open(device)?;
+    let decoder = device.video_stateless_decoder(
+        PixFormat::new(first.width, first.height, PixelFormat::FWHT_STATELESS),
+        PixFormat::new(first.width, first.height, PixelFormat::YUYV),
+    )?;
+    println!("coded format: {:?}", decoder.coded_format());
+    println!("decoded format: {:?}", decoder.decoded_format());
+
+    let media = MediaDevice::open(media)?;
+    let mut decoder = decoder.into_decoder(&media)?;
+    let mut controls = ExtControls::new();
+    let mut prev_timestamp = Duration::ZERO;
+    let mut i = 0;
+    while let Some((mut params, frame)) = frames.next().transpose()? {
+        let timestamp = Duration::from_millis(i + 1);
+        if !params.flags.contains(FwhtFlags::I_FRAME) {
+            params.backward_ref_ts = reference_timestamp(prev_timestamp);
+        }
+        controls.clear();
+        controls.set_compound(&params);
+
+        let len = decoder.decode(
+            timestamp,
+            &mut controls,
+            |mut buf| {
+                buf[..frame.len()].copy_from_slice(frame);
+                buf.set_bytes_used(frame.len());
+                Ok(())
+            },
+            |buf| Ok(buf.len()),
+        )?;
+        println!("frame {i}: {len} bytes");
+
+        prev_timestamp = timestamp;
+        i += 1;
+    }
+
+    Ok(())
+}
+
+/// Splits an FWHT bitstream into frames, and parses their headers.
+#[derive(Clone)]
+struct Frames<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = anyhow::Result<(FwhtParams, &'a [u8])>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        if self.data.len() < HEADER_SIZE || self.data[..8] != MAGIC {
+            self.data = &[];
+            return Some(Err(anyhow!("invalid FWHT frame header")));
+        }
+
+        let field = |i: usize| {
+            let offset = 8 + i * 4;
+            u32::from_be_bytes(self.data[offset..offset + 4].try_into().unwrap())
+        };
+        let params = FwhtParams {
+            version: field(0),
+            width: field(1),
+            height: field(2),
+            flags: FwhtFlags::from_bits_truncate(field(3)),
+            colorspace: field(4),
+            xfer_func: field(5),
+            ycbcr_enc: field(6),
+            quantization: field(7),
+            ..Default::default()
+        };
+        let len = (HEADER_SIZE + field(8) as usize).min(self.data.len());
+
+        // The decoder expects the whole frame, including the header.
+        let (frame, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(Ok((params, frame)))
+    }
+}
@@ -0,0 +1,58 @@
+//! Decodes a compressed bitstream file with a stateful decoder (like the FWHT decoder of
+//! `vicodec`), printing the format and size of every decoded frame.
+//!
+//! A suitable FWHT bitstream can be produced with the `encode` example.
+//!
+//! Uses the [`linuxvideo::codec::Decoder`] returned by [`linuxvideo::codec::DecoderDevice::into_decoder`].
+
+use std::{
+    env,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+};
+
+use anyhow::anyhow;
+use linuxvideo::{
+    format::{PixFormat, PixelFormat},
+    Device,
+};
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut args = env::args_os().skip(1);
+
+    let (Some(device), Some(input)) = (args.next(), args.next()) else {
+        return Err(anyhow!("usage: decode <device> <input-file>"));
+    };
+
+    let device = Device::open(device)?;
+    let decoder = device.video_decoder(PixFormat::new(0, 0, PixelFormat::FWHT))?;
+    println!("coded format: {:?}", decoder.coded_format());
+
+    let mut decoder = decoder.into_decoder()?;
+    let mut input = BufReader::new(File::open(input)?);
+    let mut frames = 0;
+    while !input.fill_buf()?.is_empty() {
+        decoder.decode(
+            |mut buf| {
+                let len = input.read(&mut buf)?;
+                buf.set_bytes_used(len);
+                Ok(())
+            },
+            |frame| {
+                frames += 1;
+                println!("frame {frames}: {} bytes", frame.len());
+                Ok(())
+            },
+        )?;
+    }
+    decoder.drain(|frame| {
+        frames += 1;
+        println!("frame {frames}: {} bytes", frame.len());
+        Ok(())
+    })?;
+
+    println!("decoded {frames} frames to {:?}", decoder.capture_format());
+    Ok(())
+}
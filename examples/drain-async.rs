@@ -0,0 +1,44 @@
+//! Captures video on a tokio runtime, printing the sequence number and timestamp of every frame.
+//!
+//! Uses the [`linuxvideo::stream::tokio::AsyncReadStream`] wrapper, which requires the `tokio`
+//! feature.
+
+use std::{env, path::Path};
+
+use anyhow::anyhow;
+use futures_util::StreamExt;
+use linuxvideo::{format::Format, stream::tokio::AsyncReadStream, BufType, Device};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut args = env::args_os().skip(1);
+
+    let path = args
+        .next()
+        .ok_or_else(|| anyhow!("usage: drain-async <device>"))?;
+
+    let device = Device::open(Path::new(&path))?;
+
+    let Format::VideoCapture(fmt) = device.format(BufType::VIDEO_CAPTURE)? else {
+        unreachable!()
+    };
+    let capture = device.video_capture(fmt)?;
+    println!("negotiated format: {:?}", capture.format());
+
+    let mut stream = AsyncReadStream::new(capture.into_stream()?)?;
+
+    println!("stream started, waiting for data");
+    while let Some(frame) = stream.next().await {
+        let frame = frame?;
+        println!(
+            "frame #{}: {} bytes, timestamp {:?}",
+            frame.sequence(),
+            frame.len(),
+            frame.timestamp(),
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+//! Captures video from a multi-planar device and ignores the video data (printing the number of
+//! bytes in each plane of the first frame, and a `.` to the screen for every frame received).
+//!
+//! Uses the [`linuxvideo::stream::ReadStream`] returned by
+//! [`linuxvideo::VideoCaptureMplaneDevice::into_stream`] to read image data.
+
+use std::{
+    env,
+    io::Write,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use linuxvideo::{format::Format, BufType, Device};
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut args = env::args_os().skip(1);
+
+    let path = args
+        .next()
+        .ok_or_else(|| anyhow!("usage: drain-mplane <device>"))?;
+
+    let device = Device::open(Path::new(&path))?;
+
+    println!(
+        "capabilities: {:?}",
+        device.capabilities()?.device_capabilities()
+    );
+
+    let Format::VideoCaptureMplane(fmt) = device.format(BufType::VIDEO_CAPTURE_MPLANE)? else {
+        unreachable!()
+    };
+    let capture = device.video_capture_mplane(fmt)?;
+    println!("negotiated format: {:?}", capture.format());
+
+    let mut stream = capture.into_stream()?;
+
+    println!("stream started, waiting for data");
+    stream.dequeue(|buf| {
+        for (i, plane) in buf.planes().iter().enumerate() {
+            println!(
+                "plane {i}: {} bytes (data offset {}, raw buffer size {})",
+                plane.len(),
+                plane.data_offset(),
+                plane.raw_buffer().len(),
+            );
+        }
+        Ok(())
+    })?;
+
+    let mut frames = 0;
+    let mut time = Instant::now();
+    loop {
+        stream.dequeue(|_buf| Ok(()))?;
+
+        frames += 1;
+        print!(".");
+        std::io::stdout().flush().ok();
+
+        if time.elapsed() >= Duration::from_secs(1) {
+            println!(" {} FPS", frames);
+
+            time = Instant::now();
+            frames = 0;
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! Captures video from several devices at once, printing the timestamps of every set of frames
+//! captured within 10 ms of each other.
+//!
+//! Uses [`linuxvideo::stream::MultiStream`] to wait for frames from all devices on one thread.
+
+use std::{env, path::Path, time::Duration};
+
+use anyhow::anyhow;
+use linuxvideo::{format::Format, stream::MultiStream, BufType, Device};
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let paths = env::args_os().skip(1).collect::<Vec<_>>();
+    if paths.is_empty() {
+        return Err(anyhow!("usage: drain-multi <device>..."));
+    }
+
+    let mut streams = Vec::new();
+    for path in &paths {
+        let device = Device::open(Path::new(path))?;
+        let Format::VideoCapture(fmt) = device.format(BufType::VIDEO_CAPTURE)? else {
+            unreachable!()
+        };
+        let capture = device.video_capture(fmt)?;
+        println!("{}: {:?}", path.to_string_lossy(), capture.format());
+        streams.push(capture.into_stream()?);
+    }
+
+    let mut multi = MultiStream::new(streams);
+    println!("streams started, waiting for data");
+    loop {
+        multi.dequeue_frameset(Duration::from_millis(10), |frames| {
+            let timestamps = frames
+                .iter()
+                .map(|frame| frame.timestamp())
+                .collect::<Vec<_>>();
+            println!("{:?}", timestamps);
+            Ok(())
+        })?;
+    }
+}
@@ -2,7 +2,9 @@
 //! received).
 //!
 //! Uses the [`linuxvideo::stream::ReadStream`] returned by [`linuxvideo::VideoCaptureDevice::into_stream`]
-//! to read image data.
+//! to read image data. With `--userptr`, the frames are captured into application-allocated
+//! [`linuxvideo::stream::UserBuffer`]s via [`linuxvideo::VideoCaptureDevice::into_userptr_stream`]
+//! instead (`vivid` supports this).
 
 use std::{
     env,
@@ -12,16 +14,20 @@ use std::{
 };
 
 use anyhow::anyhow;
-use linuxvideo::{format::Format, BufType, Device};
+use linuxvideo::{format::Format, stream::UserBuffer, BufType, Device};
+
+/// Number of buffers to allocate in `--userptr` mode.
+const USER_BUFFERS: usize = 4;
 
 fn main() -> anyhow::Result<()> {
     env_logger::init();
 
-    let mut args = env::args_os().skip(1);
+    let mut args = env::args_os().skip(1).peekable();
 
+    let userptr = args.next_if(|arg| arg == "--userptr").is_some();
     let path = args
         .next()
-        .ok_or_else(|| anyhow!("usage: drain-stream <device>"))?;
+        .ok_or_else(|| anyhow!("usage: drain-stream [--userptr] <device>"))?;
 
     let device = Device::open(Path::new(&path))?;
 
@@ -36,13 +42,19 @@ fn main() -> anyhow::Result<()> {
     let capture = device.video_capture(fmt)?;
     println!("negotiated format: {:?}", capture.format());
 
-    let mut stream = capture.into_stream()?;
+    let mut stream = if userptr {
+        let size = capture.format().size_image() as usize;
+        let buffers = (0..USER_BUFFERS).map(|_| UserBuffer::new(size)).collect();
+        capture.into_userptr_stream(buffers)?
+    } else {
+        capture.into_stream()?
+    };
 
     println!("stream started, waiting for data");
     let mut frames = 0;
     let mut time = Instant::now();
-    loop {
-        stream.dequeue(|_buf| Ok(()))?;
+    for frame in stream.frames() {
+        frame?;
 
         frames += 1;
         print!(".");
@@ -55,4 +67,6 @@ fn main() -> anyhow::Result<()> {
             frames = 0;
         }
     }
+
+    Ok(())
 }
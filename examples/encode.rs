@@ -0,0 +1,65 @@
+//! Encodes generated frames with a stateful encoder (like the FWHT encoder of `vicodec`) and
+//! writes the bitstream to a file.
+//!
+//! Uses the [`linuxvideo::codec::Encoder`] returned by [`linuxvideo::codec::EncoderDevice::into_encoder`].
+
+use std::{env, fs::File, io::Write, time::Duration};
+
+use anyhow::anyhow;
+use linuxvideo::{
+    format::{PixFormat, PixelFormat},
+    stream::ReadBufferView,
+    Device,
+};
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+const FRAMES: u32 = 60;
+const KEYFRAME_INTERVAL: u32 = 30;
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut args = env::args_os().skip(1);
+
+    let (Some(device), Some(out)) = (args.next(), args.next()) else {
+        return Err(anyhow!("usage: encode <device> <output-file>"));
+    };
+
+    let device = Device::open(device)?;
+    let encoder = device.video_encoder(
+        PixFormat::new(WIDTH, HEIGHT, PixelFormat::FWHT),
+        PixFormat::new(WIDTH, HEIGHT, PixelFormat::YUYV),
+    )?;
+    println!("coded format: {:?}", encoder.coded_format());
+    println!("raw format: {:?}", encoder.raw_format());
+
+    let mut encoder = encoder.into_encoder()?;
+    let mut out = File::create(out)?;
+    let mut on_frame = |buf: ReadBufferView<'_>| {
+        println!(
+            "{:?}: {:?}, {} bytes",
+            buf.timestamp(),
+            buf.frame_type(),
+            buf.len()
+        );
+        out.write_all(&buf)
+    };
+
+    for i in 0..FRAMES {
+        if i % KEYFRAME_INTERVAL == 0 {
+            encoder.request_keyframe()?;
+        }
+        encoder.encode(
+            |mut buf| {
+                buf.fill((i * 255 / FRAMES) as u8);
+                buf.set_timestamp(Duration::from_millis(i.into()));
+                Ok(())
+            },
+            &mut on_frame,
+        )?;
+    }
+    encoder.drain(&mut on_frame)?;
+
+    Ok(())
+}
@@ -0,0 +1,33 @@
+//! Subscribes to source change and end-of-stream events and prints every event received.
+//!
+//! The `vivid` driver emits source change events when its emulated input changes resolution (for
+//! example, when the "DV Timings Signal Mode" control is changed).
+
+use std::{env, path::Path};
+
+use anyhow::anyhow;
+use linuxvideo::{
+    event::{EventSubscriptionFlags, EventType},
+    Device,
+};
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut args = env::args_os().skip(1);
+
+    let path = args
+        .next()
+        .ok_or_else(|| anyhow!("usage: events <device>"))?;
+
+    let device = Device::open(Path::new(&path))?;
+
+    device.subscribe_event(EventType::SOURCE_CHANGE, 0, EventSubscriptionFlags::empty())?;
+    device.subscribe_event(EventType::EOS, 0, EventSubscriptionFlags::empty())?;
+
+    println!("waiting for events");
+    loop {
+        let event = device.dequeue_event()?;
+        println!("{:?}", event);
+    }
+}
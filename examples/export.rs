@@ -0,0 +1,81 @@
+//! Exports the capture buffers of a device (like `vivid`) as dma-bufs, and checks that every
+//! captured frame can be read back through its dma-buf.
+//!
+//! Uses [`linuxvideo::stream::ReadStream::export_buffers`] and
+//! [`linuxvideo::stream::ReadStream::dequeue_exported`].
+
+use std::{env, io, os::fd::AsRawFd, path::Path, ptr, slice};
+
+use anyhow::anyhow;
+use linuxvideo::{format::Format, BufType, Device};
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut args = env::args_os().skip(1);
+
+    let path = args
+        .next()
+        .ok_or_else(|| anyhow!("usage: export <device>"))?;
+
+    let device = Device::open(Path::new(&path))?;
+    let Format::VideoCapture(fmt) = device.format(BufType::VIDEO_CAPTURE)? else {
+        unreachable!()
+    };
+    let capture = device.video_capture(fmt)?;
+    println!("negotiated format: {:?}", capture.format());
+
+    let mut stream = capture.into_stream()?;
+    let dmabufs = stream.export_buffers()?;
+    // Removed buffers would show up as `None`, but this stream never removes any.
+    for (i, fd) in dmabufs.iter().enumerate() {
+        if let Some(fd) = fd {
+            println!("buffer {i}: dma-buf fd {}", fd.as_raw_fd());
+        }
+    }
+
+    for frame in 0..30 {
+        stream.dequeue_exported(|fd, buf| {
+            // A consumer would usually import the dma-buf into another device. Here, it is mapped
+            // and compared with the data the stream has mapped.
+            let matches = with_mapped(fd.as_raw_fd(), buf.len(), |data| data == &*buf)?;
+            println!(
+                "frame {frame}: buffer {}, dma-buf fd {}, {} bytes, contents match: {matches}",
+                buf.index(),
+                fd.as_raw_fd(),
+                buf.len(),
+            );
+            if !matches {
+                return Err(io::Error::other("dma-buf contents differ from buffer"));
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Maps the first `len` bytes of the dma-buf `fd` and passes them to `f`.
+fn with_mapped<T>(fd: i32, len: usize, f: impl FnOnce(&[u8]) -> T) -> io::Result<T> {
+    if len == 0 {
+        return Ok(f(&[]));
+    }
+    let ptr = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_SHARED,
+            fd,
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    let res = f(unsafe { slice::from_raw_parts(ptr.cast(), len) });
+    unsafe {
+        libc::munmap(ptr, len);
+    }
+    Ok(res)
+}
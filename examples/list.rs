@@ -6,7 +6,7 @@ fn main() -> io::Result<()> {
     env_logger::init();
 
     for res in linuxvideo::list()? {
-        match res.and_then(|device| list_device(device)) {
+        match res.and_then(list_device) {
             Ok(()) => {}
             Err(e) => {
                 eprintln!("skipping device due to error: {}", e);
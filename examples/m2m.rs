@@ -0,0 +1,53 @@
+//! Converts frames with a memory-to-memory device (like `vim2m`) and prints the size of every
+//! converted frame.
+//!
+//! Uses the [`linuxvideo::stream::M2mStream`] returned by [`linuxvideo::M2mDevice::into_stream`].
+
+use std::{env, time::Duration};
+
+use anyhow::{anyhow, bail};
+use linuxvideo::{
+    format::{PixFormat, PixelFormat},
+    CapabilityFlags, Device,
+};
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+const FRAMES: u32 = 30;
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut args = env::args_os().skip(1);
+
+    let path = args.next().ok_or_else(|| anyhow!("usage: m2m <device>"))?;
+
+    let device = Device::open(path)?;
+    let caps = device.capabilities()?.device_capabilities();
+    if !caps.contains(CapabilityFlags::VIDEO_M2M) {
+        bail!("selected device does not support the `VIDEO_M2M` capability");
+    }
+
+    let m2m = device.video_m2m(
+        PixFormat::new(WIDTH, HEIGHT, PixelFormat::RGB3),
+        PixFormat::new(WIDTH, HEIGHT, PixelFormat::BGR3),
+    )?;
+    println!("output format: {:?}", m2m.output_format());
+    println!("capture format: {:?}", m2m.capture_format());
+
+    let mut stream = m2m.into_stream()?;
+    for i in 0..FRAMES {
+        let len = stream.process(
+            |mut buf| {
+                let shade = (i * 255 / FRAMES) as u8;
+                buf.fill(shade);
+                buf.set_timestamp(Duration::from_millis(i.into()));
+                Ok(())
+            },
+            |buf| Ok(buf.len()),
+        )?;
+        println!("frame {i}: {len} bytes");
+    }
+
+    Ok(())
+}
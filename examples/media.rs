@@ -0,0 +1,77 @@
+//! Prints the topology of a media controller device, or of all media devices if none is given.
+//!
+//! Uses [`linuxvideo::media::MediaDevice::topology`].
+
+use std::{env, path::Path};
+
+use linuxvideo::media::{self, MediaDevice};
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    match env::args_os().nth(1) {
+        Some(path) => print_device(&MediaDevice::open(Path::new(&path))?)?,
+        None => {
+            for device in media::list()? {
+                print_device(&device?)?;
+                println!();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_device(device: &MediaDevice) -> anyhow::Result<()> {
+    let info = device.info()?;
+    println!(
+        "{}: {} ({})",
+        device.path()?.display(),
+        info.model(),
+        info.driver()
+    );
+    println!("- bus info: {}", info.bus_info());
+
+    let topology = device.topology()?;
+    println!("- topology version: {}", topology.version());
+    for entity in topology.entities() {
+        println!(
+            "- entity {}: {} ({:?})",
+            entity.id(),
+            entity.name(),
+            entity.function()
+        );
+        if let Some(interface) = topology.entity_interface(entity.id()) {
+            let path = interface
+                .devnode()
+                .path()
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|e| format!("<{e}>"));
+            println!("  - interface: {:?} {}", interface.interface_type(), path);
+        }
+        for pad in topology.entity_pads(entity.id()) {
+            println!("  - pad {}: {:?}", pad.index(), pad.flags());
+        }
+        for link in topology.entity_links(entity.id()) {
+            let (Some(source), Some(sink)) =
+                (topology.pad(link.source_id()), topology.pad(link.sink_id()))
+            else {
+                continue;
+            };
+            if source.entity_id() != entity.id() {
+                // Only print each data link once, at its source.
+                continue;
+            }
+            let sink_entity = topology.entity(sink.entity_id()).map_or("?", |e| e.name());
+            println!(
+                "  - link: pad {} -> \"{}\":{} {:?}",
+                source.index(),
+                sink_entity,
+                sink.index(),
+                link.flags(),
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -95,7 +95,7 @@ fn main() -> anyhow::Result<()> {
     loop {
         match &mut output {
             Output::Write(device) => {
-                device.write(&image)?;
+                device.write_all(&image)?;
             }
             Output::Stream(stream) => {
                 stream.enqueue(|mut buf| {
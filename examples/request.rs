@@ -0,0 +1,103 @@
+//! Outputs frames to a video output device (like `vivid`) using media requests, changing the
+//! brightness control with every frame. With `--capture`, frames are captured from a video capture
+//! device instead.
+//!
+//! Uses [`linuxvideo::media::Request`] and [`linuxvideo::stream::WriteStream::enqueue_in_request`]
+//! (or [`linuxvideo::stream::ReadStream::enqueue_in_request`]).
+
+use std::{env, os::unix::prelude::*};
+
+use anyhow::{anyhow, bail};
+use linuxvideo::{
+    controls::{Cid, ExtControls},
+    format::{PixFormat, PixelFormat},
+    media::MediaDevice,
+    CapabilityFlags, Device,
+};
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+const FRAMES: u32 = 30;
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut args = env::args_os().skip(1).peekable();
+
+    let capture = args.next_if(|arg| arg == "--capture").is_some();
+    let usage = || anyhow!("usage: request [--capture] <device> <media-device>");
+    let path = args.next().ok_or_else(usage)?;
+    let media_path = args.next().ok_or_else(usage)?;
+
+    let device = Device::open(path)?;
+    let caps = device.capabilities()?.device_capabilities();
+    let required = if capture {
+        CapabilityFlags::VIDEO_CAPTURE
+    } else {
+        CapabilityFlags::VIDEO_OUTPUT
+    };
+    if !caps.contains(required) {
+        bail!("selected device does not support the `{required:?}` capability");
+    }
+    let brightness = device
+        .controls()
+        .filter_map(Result::ok)
+        .find(|ctrl| ctrl.id() == Cid::BRIGHTNESS);
+    if brightness.is_none() {
+        println!("device has no brightness control, only buffers will be queued in requests");
+    }
+
+    let media = MediaDevice::open(media_path)?;
+    let request = media.alloc_request()?;
+    let format = PixFormat::new(WIDTH, HEIGHT, PixelFormat::RGB32);
+    let mut controls = ExtControls::new();
+    let mut set_brightness = |i: u32, device: RawFd| -> anyhow::Result<()> {
+        if let Some(ctrl) = &brightness {
+            let range = ctrl.maximum() - ctrl.minimum();
+            let value = ctrl.minimum() + range * i as i32 / FRAMES as i32;
+            controls.clear();
+            controls.set(Cid::BRIGHTNESS, value);
+            request.write_controls(&device, &mut controls)?;
+        }
+        Ok(())
+    };
+
+    if capture {
+        let capture = device.video_capture(format)?;
+        println!("set format: {:?}", capture.format());
+
+        let mut stream = capture.into_stream()?;
+        // Buffers can only be queued in requests if none were queued without one.
+        stream.pause()?;
+        for i in 0..FRAMES {
+            set_brightness(i, stream.as_raw_fd())?;
+            stream.enqueue_in_request(&request)?;
+            request.queue()?;
+            stream.resume()?;
+            request.wait()?;
+            request.reinit()?;
+            stream.dequeue(|buf| {
+                println!("frame {i} done, first byte: {:#04x}", buf[0]);
+                Ok(())
+            })?;
+        }
+    } else {
+        let output = device.video_output(format)?;
+        println!("set format: {:?}", output.format());
+
+        let mut stream = output.into_stream()?;
+        for i in 0..FRAMES {
+            set_brightness(i, stream.as_raw_fd())?;
+            stream.enqueue_in_request(&request, |mut buf| {
+                buf.fill(0x80);
+                Ok(())
+            })?;
+            request.queue()?;
+            request.wait()?;
+            request.reinit()?;
+            println!("frame {i} done");
+        }
+    }
+
+    Ok(())
+}
@@ -64,7 +64,12 @@ fn main() -> anyhow::Result<()> {
 
     let mut stream = capture.into_stream()?;
     println!("stream started, waiting for data");
-    for i in 0..count {
+    for (i, frame) in stream.frames().limit(count.into()).enumerate() {
+        let frame = frame?;
+        if frame.is_error() {
+            eprintln!("WARNING: error flag is set on buffer");
+        }
+
         let mut path = PathBuf::from(&file_path);
         let stem = path.file_stem().unwrap_or(OsStr::new("image"));
         let ext = path.extension().unwrap_or(OsStr::new("jpg"));
@@ -78,20 +83,8 @@ fn main() -> anyhow::Result<()> {
             .collect::<OsString>();
         path.set_file_name(filename);
 
-        let mut file = File::create(&path)?;
-        stream.dequeue(|buf| {
-            if buf.is_error() {
-                eprintln!("WARNING: error flag is set on buffer");
-            }
-            file.write_all(&*buf)?;
-            println!(
-                "wrote {} bytes to {} (raw buffer size: {} bytes)",
-                buf.len(),
-                path.display(),
-                buf.raw_buffer().len(),
-            );
-            Ok(())
-        })?;
+        File::create(&path)?.write_all(&frame)?;
+        println!("wrote {} bytes to {}", frame.len(), path.display());
     }
 
     Ok(())
@@ -0,0 +1,67 @@
+//! Enables or disables a link between two pads of a media device, like `media-ctl --links`.
+//!
+//! Pads are specified as `"<entity name>":<pad index>`, for example with `vimc`:
+//!
+//! ```text
+//! setup-link /dev/media0 '"Sensor A":0' '"Debayer A":0' 1
+//! ```
+//!
+//! Uses [`linuxvideo::media::MediaDevice::setup_link`].
+
+use std::env;
+
+use anyhow::{anyhow, Context};
+use linuxvideo::media::{MediaDevice, Pad, Topology};
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut args = env::args().skip(1);
+
+    let (Some(path), Some(source), Some(sink), Some(enabled)) =
+        (args.next(), args.next(), args.next(), args.next())
+    else {
+        return Err(anyhow!(
+            "usage: setup-link <media-device> <source-pad> <sink-pad> <0|1>"
+        ));
+    };
+    let enabled = match &*enabled {
+        "0" => false,
+        "1" => true,
+        _ => return Err(anyhow!("invalid link state '{enabled}', expected 0 or 1")),
+    };
+
+    let media = MediaDevice::open(path)?;
+    let topology = media.topology()?;
+    let source = find_pad(&topology, &source)?;
+    let sink = find_pad(&topology, &sink)?;
+    let link = topology
+        .find_link(source.id(), sink.id())
+        .ok_or_else(|| anyhow!("there is no link between the given pads"))?;
+
+    media.setup_link(link, enabled)?;
+
+    let link = media
+        .topology()?
+        .link(link.id())
+        .cloned()
+        .context("link disappeared")?;
+    println!("link {}: {:?}", link.id(), link.flags());
+
+    Ok(())
+}
+
+/// Parses a pad description like `"Sensor A":0`.
+fn find_pad<'a>(topology: &'a Topology, desc: &str) -> anyhow::Result<&'a Pad> {
+    let (name, index) = desc
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("invalid pad '{desc}', expected \"<entity>\":<index>"))?;
+    let name = name.trim_matches('"');
+    let index = index.parse().context("invalid pad index")?;
+    let entity = topology
+        .entity_by_name(name)
+        .ok_or_else(|| anyhow!("no entity named '{name}'"))?;
+    topology
+        .entity_pad(entity.id(), index)
+        .ok_or_else(|| anyhow!("entity '{name}' has no pad {index}"))
+}
@@ -0,0 +1,575 @@
+//! Stateful video codecs.
+//!
+//! Stateful codecs are memory-to-memory devices that parse or produce a compressed bitstream on
+//! their own, keeping track of the codec state (like reference frames) internally. They are
+//! driven via an [`M2mStream`], with codec-specific commands layered on top.
+//!
+//! The `vicodec` driver provides software codecs for the FWHT format that can be used for testing.
+//!
+//! Stateless codecs, which have to be passed the parsed bitstream headers of every frame, are
+//! supported by the [`stateless`] module.
+
+pub mod stateless;
+
+use std::fs::File;
+use std::os::unix::prelude::*;
+use std::time::Duration;
+use std::{io, mem, slice};
+
+use crate::buf_type::BufType;
+use crate::event::{self, Event, EventSubscriptionFlags, EventType, SourceChanges};
+use crate::format::Format;
+use crate::raw::controls::{Cid, Control};
+use crate::shared::{DecCmd, DecCmdFlags, EncCmd, EncCmdFlags};
+use crate::stream::{
+    poll_until, M2mStream, ReadBufferView, ReadStream, StreamConfig, WriteBufferView, WriteStream,
+};
+use crate::{raw, set_output_frame_interval, Fract, M2mDevice};
+
+/// A stateful encoder with negotiated formats.
+///
+/// Created by [`Device::video_encoder`][crate::Device::video_encoder] or
+/// [`Device::video_encoder_mplane`][crate::Device::video_encoder_mplane].
+pub struct EncoderDevice {
+    m2m: M2mDevice,
+}
+
+impl EncoderDevice {
+    pub(crate) fn new(m2m: M2mDevice) -> Self {
+        Self { m2m }
+    }
+
+    /// Returns the format of the compressed bitstream produced by the encoder.
+    pub fn coded_format(&self) -> &Format {
+        self.m2m.capture_format()
+    }
+
+    /// Returns the format of the raw frames passed to the encoder.
+    pub fn raw_format(&self) -> &Format {
+        self.m2m.output_format()
+    }
+
+    /// Sets the frame interval of the raw frames, which the encoder uses for rate control.
+    ///
+    /// Returns the actual frame interval chosen by the driver.
+    pub fn set_frame_interval(&self, interval: Fract) -> io::Result<Fract> {
+        let (output_type, _) = self.m2m.buf_types();
+        set_output_frame_interval(&self.m2m.file, output_type, interval)
+    }
+
+    /// Allocates the buffers of both queues and starts encoding.
+    ///
+    /// This uses the default [`StreamConfig`] for both queues. Use [`Self::into_encoder_with`] to
+    /// configure the buffers.
+    pub fn into_encoder(self) -> io::Result<Encoder> {
+        let config = StreamConfig::new();
+        self.into_encoder_with(&config, &config)
+    }
+
+    /// Allocates the buffers of the raw queue as configured by `raw` and those of the coded queue
+    /// as configured by `coded`, and starts encoding.
+    pub fn into_encoder_with(
+        self,
+        raw: &StreamConfig,
+        coded: &StreamConfig,
+    ) -> io::Result<Encoder> {
+        Ok(Encoder {
+            stream: self.m2m.into_stream_with(raw, coded)?,
+        })
+    }
+}
+
+impl AsRawFd for EncoderDevice {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.m2m.as_raw_fd()
+    }
+}
+
+impl AsFd for EncoderDevice {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.m2m.as_fd()
+    }
+}
+
+/// A running stateful encoder.
+///
+/// Raw frames are passed to the encoder with [`Encoder::encode`], and the encoded frames are
+/// passed back to a callback as they become available. Encoders typically need several raw frames
+/// before they produce the first encoded frame, and may reorder frames, so there is no 1:1
+/// correspondence between the two. The encoder copies the timestamp of each raw frame (see
+/// [`WriteBufferView::set_timestamp`]) to the encoded frame it produces from it.
+///
+/// The type of each encoded frame is available via [`ReadBufferView::frame_type`].
+///
+/// To finish encoding, call [`Encoder::drain`], which returns the remaining encoded frames.
+pub struct Encoder {
+    stream: M2mStream,
+}
+
+impl Encoder {
+    /// Returns the underlying [`M2mStream`].
+    #[inline]
+    pub fn stream(&self) -> &M2mStream {
+        &self.stream
+    }
+
+    /// Returns the underlying [`M2mStream`].
+    #[inline]
+    pub fn stream_mut(&mut self) -> &mut M2mStream {
+        &mut self.stream
+    }
+
+    /// Returns the underlying [`M2mStream`].
+    pub fn into_stream(self) -> M2mStream {
+        self.stream
+    }
+
+    /// Passes a raw frame to the encoder.
+    ///
+    /// `fill` is called with a free buffer of the raw queue to fill with the frame. If no buffer is
+    /// free, encoded frames are passed to `on_frame` until the encoder releases one.
+    ///
+    /// See [`M2mStream::feed`] for details.
+    pub fn encode<T>(
+        &mut self,
+        fill: impl FnOnce(WriteBufferView<'_>) -> io::Result<T>,
+        on_frame: impl FnMut(ReadBufferView<'_>) -> io::Result<()>,
+    ) -> io::Result<T> {
+        self.stream.feed(fill, on_frame)
+    }
+
+    /// Waits for an encoded frame and passes it to `cb`.
+    ///
+    /// Returns `None` without calling `cb` once the encoder has been stopped and has returned its
+    /// last frame.
+    pub fn dequeue<T>(
+        &mut self,
+        cb: impl FnOnce(ReadBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<Option<T>> {
+        match self.stream.capture_mut().dequeue(cb) {
+            Ok(res) => Ok(Some(res)),
+            Err(e) if e.raw_os_error() == Some(libc::EPIPE) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Requests that the next raw frame passed to the encoder is encoded as a keyframe.
+    ///
+    /// This uses the `MPEG_VIDEO_FORCE_KEY_FRAME` control, which not all encoders support.
+    pub fn request_keyframe(&self) -> io::Result<()> {
+        let mut control = Control {
+            id: Cid::MPEG_VIDEO_FORCE_KEY_FRAME,
+            value: 1,
+        };
+        unsafe {
+            raw::VIDIOC_S_CTRL.ioctl(&self.stream, &mut control)?;
+        }
+        Ok(())
+    }
+
+    /// Stops the encoder and passes all remaining encoded frames to `on_frame`.
+    ///
+    /// This encodes all raw frames that have been passed to the encoder so far. The last frame
+    /// passed to `on_frame` is flagged with [`ReadBufferView::is_last`] (and may be empty).
+    /// Afterwards, the encoder can be restarted with [`Encoder::start`].
+    pub fn drain(
+        &mut self,
+        mut on_frame: impl FnMut(ReadBufferView<'_>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        self.stop()?;
+        loop {
+            let last = self.dequeue(|view| {
+                let last = view.is_last();
+                on_frame(view)?;
+                Ok(last)
+            })?;
+            match last {
+                Some(false) => {}
+                Some(true) | None => return Ok(()),
+            }
+        }
+    }
+
+    /// Sends the `STOP` command to the encoder, which initiates draining it.
+    ///
+    /// The encoder will encode all raw frames passed to it so far, and flag the last encoded frame
+    /// with [`ReadBufferView::is_last`]. Use [`Encoder::drain`] to also wait for these frames.
+    pub fn stop(&mut self) -> io::Result<()> {
+        self.command(EncCmd::STOP, EncCmdFlags::empty())
+    }
+
+    /// Sends the `START` command to the encoder, which restarts it after it has been stopped.
+    pub fn start(&mut self) -> io::Result<()> {
+        self.command(EncCmd::START, EncCmdFlags::empty())
+    }
+
+    fn command(&mut self, cmd: EncCmd, flags: EncCmdFlags) -> io::Result<()> {
+        let mut raw = raw::EncoderCmd {
+            cmd,
+            flags,
+            raw: [0; 8],
+        };
+        unsafe {
+            raw::VIDIOC_ENCODER_CMD.ioctl(&self.stream, &mut raw)?;
+        }
+        Ok(())
+    }
+}
+
+impl AsRawFd for Encoder {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
+}
+
+impl AsFd for Encoder {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.stream.as_fd()
+    }
+}
+
+/// A stateful decoder with a configured coded format.
+///
+/// Created by [`Device::video_decoder`][crate::Device::video_decoder] or
+/// [`Device::video_decoder_mplane`][crate::Device::video_decoder_mplane].
+pub struct DecoderDevice {
+    file: File,
+    coded_format: Format,
+}
+
+impl DecoderDevice {
+    pub(crate) fn new(file: File, coded_format: Format) -> Self {
+        Self { file, coded_format }
+    }
+
+    /// Returns the format of the compressed bitstream passed to the decoder.
+    pub fn coded_format(&self) -> &Format {
+        &self.coded_format
+    }
+
+    /// Allocates the buffers for the bitstream and starts decoding.
+    ///
+    /// This uses the default [`StreamConfig`] for both queues. Use [`Self::into_decoder_with`] to
+    /// configure the buffers.
+    pub fn into_decoder(self) -> io::Result<Decoder> {
+        let config = StreamConfig::new();
+        self.into_decoder_with(&config, &config)
+    }
+
+    /// Allocates the buffers for the bitstream as configured by `coded`, and starts decoding.
+    ///
+    /// The buffers for the decoded frames are configured by `decoded` once the decoder has
+    /// determined their format. The driver's minimum buffer count is always respected.
+    pub fn into_decoder_with(
+        self,
+        coded: &StreamConfig,
+        decoded: &StreamConfig,
+    ) -> io::Result<Decoder> {
+        let (output_type, capture_type) = match self.coded_format {
+            Format::VideoOutputMplane(_) => {
+                (BufType::VIDEO_OUTPUT_MPLANE, BufType::VIDEO_CAPTURE_MPLANE)
+            }
+            _ => (BufType::VIDEO_OUTPUT, BufType::VIDEO_CAPTURE),
+        };
+
+        event::subscribe(
+            self.file.as_raw_fd(),
+            EventType::SOURCE_CHANGE,
+            0,
+            EventSubscriptionFlags::empty(),
+        )?;
+        let output = WriteStream::new(self.file.try_clone()?, output_type, coded)?;
+
+        Ok(Decoder {
+            file: self.file,
+            output,
+            capture: None,
+            capture_type,
+            capture_config: decoded.clone(),
+            capture_format: None,
+            source_changed: false,
+            capture_stopped: false,
+        })
+    }
+}
+
+impl AsRawFd for DecoderDevice {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for DecoderDevice {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+/// A running stateful decoder.
+///
+/// Chunks of the compressed bitstream are passed to the decoder with [`Decoder::decode`], and the
+/// decoded frames are passed back to a callback as they become available. The decoder copies the
+/// timestamp of each chunk (see [`WriteBufferView::set_timestamp`]) to the frames decoded from
+/// it.
+///
+/// The format of the decoded frames is determined by the decoder from the bitstream. Once the
+/// decoder has found it, it signals a source change, and the buffers for the decoded frames are
+/// allocated. The same happens when the resolution changes in the middle of the stream: after
+/// the decoder has returned all frames in the old resolution, the buffers are reallocated for the
+/// new one. [`Decoder::capture_format`] always returns the format of the frames that are being
+/// decoded.
+///
+/// To finish decoding, call [`Decoder::drain`], which returns the remaining decoded frames.
+pub struct Decoder {
+    file: File,
+    output: WriteStream,
+    capture: Option<ReadStream>,
+    capture_type: BufType,
+    capture_config: StreamConfig,
+    capture_format: Option<Format>,
+    /// Set when the decoder signaled a resolution change, until the CAPTURE queue has been
+    /// reconfigured.
+    source_changed: bool,
+    /// Set when the last buffer of the CAPTURE queue has been dequeued.
+    capture_stopped: bool,
+}
+
+impl Decoder {
+    /// Returns the format of the decoded frames.
+    ///
+    /// Returns `None` until the decoder has parsed enough of the bitstream to determine it.
+    pub fn capture_format(&self) -> Option<&Format> {
+        self.capture_format.as_ref()
+    }
+
+    /// Passes a chunk of the compressed bitstream to the decoder.
+    ///
+    /// `fill` is called with a free buffer to fill with the bitstream data. If no buffer is free,
+    /// this waits until the decoder releases one, passing decoded frames to `on_frame` and
+    /// handling source changes in the meantime.
+    ///
+    /// Frames without any data (like the one the decoder returns before a resolution change) are
+    /// not passed to `on_frame`.
+    pub fn decode<T>(
+        &mut self,
+        fill: impl FnOnce(WriteBufferView<'_>) -> io::Result<T>,
+        mut on_frame: impl FnMut(ReadBufferView<'_>) -> io::Result<()>,
+    ) -> io::Result<T> {
+        let buf_index = loop {
+            self.handle_events()?;
+            if let Some(index) = self.output.try_unqueued_buffer()? {
+                break index;
+            }
+
+            self.wait(libc::POLLOUT, &mut on_frame)?;
+        };
+
+        self.output.fill_and_enqueue(buf_index, fill)
+    }
+
+    /// Stops the decoder and passes all remaining decoded frames to `on_frame`.
+    ///
+    /// This decodes all bitstream data that has been passed to the decoder so far. Afterwards,
+    /// the decoder can be restarted with [`Decoder::start`].
+    pub fn drain(
+        &mut self,
+        mut on_frame: impl FnMut(ReadBufferView<'_>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        self.stop()?;
+        loop {
+            self.handle_events()?;
+            if self.capture.is_none() {
+                self.output.try_reclaim()?;
+            }
+            let Some(events) = drain_events(
+                self.capture.is_some(),
+                self.capture_stopped,
+                self.output.any_queued(),
+            ) else {
+                return Ok(());
+            };
+
+            self.wait(events, &mut on_frame)?;
+        }
+    }
+
+    /// Sends the `STOP` command to the decoder, which initiates draining it.
+    ///
+    /// Use [`Decoder::drain`] to also wait for the remaining frames.
+    pub fn stop(&mut self) -> io::Result<()> {
+        self.command(DecCmd::STOP)
+    }
+
+    /// Sends the `START` command to the decoder, which restarts it after it has been stopped.
+    pub fn start(&mut self) -> io::Result<()> {
+        self.command(DecCmd::START)?;
+        self.capture_stopped = false;
+        Ok(())
+    }
+
+    fn command(&mut self, cmd: DecCmd) -> io::Result<()> {
+        let mut raw = raw::DecoderCmd {
+            cmd,
+            flags: DecCmdFlags::empty(),
+            raw: [0; 16],
+        };
+        unsafe {
+            raw::VIDIOC_DECODER_CMD.ioctl(&self.file, &mut raw)?;
+        }
+        Ok(())
+    }
+
+    /// Waits until the OUTPUT queue signals `events`, an event is pending, or a decoded frame is
+    /// available. Decoded frames are passed to `on_frame`.
+    fn wait(
+        &mut self,
+        events: i16,
+        on_frame: &mut impl FnMut(ReadBufferView<'_>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let capture_active = self.capture.is_some() && !self.capture_stopped;
+        let mut pollfd = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: events | libc::POLLPRI | if capture_active { libc::POLLIN } else { 0 },
+            revents: 0,
+        };
+        poll_until(slice::from_mut(&mut pollfd), None)?;
+        if pollfd.revents == libc::POLLERR {
+            return Err(io::Error::other("decoder signaled an error via `POLLERR`"));
+        }
+
+        if pollfd.revents & libc::POLLIN != 0 {
+            self.dequeue_frame(on_frame)?;
+        }
+        Ok(())
+    }
+
+    fn dequeue_frame(
+        &mut self,
+        on_frame: &mut impl FnMut(ReadBufferView<'_>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let capture = self.capture.as_mut().expect("CAPTURE queue not set up");
+        let res = capture.dequeue(|view| {
+            let last = view.is_last();
+            if !view.is_empty() {
+                on_frame(view)?;
+            }
+            Ok(last)
+        });
+        match res {
+            Ok(last) => self.capture_stopped |= last,
+            Err(e) if e.raw_os_error() == Some(libc::EPIPE) => self.capture_stopped = true,
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    /// Processes all pending events, and reconfigures the CAPTURE queue if the decoder has
+    /// signaled a source change.
+    fn handle_events(&mut self) -> io::Result<()> {
+        loop {
+            match event::dequeue_timeout(
+                self.file.as_raw_fd(),
+                Duration::ZERO,
+                io::ErrorKind::WouldBlock,
+            ) {
+                Ok(ev) => {
+                    if let Event::SourceChange { changes, .. } = ev.event() {
+                        self.source_changed |= changes.contains(SourceChanges::RESOLUTION);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        // After a mid-stream resolution change, the decoder first returns all frames in the old
+        // resolution, ending with one flagged with `LAST`.
+        if self.source_changed && (self.capture.is_none() || self.capture_stopped) {
+            self.reconfigure_capture()?;
+        }
+        Ok(())
+    }
+
+    fn reconfigure_capture(&mut self) -> io::Result<()> {
+        // Dropping the stream turns it off and unmaps the buffers, so that they can be freed when
+        // the new ones are allocated.
+        self.capture = None;
+
+        let format = unsafe {
+            let mut format = raw::Format {
+                type_: self.capture_type,
+                ..mem::zeroed()
+            };
+            raw::VIDIOC_G_FMT.ioctl(&self.file, &mut format)?;
+            Format::from_raw(format).unwrap()
+        };
+        log::debug!("decoder source change, new format: {:?}", format);
+
+        let stream = ReadStream::new(
+            self.file.try_clone()?,
+            self.capture_type,
+            &self.capture_config,
+        )?;
+        self.capture = Some(stream);
+        self.capture_format = Some(format);
+        self.source_changed = false;
+        self.capture_stopped = false;
+        Ok(())
+    }
+}
+
+impl AsRawFd for Decoder {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for Decoder {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+/// Returns the events [`Decoder::drain`] has to wait for, or `None` once draining is complete.
+///
+/// `POLLPRI` and (while the CAPTURE queue is active) `POLLIN` are always waited for.
+fn drain_events(has_capture: bool, capture_stopped: bool, output_queued: bool) -> Option<i16> {
+    match (has_capture, capture_stopped) {
+        // The decoder hasn't found the stream format yet, so it can only consume the remaining
+        // bitstream data.
+        (false, _) => output_queued.then_some(libc::POLLOUT),
+        // Only wait for decoded frames. Consumed OUTPUT buffers are not dequeued here, so waiting
+        // for `POLLOUT` would return immediately.
+        (true, false) => Some(0),
+        (true, true) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drain_waits_for_frames() {
+        assert_eq!(drain_events(false, false, true), Some(libc::POLLOUT));
+        assert_eq!(drain_events(false, false, false), None);
+        // Once the CAPTURE queue exists, finished OUTPUT buffers must not wake up `drain`.
+        assert_eq!(drain_events(true, false, true), Some(0));
+        assert_eq!(drain_events(true, false, false), Some(0));
+        assert_eq!(drain_events(true, true, true), None);
+    }
+
+    #[test]
+    fn codec_cmd_sizes() {
+        assert_eq!(mem::size_of::<raw::EncoderCmd>(), 40);
+        assert_eq!(mem::size_of::<raw::DecoderCmd>(), 72);
+    }
+}
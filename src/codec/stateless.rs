@@ -0,0 +1,614 @@
+//! Stateless video decoders.
+//!
+//! Unlike stateful decoders, stateless decoders don't parse the bitstream themselves. Instead, the
+//! application parses the headers of every frame and passes the parameters to the decoder via
+//! compound controls (like [`H264Sps`] or [`FwhtParams`]), along with the compressed data. Both are
+//! bundled in a media [`Request`] for every frame, so that the decoder applies the right parameters
+//! to each frame.
+//!
+//! Reference frames are identified by the timestamp of the capture buffer they were decoded into,
+//! which the decoder copies from the timestamp of the corresponding bitstream buffer. Use
+//! [`reference_timestamp`] to convert a timestamp to the representation used by the controls.
+//!
+//! The `visl` driver and the stateless mode of `vicodec` can be used for testing.
+
+use std::os::unix::prelude::*;
+use std::time::Duration;
+use std::{io, mem};
+
+use crate::controls::{sealed, Cid, CompoundControl, ExtControls};
+use crate::format::Format;
+use crate::media::{MediaDevice, Request};
+use crate::stream::{BufCap, M2mStream, ReadBufferView, StreamConfig, WriteBufferView};
+use crate::M2mDevice;
+
+pub use crate::shared::{
+    FwhtFlags, H264DecodeMode, H264DecodeParamFlags, H264DpbEntryFlags, H264PpsFlags,
+    H264SliceFlags, H264SpsConstraintFlags, H264SpsFlags, H264StartCode, Mpeg2PictureFlags,
+    Mpeg2SequenceFlags, Vp8FrameFlags, Vp8LoopFilterFlags, Vp8SegmentFlags,
+};
+
+/// Converts a buffer timestamp to the representation used to refer to reference frames.
+///
+/// Buffer timestamps are passed to the driver with microsecond precision, so anything finer is
+/// discarded.
+pub fn reference_timestamp(timestamp: Duration) -> u64 {
+    timestamp.as_secs() * 1_000_000_000 + u64::from(timestamp.subsec_micros()) * 1000
+}
+
+/// Implements [`CompoundControl`] for control structs.
+macro_rules! compound_controls {
+    ($($ty:ident => $cid:ident,)*) => {
+        $(
+            impl sealed::Sealed for $ty {}
+
+            impl CompoundControl for $ty {
+                const CID: Cid = Cid::$cid;
+            }
+        )*
+    };
+}
+
+/// Implements [`Default`] by zeroing the struct.
+macro_rules! zeroed_default {
+    ($($ty:ident),*) => {
+        $(
+            impl Default for $ty {
+                fn default() -> Self {
+                    // Safety: all-zero is valid for all of these types.
+                    unsafe { mem::zeroed() }
+                }
+            }
+        )*
+    };
+}
+
+compound_controls! {
+    H264Sps => STATELESS_H264_SPS,
+    H264Pps => STATELESS_H264_PPS,
+    H264ScalingMatrix => STATELESS_H264_SCALING_MATRIX,
+    H264PredWeights => STATELESS_H264_PRED_WEIGHTS,
+    H264SliceParams => STATELESS_H264_SLICE_PARAMS,
+    H264DecodeParams => STATELESS_H264_DECODE_PARAMS,
+    FwhtParams => STATELESS_FWHT_PARAMS,
+    Vp8Frame => STATELESS_VP8_FRAME,
+    Mpeg2Sequence => STATELESS_MPEG2_SEQUENCE,
+    Mpeg2Picture => STATELESS_MPEG2_PICTURE,
+    Mpeg2Quantisation => STATELESS_MPEG2_QUANTISATION,
+}
+
+zeroed_default!(
+    H264Sps,
+    H264Pps,
+    H264ScalingMatrix,
+    H264WeightFactors,
+    H264PredWeights,
+    H264Reference,
+    H264SliceParams,
+    H264DpbEntry,
+    H264DecodeParams,
+    FwhtParams,
+    Vp8Segment,
+    Vp8LoopFilter,
+    Vp8Quantization,
+    Vp8Entropy,
+    Vp8EntropyCoderState,
+    Vp8Frame,
+    Mpeg2Sequence,
+    Mpeg2Picture,
+    Mpeg2Quantisation
+);
+
+// NB: all of these structs have to be free of implicit padding, since they are passed to the
+// driver as bytes (see `ExtControls::set_compound`). The kernel defines explicit padding fields
+// wherever necessary.
+
+/// H.264 sequence parameter set.
+///
+/// The fields match the syntax elements of the same name in the H.264 specification.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct H264Sps {
+    pub profile_idc: u8,
+    pub constraint_set_flags: H264SpsConstraintFlags,
+    pub level_idc: u8,
+    pub seq_parameter_set_id: u8,
+    pub chroma_format_idc: u8,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    pub log2_max_frame_num_minus4: u8,
+    pub pic_order_cnt_type: u8,
+    pub log2_max_pic_order_cnt_lsb_minus4: u8,
+    pub max_num_ref_frames: u8,
+    pub num_ref_frames_in_pic_order_cnt_cycle: u8,
+    pub offset_for_ref_frame: [i32; 255],
+    pub offset_for_non_ref_pic: i32,
+    pub offset_for_top_to_bottom_field: i32,
+    pub pic_width_in_mbs_minus1: u16,
+    pub pic_height_in_map_units_minus1: u16,
+    pub flags: H264SpsFlags,
+}
+
+/// H.264 picture parameter set.
+///
+/// The fields match the syntax elements of the same name in the H.264 specification.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct H264Pps {
+    pub pic_parameter_set_id: u8,
+    pub seq_parameter_set_id: u8,
+    pub num_slice_groups_minus1: u8,
+    pub num_ref_idx_l0_default_active_minus1: u8,
+    pub num_ref_idx_l1_default_active_minus1: u8,
+    pub weighted_bipred_idc: u8,
+    pub pic_init_qp_minus26: i8,
+    pub pic_init_qs_minus26: i8,
+    pub chroma_qp_index_offset: i8,
+    pub second_chroma_qp_index_offset: i8,
+    pub flags: H264PpsFlags,
+}
+
+/// H.264 scaling matrices, after applying the inverse scanning process.
+///
+/// Only needed if [`H264PpsFlags::SCALING_MATRIX_PRESENT`] is set.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct H264ScalingMatrix {
+    /// In the order Intra Y, Intra Cb, Intra Cr, Inter Y, Inter Cb, Inter Cr.
+    pub scaling_list_4x4: [[u8; 16]; 6],
+    /// In the order Intra Y, Inter Y, Intra Cb, Inter Cb, Intra Cr, Inter Cr.
+    pub scaling_list_8x8: [[u8; 64]; 6],
+}
+
+/// Luma and chroma weight factors of an [`H264PredWeights`] table.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct H264WeightFactors {
+    pub luma_weight: [i16; 32],
+    pub luma_offset: [i16; 32],
+    pub chroma_weight: [[i16; 2]; 32],
+    pub chroma_offset: [[i16; 2]; 32],
+}
+
+/// H.264 prediction weight table.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct H264PredWeights {
+    pub luma_log2_weight_denom: u16,
+    pub chroma_log2_weight_denom: u16,
+    /// Weight factors for reference list 0 and 1.
+    pub weight_factors: [H264WeightFactors; 2],
+}
+
+/// Reference to an entry of [`H264DecodeParams::dpb`].
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct H264Reference {
+    /// Which fields are referenced (1 for the top field, 2 for the bottom field, 3 for both).
+    pub fields: u8,
+    /// Index into [`H264DecodeParams::dpb`].
+    pub index: u8,
+}
+
+/// H.264 slice parameters, used by [`H264DecodeMode::SLICE_BASED`] decoders.
+///
+/// Except where noted, the fields match the slice header syntax elements of the same name.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct H264SliceParams {
+    /// Offset in bits from the start of the slice to `slice_data()`.
+    pub header_bit_size: u32,
+    pub first_mb_in_slice: u32,
+    pub slice_type: u8,
+    pub colour_plane_id: u8,
+    pub redundant_pic_cnt: u8,
+    pub cabac_init_idc: u8,
+    pub slice_qp_delta: i8,
+    pub slice_qs_delta: i8,
+    pub disable_deblocking_filter_idc: u8,
+    pub slice_alpha_c0_offset_div2: i8,
+    pub slice_beta_offset_div2: i8,
+    pub num_ref_idx_l0_active_minus1: u8,
+    pub num_ref_idx_l1_active_minus1: u8,
+    reserved: u8,
+    /// Reference picture list 0, after applying the per-slice modifications.
+    pub ref_pic_list0: [H264Reference; 32],
+    /// Reference picture list 1, after applying the per-slice modifications.
+    pub ref_pic_list1: [H264Reference; 32],
+    pub flags: H264SliceFlags,
+}
+
+/// An entry of the H.264 decoded picture buffer.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct H264DpbEntry {
+    /// Timestamp of the capture buffer holding the reference frame (see
+    /// [`reference_timestamp`]).
+    pub reference_ts: u64,
+    pub pic_num: u32,
+    pub frame_num: u16,
+    /// Which fields are referenced (1 for the top field, 2 for the bottom field, 3 for both).
+    pub fields: u8,
+    reserved: [u8; 5],
+    pub top_field_order_cnt: i32,
+    pub bottom_field_order_cnt: i32,
+    pub flags: H264DpbEntryFlags,
+}
+
+/// H.264 decoding parameters of a frame.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct H264DecodeParams {
+    /// The decoded picture buffer.
+    pub dpb: [H264DpbEntry; 16],
+    pub nal_ref_idc: u16,
+    pub frame_num: u16,
+    pub top_field_order_cnt: i32,
+    pub bottom_field_order_cnt: i32,
+    pub idr_pic_id: u16,
+    pub pic_order_cnt_lsb: u16,
+    pub delta_pic_order_cnt_bottom: i32,
+    pub delta_pic_order_cnt0: i32,
+    pub delta_pic_order_cnt1: i32,
+    /// Size in bits of the `dec_ref_pic_marking()` syntax element.
+    pub dec_ref_pic_marking_bit_size: u32,
+    /// Size in bits of the picture order count syntax elements.
+    pub pic_order_cnt_bit_size: u32,
+    pub slice_group_change_cycle: u32,
+    reserved: u32,
+    pub flags: H264DecodeParamFlags,
+}
+
+/// Parameters of an FWHT frame, as used by the stateless mode of `vicodec`.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct FwhtParams {
+    /// Timestamp of the capture buffer holding the reference frame (see
+    /// [`reference_timestamp`]).
+    pub backward_ref_ts: u64,
+    /// The FWHT version, [`FwhtParams::VERSION`].
+    pub version: u32,
+    pub width: u32,
+    pub height: u32,
+    pub flags: FwhtFlags,
+    pub colorspace: u32,
+    pub xfer_func: u32,
+    pub ycbcr_enc: u32,
+    pub quantization: u32,
+}
+
+impl FwhtParams {
+    /// The current version of the FWHT format.
+    pub const VERSION: u32 = 3;
+}
+
+/// VP8 segment-based adjustments.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Vp8Segment {
+    pub quant_update: [i8; 4],
+    pub lf_update: [i8; 4],
+    pub segment_probs: [u8; 3],
+    padding: u8,
+    pub flags: Vp8SegmentFlags,
+}
+
+/// VP8 loop filter parameters.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Vp8LoopFilter {
+    pub ref_frm_delta: [i8; 4],
+    pub mb_mode_delta: [i8; 4],
+    pub sharpness_level: u8,
+    pub level: u8,
+    padding: u16,
+    pub flags: Vp8LoopFilterFlags,
+}
+
+/// VP8 dequantization indices.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Vp8Quantization {
+    pub y_ac_qi: u8,
+    pub y_dc_delta: i8,
+    pub y2_dc_delta: i8,
+    pub y2_ac_delta: i8,
+    pub uv_dc_delta: i8,
+    pub uv_ac_delta: i8,
+    padding: u16,
+}
+
+/// VP8 probability updates.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Vp8Entropy {
+    pub coeff_probs: [[[[u8; 11]; 3]; 8]; 4],
+    pub y_mode_probs: [u8; 4],
+    pub uv_mode_probs: [u8; 3],
+    pub mv_probs: [[u8; 19]; 2],
+    padding: [u8; 3],
+}
+
+/// State of the VP8 boolean decoder after parsing the frame header.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Vp8EntropyCoderState {
+    pub range: u8,
+    pub value: u8,
+    pub bit_count: u8,
+    padding: u8,
+}
+
+/// VP8 frame parameters.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Vp8Frame {
+    pub segment: Vp8Segment,
+    pub lf: Vp8LoopFilter,
+    pub quant: Vp8Quantization,
+    pub entropy: Vp8Entropy,
+    pub coder_state: Vp8EntropyCoderState,
+    pub width: u16,
+    pub height: u16,
+    pub horizontal_scale: u8,
+    pub vertical_scale: u8,
+    pub version: u8,
+    pub prob_skip_false: u8,
+    pub prob_intra: u8,
+    pub prob_last: u8,
+    pub prob_gf: u8,
+    pub num_dct_parts: u8,
+    /// Size of the first (control) partition.
+    pub first_part_size: u32,
+    /// Size in bits of the header of the first partition.
+    pub first_part_header_bits: u32,
+    pub dct_part_sizes: [u32; 8],
+    /// Timestamp of the capture buffer holding the "last" reference frame (see
+    /// [`reference_timestamp`]).
+    pub last_frame_ts: u64,
+    /// Timestamp of the capture buffer holding the "golden" reference frame.
+    pub golden_frame_ts: u64,
+    /// Timestamp of the capture buffer holding the "alt" reference frame.
+    pub alt_frame_ts: u64,
+    pub flags: Vp8FrameFlags,
+}
+
+/// MPEG-2 sequence header and sequence extension.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Mpeg2Sequence {
+    /// Combination of `horizontal_size_value` and `horizontal_size_extension`.
+    pub horizontal_size: u16,
+    /// Combination of `vertical_size_value` and `vertical_size_extension`.
+    pub vertical_size: u16,
+    /// Combination of `vbv_buffer_size_value` and `vbv_buffer_size_extension`.
+    pub vbv_buffer_size: u32,
+    pub profile_and_level_indication: u16,
+    pub chroma_format: u8,
+    pub flags: Mpeg2SequenceFlags,
+}
+
+/// MPEG-2 picture header and picture coding extension.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Mpeg2Picture {
+    /// Timestamp of the capture buffer used for backward prediction (see
+    /// [`reference_timestamp`]).
+    pub backward_ref_ts: u64,
+    /// Timestamp of the capture buffer used for forward prediction.
+    pub forward_ref_ts: u64,
+    pub flags: Mpeg2PictureFlags,
+    pub f_code: [[u8; 2]; 2],
+    /// 1 for I, 2 for P, 3 for B and 4 for D pictures.
+    pub picture_coding_type: u8,
+    /// 1 for the top field, 2 for the bottom field, 3 for a frame picture.
+    pub picture_structure: u8,
+    pub intra_dc_precision: u8,
+    reserved: [u8; 5],
+}
+
+/// MPEG-2 quantisation matrices, in zigzag scanning order.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Mpeg2Quantisation {
+    pub intra_quantiser_matrix: [u8; 64],
+    pub non_intra_quantiser_matrix: [u8; 64],
+    pub chroma_intra_quantiser_matrix: [u8; 64],
+    pub chroma_non_intra_quantiser_matrix: [u8; 64],
+}
+
+/// A stateless decoder with negotiated formats.
+///
+/// Created by [`Device::video_stateless_decoder`][crate::Device::video_stateless_decoder] or
+/// [`Device::video_stateless_decoder_mplane`][crate::Device::video_stateless_decoder_mplane].
+pub struct StatelessDecoderDevice {
+    m2m: M2mDevice,
+}
+
+impl StatelessDecoderDevice {
+    pub(crate) fn new(m2m: M2mDevice) -> Self {
+        Self { m2m }
+    }
+
+    /// Returns the format of the compressed bitstream passed to the decoder.
+    pub fn coded_format(&self) -> &Format {
+        self.m2m.output_format()
+    }
+
+    /// Returns the format of the decoded frames.
+    pub fn decoded_format(&self) -> &Format {
+        self.m2m.capture_format()
+    }
+
+    /// Allocates the buffers of both queues and starts decoding, using `media` to allocate
+    /// requests.
+    ///
+    /// `media` has to be the media controller device the decoder belongs to. This uses the default
+    /// [`StreamConfig`] for both queues. Use [`Self::into_decoder_with`] to configure the buffers.
+    pub fn into_decoder(self, media: &MediaDevice) -> io::Result<StatelessDecoder> {
+        let config = StreamConfig::new();
+        self.into_decoder_with(media, &config, &config)
+    }
+
+    /// Allocates the buffers of the coded queue as configured by `coded` and those of the decoded
+    /// queue as configured by `decoded`, and starts decoding.
+    ///
+    /// Frames that are used as references stay in their capture buffers while the decoder reads
+    /// them, so `decoded` needs to provide at least one buffer more than the number of reference
+    /// frames the codec uses.
+    ///
+    /// Returns an error of kind [`io::ErrorKind::Unsupported`] if the decoder does not support
+    /// requests. This is checked before any buffers are allocated.
+    pub fn into_decoder_with(
+        self,
+        media: &MediaDevice,
+        coded: &StreamConfig,
+        decoded: &StreamConfig,
+    ) -> io::Result<StatelessDecoder> {
+        if !self
+            .m2m
+            .output_capabilities()?
+            .contains(BufCap::SUPPORTS_REQUESTS)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "decoder does not support requests",
+            ));
+        }
+
+        let request = media.alloc_request()?;
+        let stream = self.m2m.into_stream_with(coded, decoded)?;
+        Ok(StatelessDecoder { stream, request })
+    }
+}
+
+impl AsRawFd for StatelessDecoderDevice {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.m2m.as_raw_fd()
+    }
+}
+
+impl AsFd for StatelessDecoderDevice {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.m2m.as_fd()
+    }
+}
+
+/// A running stateless decoder.
+///
+/// Every call to [`StatelessDecoder::decode`] submits one frame along with its parameter controls,
+/// waits for the decoder to process it, and passes the decoded frame to a callback. Since every
+/// request has to produce a decoded frame, H.264 decoders have to be used in
+/// [`H264DecodeMode::FRAME_BASED`] mode, or with a single slice per frame.
+pub struct StatelessDecoder {
+    stream: M2mStream,
+    request: Request,
+}
+
+impl StatelessDecoder {
+    /// Returns the underlying [`M2mStream`].
+    #[inline]
+    pub fn stream(&self) -> &M2mStream {
+        &self.stream
+    }
+
+    /// Returns the underlying [`M2mStream`].
+    #[inline]
+    pub fn stream_mut(&mut self) -> &mut M2mStream {
+        &mut self.stream
+    }
+
+    /// Decodes a frame.
+    ///
+    /// `fill` is called with a free buffer of the coded queue to fill with the compressed data.
+    /// The data and `controls` (which should contain the codec's parameter controls for this
+    /// frame) are then submitted to the decoder in a request. Once the decoder has processed the
+    /// request, the decoded frame is passed to `on_frame`.
+    ///
+    /// `timestamp` is attached to the frame, and is used to refer to it as a reference frame in
+    /// the controls of later frames (see [`reference_timestamp`]). Every frame should get a
+    /// distinct timestamp.
+    pub fn decode<T>(
+        &mut self,
+        timestamp: Duration,
+        controls: &mut ExtControls,
+        fill: impl FnOnce(WriteBufferView<'_>) -> io::Result<()>,
+        on_frame: impl FnOnce(ReadBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        if let Err(e) = self.submit(timestamp, controls, fill) {
+            // Return the request to its initial state, so that it can be used for the next frame.
+            self.request.reinit().ok();
+            return Err(e);
+        }
+        self.request.reinit()?;
+
+        self.stream.capture_mut().dequeue(on_frame)
+    }
+
+    fn submit(
+        &mut self,
+        timestamp: Duration,
+        controls: &mut ExtControls,
+        fill: impl FnOnce(WriteBufferView<'_>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        if !controls.is_empty() {
+            self.request.write_controls(&self.stream, controls)?;
+        }
+        self.stream
+            .output_mut()
+            .enqueue_in_request(&self.request, |mut buf| {
+                buf.set_timestamp(timestamp);
+                fill(buf)
+            })?;
+        self.request.queue()?;
+        self.request.wait()
+    }
+}
+
+impl AsRawFd for StatelessDecoder {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
+}
+
+impl AsFd for StatelessDecoder {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.stream.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_struct_sizes() {
+        // These have to match the kernel's definitions, since the driver checks the payload size.
+        assert_eq!(mem::size_of::<H264Sps>(), 1048);
+        assert_eq!(mem::size_of::<H264Pps>(), 12);
+        assert_eq!(mem::size_of::<H264ScalingMatrix>(), 480);
+        assert_eq!(mem::size_of::<H264PredWeights>(), 772);
+        assert_eq!(mem::size_of::<H264SliceParams>(), 152);
+        assert_eq!(mem::size_of::<H264DpbEntry>(), 32);
+        assert_eq!(mem::size_of::<H264DecodeParams>(), 560);
+        assert_eq!(mem::size_of::<FwhtParams>(), 40);
+        assert_eq!(mem::size_of::<Vp8Entropy>(), 1104);
+        assert_eq!(mem::size_of::<Vp8Frame>(), 1232);
+        assert_eq!(mem::size_of::<Mpeg2Sequence>(), 12);
+        assert_eq!(mem::size_of::<Mpeg2Picture>(), 32);
+        assert_eq!(mem::size_of::<Mpeg2Quantisation>(), 256);
+    }
+
+    #[test]
+    fn reference_timestamps() {
+        // Sub-microsecond precision is lost when the timestamp is passed to the driver.
+        assert_eq!(
+            reference_timestamp(Duration::new(2, 3_004_005)),
+            2_003_004_000
+        );
+    }
+}
@@ -1,6 +1,7 @@
 //! Device control enumeration and access.
 
-use std::{fmt, io, mem};
+use std::os::unix::prelude::*;
+use std::{fmt, io, mem, slice};
 
 use crate::shared::CONTROL_FLAGS_NEXT_CTRL;
 use crate::{byte_array_to_str, raw, Device};
@@ -64,7 +65,7 @@ impl Iterator for ControlIter<'_> {
                             continue; // continue, because there might be gaps
                         } else {
                             self.finished = true;
-                            return Some(Err(e.into()));
+                            return Some(Err(e));
                         }
                     }
                 }
@@ -185,7 +186,7 @@ impl Iterator for TextMenuIter<'_> {
                     match raw::VIDIOC_QUERYMENU.ioctl(self.device, &mut raw) {
                         Ok(_) => return Some(Ok(TextMenuItem { raw })),
                         Err(e) if e.raw_os_error() == Some(libc::EINVAL as _) => continue,
-                        Err(other) => return Some(Err(other.into())),
+                        Err(other) => return Some(Err(other)),
                     }
                 }
             }
@@ -210,3 +211,181 @@ impl TextMenuItem {
         byte_array_to_str(unsafe { &self.raw.name_or_value.name })
     }
 }
+
+/// A compound control whose value is a fixed-layout struct.
+///
+/// Values of these controls can be added to an [`ExtControls`] list via
+/// [`ExtControls::set_compound`]. This trait cannot be implemented outside of this crate.
+pub trait CompoundControl: Copy + sealed::Sealed {
+    /// The ID of the control.
+    const CID: Cid;
+}
+
+pub(crate) mod sealed {
+    pub trait Sealed {}
+}
+
+/// A list of control values that are applied together.
+///
+/// Unlike [`Device::write_control_raw`], which sets one control at a time, all values in a
+/// [`ExtControls`] list are applied atomically via `VIDIOC_S_EXT_CTRLS`: if any of them is invalid,
+/// none of the controls are changed. This also supports 64-bit and compound controls, which cannot
+/// be accessed via [`Device::write_control_raw`].
+///
+/// The list can be applied directly with [`Device::write_controls`], or stored in a
+/// [`Request`][crate::media::Request] via [`Request::write_controls`], so that the values are
+/// applied when the driver processes the request.
+///
+/// [`Request::write_controls`]: crate::media::Request::write_controls
+#[derive(Debug, Clone, Default)]
+pub struct ExtControls {
+    controls: Vec<(Cid, ExtValue)>,
+}
+
+#[derive(Debug, Clone)]
+enum ExtValue {
+    Value(i32),
+    Value64(i64),
+    Payload(Vec<u8>),
+}
+
+impl ExtControls {
+    /// Creates an empty list of controls.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a value for a control of type [`CtrlType::INTEGER`], [`CtrlType::BOOLEAN`],
+    /// [`CtrlType::MENU`], [`CtrlType::BUTTON`] or [`CtrlType::BITMASK`].
+    pub fn set(&mut self, cid: Cid, value: i32) -> &mut Self {
+        self.controls.push((cid, ExtValue::Value(value)));
+        self
+    }
+
+    /// Adds a value for a control of type [`CtrlType::INTEGER64`].
+    pub fn set_i64(&mut self, cid: Cid, value: i64) -> &mut Self {
+        self.controls.push((cid, ExtValue::Value64(value)));
+        self
+    }
+
+    /// Adds the payload of a string, array or compound control.
+    ///
+    /// The size of `payload` has to match the size the driver expects for the control. Strings
+    /// have to include the terminating NUL byte.
+    pub fn set_payload(&mut self, cid: Cid, payload: impl Into<Vec<u8>>) -> &mut Self {
+        self.controls.push((cid, ExtValue::Payload(payload.into())));
+        self
+    }
+
+    /// Adds the value of a compound control with a fixed layout, like the stateless codec controls
+    /// in [`codec::stateless`][crate::codec::stateless].
+    pub fn set_compound<T: CompoundControl>(&mut self, value: &T) -> &mut Self {
+        // Safety: `CompoundControl` is only implemented for `repr(C)` types without padding, so
+        // all of their bytes are initialized.
+        let bytes =
+            unsafe { slice::from_raw_parts((value as *const T).cast::<u8>(), mem::size_of::<T>()) };
+        self.set_payload(T::CID, bytes)
+    }
+
+    /// Returns the number of control values in the list.
+    pub fn len(&self) -> usize {
+        self.controls.len()
+    }
+
+    /// Returns whether the list contains no control values.
+    pub fn is_empty(&self) -> bool {
+        self.controls.is_empty()
+    }
+
+    /// Removes all control values from the list, so that it can be reused.
+    pub fn clear(&mut self) {
+        self.controls.clear();
+    }
+
+    /// Applies the controls via `VIDIOC_S_EXT_CTRLS`.
+    ///
+    /// `which` selects whether the current values or the values stored in the request
+    /// `request_fd` are changed.
+    pub(crate) fn write(&mut self, fd: RawFd, which: u32, request_fd: RawFd) -> io::Result<()> {
+        let mut controls = self
+            .controls
+            .iter_mut()
+            .map(|(cid, value)| {
+                let (size, value) = match value {
+                    ExtValue::Value(value) => (0, raw::controls::ExtControlValue { value: *value }),
+                    ExtValue::Value64(value64) => {
+                        (0, raw::controls::ExtControlValue { value64: *value64 })
+                    }
+                    // The driver writes back the value it applied, so the payload has to be
+                    // writable.
+                    ExtValue::Payload(payload) => (
+                        payload.len() as u32,
+                        raw::controls::ExtControlValue {
+                            ptr: payload.as_mut_ptr().cast(),
+                        },
+                    ),
+                };
+                raw::controls::ExtControl {
+                    id: *cid,
+                    size,
+                    reserved2: [0],
+                    value,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let count = controls.len() as u32;
+        let mut raw = raw::controls::ExtControls {
+            which,
+            count,
+            // Drivers set this to `count` for errors that don't concern a specific control, but
+            // it's left untouched if the ioctl isn't supported at all.
+            error_idx: count,
+            request_fd,
+            reserved: [0],
+            controls: controls.as_mut_ptr(),
+        };
+        match unsafe { raw::VIDIOC_S_EXT_CTRLS.ioctl(&fd, &mut raw) } {
+            Ok(_) => Ok(()),
+            // If the error can be attributed to a specific control, `error_idx` refers to it.
+            Err(e) => match self.controls.get(raw.error_idx as usize) {
+                Some((cid, _)) => Err(io::Error::new(
+                    e.kind(),
+                    format!("failed to set control {cid:?}: {e}"),
+                )),
+                None => Err(e),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn ext_control_struct_sizes() {
+        assert_eq!(mem::size_of::<raw::controls::ExtControl>(), 20);
+        assert_eq!(mem::size_of::<raw::controls::ExtControls>(), 32);
+    }
+
+    #[test]
+    fn ext_controls_write_error() {
+        let mut controls = ExtControls::new();
+        controls
+            .set(Cid::BRIGHTNESS, 1)
+            .set_payload(Cid::CONTRAST, [0; 4]);
+        assert_eq!(controls.len(), 2);
+
+        // `/dev/null` doesn't support the ioctl, and the error can't be attributed to a control.
+        let null = std::fs::File::open("/dev/null").unwrap();
+        let err = controls
+            .write(null.as_raw_fd(), raw::controls::CTRL_WHICH_CUR_VAL, -1)
+            .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOTTY));
+
+        controls.clear();
+        assert!(controls.is_empty());
+    }
+}
@@ -0,0 +1,363 @@
+//! V4L2 events.
+//!
+//! Drivers can notify applications of asynchronous changes via events. Events of a given
+//! [`EventType`] have to be subscribed to via `subscribe_event` before they are delivered, and can
+//! then be dequeued via `dequeue_event`. Both methods are available on [`Device`][crate::Device],
+//! [`ReadStream`][crate::stream::ReadStream] and [`WriteStream`][crate::stream::WriteStream].
+//!
+//! Pending events are signaled by `poll(2)` reporting `POLLPRI` on the device file descriptor.
+
+use std::ffi::c_int;
+use std::time::{Duration, Instant};
+use std::{fmt, io, mem};
+
+use crate::raw;
+use crate::shared::{Field, MotionDetFlags};
+use crate::stream::poll_until;
+
+pub use crate::raw::controls::Cid;
+pub use crate::shared::{
+    ControlFlags, CtrlChanges, CtrlType, EventSubscriptionFlags, EventType, SourceChanges,
+};
+
+/// An event reported by a device.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum Event {
+    /// Vertical sync of the incoming video signal.
+    Vsync {
+        /// The field that is about to be received.
+        field: Field,
+    },
+    /// The end of the stream was reached.
+    ///
+    /// This is typically sent by decoders once the last buffer has been decoded.
+    Eos,
+    /// A control changed its value, flags or range.
+    Ctrl(CtrlEvent),
+    /// A new frame started to be received.
+    FrameSync {
+        /// The sequence number of the frame being received.
+        frame_sequence: u32,
+    },
+    /// The parameters of the source changed.
+    ///
+    /// For [`SourceChanges::RESOLUTION`], the application has to stop streaming, query the new
+    /// format and reallocate the buffers.
+    SourceChange {
+        /// The index of the input (or pad) that changed, as passed to
+        /// [`subscribe_event`][crate::Device::subscribe_event].
+        index: u32,
+        /// What changed about the source.
+        changes: SourceChanges,
+    },
+    /// The motion detection state changed.
+    MotionDet {
+        /// The sequence number of the frame the state changed in, if known.
+        frame_sequence: Option<u32>,
+        /// Bitmask of the regions that have detected motion.
+        region_mask: u32,
+    },
+    /// An event of a type not covered by the other variants (for example, a driver-specific
+    /// event).
+    Other {
+        event_type: EventType,
+        id: u32,
+        data: [u8; 64],
+    },
+}
+
+/// The state of a control, reported by [`Event::Ctrl`].
+#[derive(Clone, Copy)]
+pub struct CtrlEvent {
+    id: Cid,
+    raw: raw::EventCtrl,
+}
+
+impl CtrlEvent {
+    /// Returns the ID of the control that changed.
+    #[inline]
+    pub fn id(&self) -> Cid {
+        self.id
+    }
+
+    /// Returns what changed about the control.
+    #[inline]
+    pub fn changes(&self) -> CtrlChanges {
+        self.raw.changes
+    }
+
+    #[inline]
+    pub fn control_type(&self) -> CtrlType {
+        self.raw.type_
+    }
+
+    /// Returns the current value of the control.
+    ///
+    /// For controls with a payload, this is always 0.
+    pub fn value(&self) -> i64 {
+        unsafe {
+            if self.raw.type_ == CtrlType::INTEGER64 {
+                self.raw.value.value64
+            } else {
+                self.raw.value.value.into()
+            }
+        }
+    }
+
+    #[inline]
+    pub fn flags(&self) -> ControlFlags {
+        self.raw.flags
+    }
+
+    #[inline]
+    pub fn minimum(&self) -> i32 {
+        self.raw.minimum
+    }
+
+    #[inline]
+    pub fn maximum(&self) -> i32 {
+        self.raw.maximum
+    }
+
+    #[inline]
+    pub fn step(&self) -> i32 {
+        self.raw.step
+    }
+
+    #[inline]
+    pub fn default_value(&self) -> i32 {
+        self.raw.default_value
+    }
+}
+
+impl fmt::Debug for CtrlEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CtrlEvent")
+            .field("id", &self.id)
+            .field("changes", &self.changes())
+            .field("control_type", &self.control_type())
+            .field("value", &self.value())
+            .field("flags", &self.flags())
+            .field("minimum", &self.minimum())
+            .field("maximum", &self.maximum())
+            .field("step", &self.step())
+            .field("default_value", &self.default_value())
+            .finish()
+    }
+}
+
+/// An [`Event`] dequeued from a device, along with its metadata.
+pub struct DequeuedEvent(raw::Event);
+
+impl DequeuedEvent {
+    /// Returns the event.
+    pub fn event(&self) -> Event {
+        let raw = &self.0;
+        unsafe {
+            match raw.type_ {
+                EventType::VSYNC => Event::Vsync {
+                    field: Field(raw.u.vsync.field.into()),
+                },
+                EventType::EOS => Event::Eos,
+                EventType::CTRL => Event::Ctrl(CtrlEvent {
+                    id: Cid(raw.id),
+                    raw: raw.u.ctrl,
+                }),
+                EventType::FRAME_SYNC => Event::FrameSync {
+                    frame_sequence: raw.u.frame_sync.frame_sequence,
+                },
+                EventType::SOURCE_CHANGE => Event::SourceChange {
+                    index: raw.id,
+                    changes: raw.u.src_change.changes,
+                },
+                EventType::MOTION_DET => {
+                    let det = raw.u.motion_det;
+                    Event::MotionDet {
+                        frame_sequence: det
+                            .flags
+                            .contains(MotionDetFlags::HAVE_FRAME_SEQ)
+                            .then_some(det.frame_sequence),
+                        region_mask: det.region_mask,
+                    }
+                }
+                event_type => Event::Other {
+                    event_type,
+                    id: raw.id,
+                    data: raw.u.data,
+                },
+            }
+        }
+    }
+
+    /// Returns the type of the event.
+    #[inline]
+    pub fn event_type(&self) -> EventType {
+        self.0.type_
+    }
+
+    /// Returns the number of events that are still pending after this one.
+    #[inline]
+    pub fn pending(&self) -> u32 {
+        self.0.pending
+    }
+
+    /// Returns the sequence number of this event.
+    ///
+    /// Sequence numbers are counted per file descriptor and event type. A gap indicates that
+    /// events were lost because the application did not dequeue them fast enough.
+    #[inline]
+    pub fn sequence(&self) -> u32 {
+        self.0.sequence
+    }
+
+    /// Returns the time at which the event was reported, based on `CLOCK_MONOTONIC`.
+    pub fn timestamp(&self) -> Duration {
+        let ts = self.0.timestamp;
+        Duration::new(
+            ts.tv_sec.max(0) as u64,
+            ts.tv_nsec.clamp(0, 999_999_999) as u32,
+        )
+    }
+}
+
+impl fmt::Debug for DequeuedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DequeuedEvent")
+            .field("event", &self.event())
+            .field("pending", &self.pending())
+            .field("sequence", &self.sequence())
+            .field("timestamp", &self.timestamp())
+            .finish()
+    }
+}
+
+pub(crate) fn subscribe(
+    fd: c_int,
+    event_type: EventType,
+    id: u32,
+    flags: EventSubscriptionFlags,
+) -> io::Result<()> {
+    let sub = raw::EventSubscription {
+        type_: event_type,
+        id,
+        flags,
+        reserved: [0; 5],
+    };
+    unsafe {
+        raw::VIDIOC_SUBSCRIBE_EVENT.ioctl(&fd, &sub)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn unsubscribe(fd: c_int, event_type: EventType, id: u32) -> io::Result<()> {
+    let sub = raw::EventSubscription {
+        type_: event_type,
+        id,
+        flags: EventSubscriptionFlags::empty(),
+        reserved: [0; 5],
+    };
+    unsafe {
+        raw::VIDIOC_UNSUBSCRIBE_EVENT.ioctl(&fd, &sub)?;
+    }
+    Ok(())
+}
+
+/// Waits for a pending event until `deadline`, and dequeues it.
+///
+/// If no event arrives in time, an error of kind `kind` is returned.
+pub(crate) fn dequeue(
+    fd: c_int,
+    deadline: Option<Instant>,
+    kind: io::ErrorKind,
+) -> io::Result<DequeuedEvent> {
+    loop {
+        // `VIDIOC_DQEVENT` only blocks on blocking file descriptors, so we `poll` for `POLLPRI`
+        // first. This also works while the device is not streaming.
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLPRI,
+            revents: 0,
+        };
+        if !poll_until(std::slice::from_mut(&mut pollfd), deadline)? {
+            return Err(kind.into());
+        }
+
+        unsafe {
+            let mut event: raw::Event = mem::zeroed();
+            match raw::VIDIOC_DQEVENT.ioctl(&fd, &mut event) {
+                Ok(_) => return Ok(DequeuedEvent(event)),
+                // Someone else dequeued the event first.
+                Err(e) if e.raw_os_error() == Some(libc::ENOENT) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Like [`dequeue`], but with a timeout instead of a deadline.
+pub(crate) fn dequeue_timeout(
+    fd: c_int,
+    timeout: Duration,
+    kind: io::ErrorKind,
+) -> io::Result<DequeuedEvent> {
+    // Timeouts too large to be represented wait indefinitely.
+    dequeue(fd, Instant::now().checked_add(timeout), kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn event_struct_sizes() {
+        assert_eq!(mem::size_of::<raw::EventSubscription>(), 32);
+        assert_eq!(mem::size_of::<raw::EventUnion>(), 64);
+        assert_eq!(mem::size_of::<raw::Event>(), 136);
+    }
+
+    #[test]
+    fn decode_events() {
+        let mut raw: raw::Event = unsafe { mem::zeroed() };
+        raw.type_ = EventType::SOURCE_CHANGE;
+        raw.id = 1;
+        raw.u.src_change = raw::EventSrcChange {
+            changes: SourceChanges::RESOLUTION,
+        };
+        raw.timestamp.tv_sec = 2;
+        raw.timestamp.tv_nsec = 500;
+        let event = DequeuedEvent(raw);
+        assert!(matches!(
+            event.event(),
+            Event::SourceChange { index: 1, changes } if changes == SourceChanges::RESOLUTION
+        ));
+        assert_eq!(event.timestamp(), Duration::new(2, 500));
+
+        raw.type_ = EventType::CTRL;
+        raw.id = Cid::BRIGHTNESS.0;
+        raw.u.ctrl = unsafe { mem::zeroed() };
+        raw.u.ctrl.type_ = CtrlType::INTEGER;
+        raw.u.ctrl.value.value64 = -1;
+        raw.u.ctrl.value.value = 42;
+        let Event::Ctrl(ctrl) = DequeuedEvent(raw).event() else {
+            panic!("expected a control event");
+        };
+        assert_eq!(ctrl.id(), Cid::BRIGHTNESS);
+        assert_eq!(ctrl.value(), 42);
+
+        raw.type_ = EventType::MOTION_DET;
+        raw.u.motion_det = raw::EventMotionDet {
+            flags: MotionDetFlags::empty(),
+            frame_sequence: 7,
+            region_mask: 0b10,
+        };
+        assert!(matches!(
+            DequeuedEvent(raw).event(),
+            Event::MotionDet {
+                frame_sequence: None,
+                region_mask: 0b10
+            }
+        ));
+    }
+}
@@ -6,7 +6,7 @@ use crate::shared::{FrmIvalType, FrmSizeType};
 use crate::{byte_array_to_str, raw, BufType, Device, Fract};
 
 pub use crate::pixel_format::PixelFormat;
-pub use crate::shared::FormatFlags;
+pub use crate::shared::{Field, FormatFlags};
 
 /// Formats of all possible buffer types.
 #[derive(Debug)]
@@ -52,6 +52,41 @@ impl Format {
             _ => return None,
         })
     }
+
+    pub(crate) fn to_raw(&self) -> raw::Format {
+        let mut raw_format: raw::Format = unsafe { mem::zeroed() };
+        match self {
+            Format::VideoCapture(f) => {
+                raw_format.type_ = BufType::VIDEO_CAPTURE;
+                raw_format.fmt.pix = f.to_raw();
+            }
+            Format::VideoOutput(f) => {
+                raw_format.type_ = BufType::VIDEO_OUTPUT;
+                raw_format.fmt.pix = f.to_raw();
+            }
+            Format::VideoCaptureMplane(f) => {
+                raw_format.type_ = BufType::VIDEO_CAPTURE_MPLANE;
+                raw_format.fmt.pix_mp = f.to_raw();
+            }
+            Format::VideoOutputMplane(f) => {
+                raw_format.type_ = BufType::VIDEO_OUTPUT_MPLANE;
+                raw_format.fmt.pix_mp = f.to_raw();
+            }
+            Format::VideoOverlay(f) => {
+                raw_format.type_ = BufType::VIDEO_OVERLAY;
+                raw_format.fmt.win = f.to_raw();
+            }
+            Format::MetaCapture(f) => {
+                raw_format.type_ = BufType::META_CAPTURE;
+                raw_format.fmt.meta = f.to_raw();
+            }
+            Format::MetaOutput(f) => {
+                raw_format.type_ = BufType::META_OUTPUT;
+                raw_format.fmt.meta = f.to_raw();
+            }
+        }
+        raw_format
+    }
 }
 
 impl PixFormat {
@@ -64,7 +99,7 @@ impl PixFormat {
         })
     }
 
-    pub(crate) fn to_raw(self) -> raw::PixFormat {
+    pub(crate) fn to_raw(&self) -> raw::PixFormat {
         self.0
     }
 
@@ -90,10 +125,34 @@ impl PixFormat {
 }
 
 impl PixFormatMplane {
-    pub(crate) fn to_raw(self) -> raw::PixFormatMplane {
+    /// Creates a multi-planar pixel format.
+    ///
+    /// The number of planes and their layout is filled in by the driver during format negotiation.
+    pub fn new(width: u32, height: u32, pixel_format: PixelFormat) -> Self {
+        Self(raw::PixFormatMplane {
+            width,
+            height,
+            pixel_format,
+            ..unsafe { mem::zeroed() }
+        })
+    }
+
+    pub(crate) fn to_raw(&self) -> raw::PixFormatMplane {
         self.0
     }
 
+    pub fn width(&self) -> u32 {
+        self.0.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.0.height
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.0.pixel_format
+    }
+
     pub fn num_planes(&self) -> usize {
         self.0.num_planes.into()
     }
@@ -115,7 +174,7 @@ impl PlanePixFormat {
 }
 
 impl Window {
-    pub(crate) fn to_raw(self) -> raw::Window {
+    pub(crate) fn to_raw(&self) -> raw::Window {
         self.0
     }
 }
@@ -132,7 +191,7 @@ impl MetaFormat {
         self.0.buffersize
     }
 
-    pub(crate) fn to_raw(self) -> raw::MetaFormat {
+    pub(crate) fn to_raw(&self) -> raw::MetaFormat {
         self.0
     }
 }
@@ -320,7 +379,7 @@ impl FrameSizes {
                                 });
                             }
                             Err(e) if e.raw_os_error() == Some(libc::EINVAL as _) => break,
-                            Err(e) => return Err(e.into()),
+                            Err(e) => return Err(e),
                         }
                     }
 
@@ -459,7 +518,7 @@ impl FrameIntervals {
                                 });
                             }
                             Err(e) if e.raw_os_error() == Some(libc::EINVAL as _) => break,
-                            Err(e) => return Err(e.into()),
+                            Err(e) => return Err(e),
                         }
                     }
 
@@ -9,8 +9,11 @@
 #[macro_use]
 mod macros;
 mod buf_type;
+pub mod codec;
 pub mod controls;
+pub mod event;
 pub mod format;
+pub mod media;
 mod pixel_format;
 mod raw;
 mod shared;
@@ -20,18 +23,24 @@ pub mod uvc;
 use pixel_format::PixelFormat;
 use std::{
     fmt,
-    fs::{self, File, OpenOptions},
+    fs::{self, File},
     io::{self, Read, Write},
     mem::{self, MaybeUninit},
     os::unix::prelude::*,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
-use controls::{ControlDesc, ControlIter, TextMenuIter};
-use format::{Format, FormatDescIter, FrameIntervals, FrameSizes, MetaFormat, PixFormat};
+use codec::stateless::StatelessDecoderDevice;
+use codec::{DecoderDevice, EncoderDevice};
+use controls::{ControlDesc, ControlIter, ExtControls, TextMenuIter};
+use event::{DequeuedEvent, EventSubscriptionFlags, EventType};
+use format::{
+    Format, FormatDescIter, FrameIntervals, FrameSizes, MetaFormat, PixFormat, PixFormatMplane,
+};
 use raw::controls::Cid;
-use shared::{CaptureParamFlags, Memory, StreamParamCaps};
-use stream::{ReadStream, WriteStream, DEFAULT_BUFFER_COUNT};
+use shared::{CaptureParamFlags, StreamParamCaps};
+use stream::{BufCap, M2mStream, ReadStream, StreamConfig, UserBuffer, WriteStream};
 
 pub use buf_type::*;
 pub use shared::{
@@ -62,7 +71,7 @@ pub fn list() -> io::Result<impl Iterator<Item = io::Result<Device>>> {
     Ok(fs::read_dir("/dev")?.flat_map(|file| {
         let file = match file {
             Ok(file) => file,
-            Err(e) => return Some(Err(e.into())),
+            Err(e) => return Some(Err(e)),
         };
 
         let name = file.file_name();
@@ -86,13 +95,44 @@ pub fn list() -> io::Result<impl Iterator<Item = io::Result<Device>>> {
                     return None;
                 }
             }
-            Err(e) => return Some(Err(e.into())),
+            Err(e) => return Some(Err(e)),
         }
 
-        Some(Device::open(&file.path()))
+        Some(Device::open(file.path()))
     }))
 }
 
+/// Options that control how a [`Device`] is opened.
+///
+/// This works like [`std::fs::OpenOptions`]: create it with [`OpenOptions::new`], configure it, then
+/// call [`OpenOptions::open`] or pass it to [`Device::open_with`].
+#[derive(Debug, Clone, Default)]
+pub struct OpenOptions {
+    nonblocking: bool,
+}
+
+impl OpenOptions {
+    /// Creates a new set of options with default values (blocking mode).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether the device should be opened in non-blocking mode (`O_NONBLOCK`).
+    ///
+    /// In non-blocking mode, operations that would have to wait for the device return an error
+    /// of kind [`io::ErrorKind::WouldBlock`] instead. This affects [`ReadStream::dequeue`] and
+    /// [`WriteStream::enqueue`], as well as reading from or writing to the device directly.
+    pub fn nonblocking(&mut self, nonblocking: bool) -> &mut Self {
+        self.nonblocking = nonblocking;
+        self
+    }
+
+    /// Opens the V4L2 device at `path` with these options.
+    pub fn open<A: AsRef<Path>>(&self, path: A) -> io::Result<Device> {
+        Device::open_with(path, self)
+    }
+}
+
 /// A V4L2 device.
 #[derive(Debug)]
 pub struct Device {
@@ -105,11 +145,26 @@ impl Device {
     ///
     /// If the path does not refer to a V4L2 device node, an error will be returned.
     pub fn open<A: AsRef<Path>>(path: A) -> io::Result<Self> {
-        Self::open_impl(path.as_ref())
+        Self::open_impl(path.as_ref(), &OpenOptions::new())
+    }
+
+    /// Opens a V4L2 device file from the given path, using the given [`OpenOptions`].
+    ///
+    /// If the path does not refer to a V4L2 device node, an error will be returned.
+    pub fn open_with<A: AsRef<Path>>(path: A, options: &OpenOptions) -> io::Result<Self> {
+        Self::open_impl(path.as_ref(), options)
     }
 
-    fn open_impl(path: &Path) -> io::Result<Self> {
-        let file = OpenOptions::new().read(true).write(true).open(path)?;
+    fn open_impl(path: &Path, options: &OpenOptions) -> io::Result<Self> {
+        let mut flags = 0;
+        if options.nonblocking {
+            flags |= libc::O_NONBLOCK;
+        }
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(flags)
+            .open(path)?;
         let mut this = Self {
             file,
             available_capabilities: CapabilityFlags::empty(),
@@ -227,6 +282,59 @@ impl Device {
         Ok(())
     }
 
+    /// Atomically sets the values of all controls in `controls`.
+    ///
+    /// If any value is rejected, none of the controls are changed, and the returned error names
+    /// the offending control (if the driver reports it).
+    pub fn write_controls(&mut self, controls: &mut ExtControls) -> io::Result<()> {
+        controls.write(self.fd(), raw::controls::CTRL_WHICH_CUR_VAL, -1)
+    }
+
+    /// Subscribes to events of type `event_type`.
+    ///
+    /// `id` selects the event source for event types that have several: for [`EventType::CTRL`]
+    /// it is the control ID, and for [`EventType::SOURCE_CHANGE`] the input index. For other event
+    /// types, it should be 0.
+    pub fn subscribe_event(
+        &self,
+        event_type: EventType,
+        id: u32,
+        flags: EventSubscriptionFlags,
+    ) -> io::Result<()> {
+        event::subscribe(self.fd(), event_type, id, flags)
+    }
+
+    /// Unsubscribes from events previously subscribed to with [`Device::subscribe_event`].
+    pub fn unsubscribe_event(&self, event_type: EventType, id: u32) -> io::Result<()> {
+        event::unsubscribe(self.fd(), event_type, id)
+    }
+
+    /// Unsubscribes from all events.
+    pub fn unsubscribe_all_events(&self) -> io::Result<()> {
+        event::unsubscribe(self.fd(), EventType::ALL, 0)
+    }
+
+    /// Waits for a subscribed event to occur, and dequeues it.
+    ///
+    /// This blocks even if the device was opened in non-blocking mode.
+    pub fn dequeue_event(&self) -> io::Result<DequeuedEvent> {
+        event::dequeue(self.fd(), None, io::ErrorKind::TimedOut)
+    }
+
+    /// Dequeues a pending event without blocking.
+    ///
+    /// If no event is pending, an error of kind [`io::ErrorKind::WouldBlock`] is returned.
+    pub fn try_dequeue_event(&self) -> io::Result<DequeuedEvent> {
+        event::dequeue_timeout(self.fd(), Duration::ZERO, io::ErrorKind::WouldBlock)
+    }
+
+    /// Waits at most `timeout` for a subscribed event to occur, and dequeues it.
+    ///
+    /// If no event occurs in time, an error of kind [`io::ErrorKind::TimedOut`] is returned.
+    pub fn dequeue_event_timeout(&self, timeout: Duration) -> io::Result<DequeuedEvent> {
+        event::dequeue_timeout(self.fd(), timeout, io::ErrorKind::TimedOut)
+    }
+
     /// Reads the stream format in use by `buf_type`.
     ///
     /// The returned [`Format`] variant will match `buf_type`.
@@ -256,37 +364,7 @@ impl Device {
     /// will not be changed). The modified `Format` is returned.
     fn set_format_raw(&mut self, format: Format) -> io::Result<Format> {
         unsafe {
-            let mut raw_format: raw::Format = mem::zeroed();
-            match format {
-                Format::VideoCapture(f) => {
-                    raw_format.type_ = BufType::VIDEO_CAPTURE;
-                    raw_format.fmt.pix = f.to_raw();
-                }
-                Format::VideoOutput(f) => {
-                    raw_format.type_ = BufType::VIDEO_OUTPUT;
-                    raw_format.fmt.pix = f.to_raw();
-                }
-                Format::VideoCaptureMplane(f) => {
-                    raw_format.type_ = BufType::VIDEO_CAPTURE_MPLANE;
-                    raw_format.fmt.pix_mp = f.to_raw();
-                }
-                Format::VideoOutputMplane(f) => {
-                    raw_format.type_ = BufType::VIDEO_OUTPUT_MPLANE;
-                    raw_format.fmt.pix_mp = f.to_raw();
-                }
-                Format::VideoOverlay(f) => {
-                    raw_format.type_ = BufType::VIDEO_OVERLAY;
-                    raw_format.fmt.win = f.to_raw();
-                }
-                Format::MetaCapture(f) => {
-                    raw_format.type_ = BufType::META_CAPTURE;
-                    raw_format.fmt.meta = f.to_raw();
-                }
-                Format::MetaOutput(f) => {
-                    raw_format.type_ = BufType::META_OUTPUT;
-                    raw_format.fmt.meta = f.to_raw();
-                }
-            }
+            let mut raw_format = format.to_raw();
             raw::VIDIOC_S_FMT.ioctl(self, &mut raw_format)?;
             let fmt = Format::from_raw(raw_format).unwrap();
             Ok(fmt)
@@ -333,6 +411,46 @@ impl Device {
         })
     }
 
+    /// Puts the device into multi-planar video capture mode and negotiates a pixel format.
+    ///
+    /// Multi-planar formats store each image in several separate memory planes (for example, one
+    /// for luma and one for chroma). Some devices only support the multi-planar API, even for
+    /// formats that only have a single plane.
+    ///
+    /// Format negotiation works like in [`Device::video_capture`].
+    pub fn video_capture_mplane(
+        mut self,
+        format: PixFormatMplane,
+    ) -> io::Result<VideoCaptureMplaneDevice> {
+        let format = match self.set_format_raw(Format::VideoCaptureMplane(format))? {
+            Format::VideoCaptureMplane(fmt) => fmt,
+            _ => unreachable!(),
+        };
+
+        Ok(VideoCaptureMplaneDevice {
+            file: self.file,
+            format,
+        })
+    }
+
+    /// Puts the device into multi-planar video output mode and negotiates a pixel format.
+    ///
+    /// Format negotiation works like in [`Device::video_output`].
+    pub fn video_output_mplane(
+        mut self,
+        format: PixFormatMplane,
+    ) -> io::Result<VideoOutputMplaneDevice> {
+        let format = match self.set_format_raw(Format::VideoOutputMplane(format))? {
+            Format::VideoOutputMplane(fmt) => fmt,
+            _ => unreachable!(),
+        };
+
+        Ok(VideoOutputMplaneDevice {
+            file: self.file,
+            format,
+        })
+    }
+
     /// Puts the device into metadata capture mode and negotiates a data format.
     pub fn meta_capture(mut self, format: MetaFormat) -> io::Result<MetaCaptureDevice> {
         let format = match self.set_format_raw(Format::MetaCapture(format))? {
@@ -345,6 +463,141 @@ impl Device {
             format,
         })
     }
+
+    /// Puts a memory-to-memory device into video processing mode and negotiates the formats of
+    /// both queues.
+    ///
+    /// Memory-to-memory devices (like scalers, format converters and codecs) read frames from an
+    /// OUTPUT queue, process them, and write the results to a CAPTURE queue on the same file
+    /// descriptor. Such devices report [`CapabilityFlags::VIDEO_M2M`].
+    ///
+    /// The `output` format is negotiated first, since the driver may adjust the `capture` format
+    /// based on it. Format negotiation otherwise works like in [`Device::video_capture`].
+    pub fn video_m2m(mut self, output: PixFormat, capture: PixFormat) -> io::Result<M2mDevice> {
+        let output_format = self.set_format_raw(Format::VideoOutput(output))?;
+        let capture_format = self.set_format_raw(Format::VideoCapture(capture))?;
+
+        Ok(M2mDevice {
+            file: self.file,
+            output_format,
+            capture_format,
+        })
+    }
+
+    /// Puts a stateful encoder into encoding mode and negotiates the coded and raw formats.
+    ///
+    /// `coded` is the format of the compressed bitstream produced by the encoder (on the CAPTURE
+    /// queue), and `raw` the format of the uncompressed frames passed to it (on the OUTPUT
+    /// queue). As required by the stateful encoder interface, the coded format is negotiated
+    /// first, since it determines which raw formats are supported. The resolution is set via the
+    /// raw format.
+    pub fn video_encoder(mut self, coded: PixFormat, raw: PixFormat) -> io::Result<EncoderDevice> {
+        let capture_format = self.set_format_raw(Format::VideoCapture(coded))?;
+        let output_format = self.set_format_raw(Format::VideoOutput(raw))?;
+
+        Ok(EncoderDevice::new(M2mDevice {
+            file: self.file,
+            output_format,
+            capture_format,
+        }))
+    }
+
+    /// Puts a multi-planar stateful encoder into encoding mode and negotiates the coded and raw
+    /// formats.
+    ///
+    /// This works like [`Device::video_encoder`].
+    pub fn video_encoder_mplane(
+        mut self,
+        coded: PixFormatMplane,
+        raw: PixFormatMplane,
+    ) -> io::Result<EncoderDevice> {
+        let capture_format = self.set_format_raw(Format::VideoCaptureMplane(coded))?;
+        let output_format = self.set_format_raw(Format::VideoOutputMplane(raw))?;
+
+        Ok(EncoderDevice::new(M2mDevice {
+            file: self.file,
+            output_format,
+            capture_format,
+        }))
+    }
+
+    /// Puts a stateful decoder into decoding mode and sets the coded format.
+    ///
+    /// `coded` is the format of the compressed bitstream passed to the decoder (on the OUTPUT
+    /// queue). Its resolution may be left at 0, since the decoder determines the resolution and
+    /// the format of the decoded frames from the bitstream.
+    pub fn video_decoder(mut self, coded: PixFormat) -> io::Result<DecoderDevice> {
+        let coded_format = self.set_format_raw(Format::VideoOutput(coded))?;
+        Ok(DecoderDevice::new(self.file, coded_format))
+    }
+
+    /// Puts a multi-planar stateful decoder into decoding mode and sets the coded format.
+    ///
+    /// This works like [`Device::video_decoder`].
+    pub fn video_decoder_mplane(mut self, coded: PixFormatMplane) -> io::Result<DecoderDevice> {
+        let coded_format = self.set_format_raw(Format::VideoOutputMplane(coded))?;
+        Ok(DecoderDevice::new(self.file, coded_format))
+    }
+
+    /// Puts a stateless decoder into decoding mode and negotiates the coded and decoded formats.
+    ///
+    /// `coded` is the format of the compressed data passed to the decoder (on the OUTPUT queue),
+    /// like [`PixelFormat::FWHT_STATELESS`]. Unlike stateful decoders, stateless decoders need to
+    /// be told the resolution of the stream up front. `decoded` is the format of the decoded frames
+    /// (on the CAPTURE queue), which is negotiated after the coded format.
+    pub fn video_stateless_decoder(
+        mut self,
+        coded: PixFormat,
+        decoded: PixFormat,
+    ) -> io::Result<StatelessDecoderDevice> {
+        let output_format = self.set_format_raw(Format::VideoOutput(coded))?;
+        let capture_format = self.set_format_raw(Format::VideoCapture(decoded))?;
+
+        Ok(StatelessDecoderDevice::new(M2mDevice {
+            file: self.file,
+            output_format,
+            capture_format,
+        }))
+    }
+
+    /// Puts a multi-planar stateless decoder into decoding mode and negotiates the coded and
+    /// decoded formats.
+    ///
+    /// This works like [`Device::video_stateless_decoder`].
+    pub fn video_stateless_decoder_mplane(
+        mut self,
+        coded: PixFormatMplane,
+        decoded: PixFormatMplane,
+    ) -> io::Result<StatelessDecoderDevice> {
+        let output_format = self.set_format_raw(Format::VideoOutputMplane(coded))?;
+        let capture_format = self.set_format_raw(Format::VideoCaptureMplane(decoded))?;
+
+        Ok(StatelessDecoderDevice::new(M2mDevice {
+            file: self.file,
+            output_format,
+            capture_format,
+        }))
+    }
+
+    /// Puts a multi-planar memory-to-memory device into video processing mode and negotiates the
+    /// formats of both queues.
+    ///
+    /// This works like [`Device::video_m2m`], but for devices that report
+    /// [`CapabilityFlags::VIDEO_M2M_MPLANE`].
+    pub fn video_m2m_mplane(
+        mut self,
+        output: PixFormatMplane,
+        capture: PixFormatMplane,
+    ) -> io::Result<M2mDevice> {
+        let output_format = self.set_format_raw(Format::VideoOutputMplane(output))?;
+        let capture_format = self.set_format_raw(Format::VideoCaptureMplane(capture))?;
+
+        Ok(M2mDevice {
+            file: self.file,
+            output_format,
+            capture_format,
+        })
+    }
 }
 
 impl AsRawFd for Device {
@@ -384,33 +637,49 @@ impl VideoCaptureDevice {
     /// Supported frame intervals depend on the pixel format and video resolution and can be
     /// enumerated with [`Device::frame_intervals`].
     pub fn set_frame_interval(&self, interval: Fract) -> io::Result<Fract> {
-        unsafe {
-            let mut parm = raw::StreamParm {
-                type_: BufType::VIDEO_CAPTURE,
-                union: raw::StreamParmUnion {
-                    capture: raw::CaptureParm {
-                        timeperframe: interval,
-                        capability: StreamParamCaps::TIMEPERFRAME,
-                        capturemode: CaptureParamFlags::empty(),
-                        extendedmode: 0,
-                        readbuffers: 0,
-                        reserved: [0; 4],
-                    },
-                },
-            };
-            raw::VIDIOC_S_PARM.ioctl(self, &mut parm)?;
-            Ok(parm.union.capture.timeperframe)
-        }
+        set_capture_frame_interval(&self.file, BufType::VIDEO_CAPTURE, interval)
     }
 
     /// Initializes streaming I/O mode.
+    ///
+    /// This uses the default [`StreamConfig`]. Use [`Self::into_stream_with`] to configure the
+    /// buffers.
     pub fn into_stream(self) -> io::Result<ReadStream> {
-        Ok(ReadStream::new(
-            self.file,
-            BufType::VIDEO_CAPTURE,
-            Memory::MMAP,
-            DEFAULT_BUFFER_COUNT,
-        )?)
+        self.into_stream_with(&StreamConfig::new())
+    }
+
+    /// Initializes streaming I/O mode with buffers configured by `config`.
+    pub fn into_stream_with(self, config: &StreamConfig) -> io::Result<ReadStream> {
+        ReadStream::new(self.file, BufType::VIDEO_CAPTURE, config)
+    }
+
+    /// Initializes streaming I/O mode using application-allocated buffers.
+    ///
+    /// The driver will write captured frames directly into `buffers`. Each buffer has to be at
+    /// least [`PixFormat::size_image`] bytes large, and enough buffers have to be provided to
+    /// satisfy the driver's minimum buffer count, otherwise an error of kind
+    /// [`io::ErrorKind::InvalidInput`] is returned.
+    ///
+    /// If the driver is unable to use all of the provided buffers, the excess buffers are freed.
+    pub fn into_userptr_stream(self, buffers: Vec<UserBuffer>) -> io::Result<ReadStream> {
+        ReadStream::with_user_buffers(self.file, BufType::VIDEO_CAPTURE, single_planar(buffers))
+    }
+
+    /// Initializes streaming I/O mode, importing the given dma-bufs as capture buffers.
+    ///
+    /// The driver will write captured frames directly into the dma-bufs, so that they can be passed
+    /// on to other devices without copying. [`ReadBufferView::index`] indicates which dma-buf was
+    /// filled. Each dma-buf has to be at least [`PixFormat::size_image`] bytes large, and enough
+    /// dma-bufs have to be provided to satisfy the driver's minimum buffer count, otherwise an
+    /// error of kind [`io::ErrorKind::InvalidInput`] is returned.
+    ///
+    /// If the dma-bufs can be `mmap`ped, their contents are also accessible through the
+    /// [`ReadBufferView`]. Otherwise, the [`ReadBufferView`] will be empty.
+    ///
+    /// [`ReadBufferView::index`]: stream::ReadBufferView::index
+    /// [`ReadBufferView`]: stream::ReadBufferView
+    pub fn into_dmabuf_stream(self, dmabufs: Vec<OwnedFd>) -> io::Result<ReadStream> {
+        ReadStream::with_dmabufs(self.file, BufType::VIDEO_CAPTURE, single_planar(dmabufs))
     }
 }
 
@@ -453,13 +722,45 @@ impl VideoOutputDevice {
     }
 
     /// Initializes streaming I/O mode.
+    ///
+    /// This uses the default [`StreamConfig`]. Use [`Self::into_stream_with`] to configure the
+    /// buffers.
     pub fn into_stream(self) -> io::Result<WriteStream> {
-        Ok(WriteStream::new(
-            self.file,
-            BufType::VIDEO_CAPTURE,
-            Memory::MMAP,
-            DEFAULT_BUFFER_COUNT,
-        )?)
+        self.into_stream_with(&StreamConfig::new())
+    }
+
+    /// Initializes streaming I/O mode with buffers configured by `config`.
+    pub fn into_stream_with(self, config: &StreamConfig) -> io::Result<WriteStream> {
+        WriteStream::new(self.file, BufType::VIDEO_OUTPUT, config)
+    }
+
+    /// Initializes streaming I/O mode using application-allocated buffers.
+    ///
+    /// The driver will read output frames directly from `buffers`. Each buffer has to be at least
+    /// [`PixFormat::size_image`] bytes large, and enough buffers have to be provided to satisfy the
+    /// driver's minimum buffer count, otherwise an error of kind [`io::ErrorKind::InvalidInput`]
+    /// is returned.
+    ///
+    /// If the driver is unable to use all of the provided buffers, the excess buffers are freed.
+    pub fn into_userptr_stream(self, buffers: Vec<UserBuffer>) -> io::Result<WriteStream> {
+        WriteStream::with_user_buffers(self.file, BufType::VIDEO_OUTPUT, single_planar(buffers))
+    }
+
+    /// Initializes streaming I/O mode, importing the given dma-bufs as output buffers.
+    ///
+    /// The driver will read output frames directly from the dma-bufs. [`WriteBufferView::index`]
+    /// indicates which dma-buf is about to be enqueued. Each dma-buf has to be at least
+    /// [`PixFormat::size_image`] bytes large, and enough dma-bufs have to be provided to satisfy
+    /// the driver's minimum buffer count, otherwise an error of kind
+    /// [`io::ErrorKind::InvalidInput`] is returned.
+    ///
+    /// If the dma-bufs can be `mmap`ped, their contents are also accessible through the
+    /// [`WriteBufferView`]. Otherwise, the [`WriteBufferView`] will be empty.
+    ///
+    /// [`WriteBufferView::index`]: stream::WriteBufferView::index
+    /// [`WriteBufferView`]: stream::WriteBufferView
+    pub fn into_dmabuf_stream(self, dmabufs: Vec<OwnedFd>) -> io::Result<WriteStream> {
+        WriteStream::with_dmabufs(self.file, BufType::VIDEO_OUTPUT, single_planar(dmabufs))
     }
 }
 
@@ -494,6 +795,140 @@ impl AsFd for VideoOutputDevice {
     }
 }
 
+/// A video device configured for multi-planar video capture.
+///
+/// Returned by [`Device::video_capture_mplane`].
+pub struct VideoCaptureMplaneDevice {
+    file: File,
+    format: PixFormatMplane,
+}
+
+impl VideoCaptureMplaneDevice {
+    /// Returns the pixel format the driver chose for capturing.
+    ///
+    /// This may (and usually will) differ from the format passed to
+    /// [`Device::video_capture_mplane`].
+    pub fn format(&self) -> &PixFormatMplane {
+        &self.format
+    }
+
+    /// Requests a change to the frame interval.
+    ///
+    /// Returns the actual frame interval chosen by the driver.
+    pub fn set_frame_interval(&self, interval: Fract) -> io::Result<Fract> {
+        set_capture_frame_interval(&self.file, BufType::VIDEO_CAPTURE_MPLANE, interval)
+    }
+
+    /// Initializes streaming I/O mode.
+    ///
+    /// The planes of each captured frame can be accessed via [`ReadBufferView::planes`].
+    ///
+    /// [`ReadBufferView::planes`]: stream::ReadBufferView::planes
+    pub fn into_stream(self) -> io::Result<ReadStream> {
+        self.into_stream_with(&StreamConfig::new())
+    }
+
+    /// Initializes streaming I/O mode with buffers configured by `config`.
+    pub fn into_stream_with(self, config: &StreamConfig) -> io::Result<ReadStream> {
+        ReadStream::new(self.file, BufType::VIDEO_CAPTURE_MPLANE, config)
+    }
+
+    /// Initializes streaming I/O mode using application-allocated buffers.
+    ///
+    /// Every element of `buffers` holds the planes of one buffer. Each buffer needs to have as
+    /// many planes as the negotiated format, and each plane has to be at least as large as the
+    /// [`PlanePixFormat::size_image`] of the corresponding plane format.
+    ///
+    /// [`PlanePixFormat::size_image`]: format::PlanePixFormat::size_image
+    pub fn into_userptr_stream(self, buffers: Vec<Vec<UserBuffer>>) -> io::Result<ReadStream> {
+        ReadStream::with_user_buffers(self.file, BufType::VIDEO_CAPTURE_MPLANE, buffers)
+    }
+
+    /// Initializes streaming I/O mode, importing the given dma-bufs as capture buffers.
+    ///
+    /// Every element of `dmabufs` holds the planes of one buffer. Otherwise, this works like
+    /// [`VideoCaptureDevice::into_dmabuf_stream`].
+    pub fn into_dmabuf_stream(self, dmabufs: Vec<Vec<OwnedFd>>) -> io::Result<ReadStream> {
+        ReadStream::with_dmabufs(self.file, BufType::VIDEO_CAPTURE_MPLANE, dmabufs)
+    }
+}
+
+impl AsRawFd for VideoCaptureMplaneDevice {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for VideoCaptureMplaneDevice {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        unsafe { BorrowedFd::borrow_raw(self.as_raw_fd()) }
+    }
+}
+
+/// A video device configured for multi-planar video output.
+///
+/// Returned by [`Device::video_output_mplane`].
+pub struct VideoOutputMplaneDevice {
+    file: File,
+    format: PixFormatMplane,
+}
+
+impl VideoOutputMplaneDevice {
+    /// Returns the video format chosen by the driver.
+    pub fn format(&self) -> &PixFormatMplane {
+        &self.format
+    }
+
+    /// Initializes streaming I/O mode.
+    ///
+    /// The planes of each output frame can be accessed via [`WriteBufferView::planes_mut`].
+    ///
+    /// [`WriteBufferView::planes_mut`]: stream::WriteBufferView::planes_mut
+    pub fn into_stream(self) -> io::Result<WriteStream> {
+        self.into_stream_with(&StreamConfig::new())
+    }
+
+    /// Initializes streaming I/O mode with buffers configured by `config`.
+    pub fn into_stream_with(self, config: &StreamConfig) -> io::Result<WriteStream> {
+        WriteStream::new(self.file, BufType::VIDEO_OUTPUT_MPLANE, config)
+    }
+
+    /// Initializes streaming I/O mode using application-allocated buffers.
+    ///
+    /// Every element of `buffers` holds the planes of one buffer. Each buffer needs to have as
+    /// many planes as the negotiated format, and each plane has to be at least as large as the
+    /// [`PlanePixFormat::size_image`] of the corresponding plane format.
+    ///
+    /// [`PlanePixFormat::size_image`]: format::PlanePixFormat::size_image
+    pub fn into_userptr_stream(self, buffers: Vec<Vec<UserBuffer>>) -> io::Result<WriteStream> {
+        WriteStream::with_user_buffers(self.file, BufType::VIDEO_OUTPUT_MPLANE, buffers)
+    }
+
+    /// Initializes streaming I/O mode, importing the given dma-bufs as output buffers.
+    ///
+    /// Every element of `dmabufs` holds the planes of one buffer. Otherwise, this works like
+    /// [`VideoOutputDevice::into_dmabuf_stream`].
+    pub fn into_dmabuf_stream(self, dmabufs: Vec<Vec<OwnedFd>>) -> io::Result<WriteStream> {
+        WriteStream::with_dmabufs(self.file, BufType::VIDEO_OUTPUT_MPLANE, dmabufs)
+    }
+}
+
+impl AsRawFd for VideoOutputMplaneDevice {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for VideoOutputMplaneDevice {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        unsafe { BorrowedFd::borrow_raw(self.as_raw_fd()) }
+    }
+}
+
 /// A device configured for metadata capture.
 ///
 /// Returned by [`Device::meta_capture`].
@@ -509,13 +944,16 @@ impl MetaCaptureDevice {
     }
 
     /// Initializes streaming I/O mode.
+    ///
+    /// This uses the default [`StreamConfig`]. Use [`Self::into_stream_with`] to configure the
+    /// buffers.
     pub fn into_stream(self) -> io::Result<ReadStream> {
-        Ok(ReadStream::new(
-            self.file,
-            BufType::META_CAPTURE,
-            Memory::MMAP,
-            DEFAULT_BUFFER_COUNT,
-        )?)
+        self.into_stream_with(&StreamConfig::new())
+    }
+
+    /// Initializes streaming I/O mode with buffers configured by `config`.
+    pub fn into_stream_with(self, config: &StreamConfig) -> io::Result<ReadStream> {
+        ReadStream::new(self.file, BufType::META_CAPTURE, config)
     }
 }
 
@@ -543,6 +981,87 @@ impl AsFd for MetaCaptureDevice {
     }
 }
 
+/// A memory-to-memory device with configured OUTPUT and CAPTURE queues.
+///
+/// Created by [`Device::video_m2m`] or [`Device::video_m2m_mplane`].
+pub struct M2mDevice {
+    file: File,
+    output_format: Format,
+    capture_format: Format,
+}
+
+impl M2mDevice {
+    /// Returns the format of the OUTPUT queue (the frames passed to the device).
+    ///
+    /// This is either [`Format::VideoOutput`] or [`Format::VideoOutputMplane`].
+    pub fn output_format(&self) -> &Format {
+        &self.output_format
+    }
+
+    /// Returns the format of the CAPTURE queue (the frames produced by the device).
+    ///
+    /// This is either [`Format::VideoCapture`] or [`Format::VideoCaptureMplane`].
+    pub fn capture_format(&self) -> &Format {
+        &self.capture_format
+    }
+
+    /// Initializes streaming I/O mode on both queues.
+    ///
+    /// This uses the default [`StreamConfig`] for both queues. Use [`Self::into_stream_with`] to
+    /// configure the buffers.
+    pub fn into_stream(self) -> io::Result<M2mStream> {
+        let config = StreamConfig::new();
+        self.into_stream_with(&config, &config)
+    }
+
+    /// Initializes streaming I/O mode, with the buffers of the OUTPUT queue configured by `output`
+    /// and those of the CAPTURE queue configured by `capture`.
+    pub fn into_stream_with(
+        self,
+        output: &StreamConfig,
+        capture: &StreamConfig,
+    ) -> io::Result<M2mStream> {
+        let (output_type, capture_type) = self.buf_types();
+
+        // Both streams operate on the same open file description, which is what V4L2 associates
+        // the queues with.
+        let capture_file = self.file.try_clone()?;
+        let output = WriteStream::new(self.file, output_type, output)?;
+        let capture = ReadStream::new(capture_file, capture_type, capture)?;
+        Ok(M2mStream::new(output, capture))
+    }
+
+    /// Returns the capabilities of the OUTPUT queue, without allocating any buffers.
+    pub(crate) fn output_capabilities(&self) -> io::Result<BufCap> {
+        let (output_type, _) = self.buf_types();
+        stream::queue_capabilities(self.file.as_raw_fd(), output_type)
+    }
+
+    /// Returns the buffer types of the OUTPUT and CAPTURE queue.
+    fn buf_types(&self) -> (BufType, BufType) {
+        match self.output_format {
+            Format::VideoOutputMplane(_) => {
+                (BufType::VIDEO_OUTPUT_MPLANE, BufType::VIDEO_CAPTURE_MPLANE)
+            }
+            _ => (BufType::VIDEO_OUTPUT, BufType::VIDEO_CAPTURE),
+        }
+    }
+}
+
+impl AsRawFd for M2mDevice {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for M2mDevice {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        unsafe { BorrowedFd::borrow_raw(self.as_raw_fd()) }
+    }
+}
+
 /// Stores generic device information.
 ///
 /// Returned by [`Device::capabilities`].
@@ -820,6 +1339,56 @@ impl fmt::Debug for Input {
     }
 }
 
+/// Sets the frame interval of a capture stream of type `buf_type`.
+fn set_capture_frame_interval(
+    file: &File,
+    buf_type: BufType,
+    interval: Fract,
+) -> io::Result<Fract> {
+    unsafe {
+        let mut parm = raw::StreamParm {
+            type_: buf_type,
+            union: raw::StreamParmUnion {
+                capture: raw::CaptureParm {
+                    timeperframe: interval,
+                    capability: StreamParamCaps::TIMEPERFRAME,
+                    capturemode: CaptureParamFlags::empty(),
+                    extendedmode: 0,
+                    readbuffers: 0,
+                    reserved: [0; 4],
+                },
+            },
+        };
+        raw::VIDIOC_S_PARM.ioctl(file, &mut parm)?;
+        Ok(parm.union.capture.timeperframe)
+    }
+}
+
+fn set_output_frame_interval(file: &File, buf_type: BufType, interval: Fract) -> io::Result<Fract> {
+    unsafe {
+        let mut parm = raw::StreamParm {
+            type_: buf_type,
+            union: raw::StreamParmUnion {
+                output: raw::OutputParm {
+                    timeperframe: interval,
+                    capability: StreamParamCaps::TIMEPERFRAME,
+                    outputmode: 0,
+                    extendedmode: 0,
+                    writebuffers: 0,
+                    reserved: [0; 4],
+                },
+            },
+        };
+        raw::VIDIOC_S_PARM.ioctl(file, &mut parm)?;
+        Ok(parm.union.output.timeperframe)
+    }
+}
+
+/// Wraps every buffer in a list of planes, for single-planar buffer types.
+fn single_planar<T>(buffers: Vec<T>) -> Vec<Vec<T>> {
+    buffers.into_iter().map(|buf| vec![buf]).collect()
+}
+
 /// Turns a zero-padded byte array containing UTF-8 or ASCII data into a `&str`.
 fn byte_array_to_str(bytes: &[u8]) -> &str {
     let len = bytes
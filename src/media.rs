@@ -0,0 +1,1033 @@
+//! Media controller devices and requests.
+//!
+//! Many V4L2 devices are accompanied by a media controller device (`/dev/media*`), which describes
+//! how the hardware is structured. Its [`Topology`] is a graph of [`Entity`]s (hardware or software
+//! blocks, like sensors, scalers or DMA engines), which are connected via [`Link`]s between their
+//! [`Pad`]s. Entities that can be controlled from userspace are associated with an [`Interface`],
+//! which refers to a device node like `/dev/video0` or `/dev/v4l-subdev0`. Devices that report
+//! [`CapabilityFlags::IO_MC`] have to be configured via their media device: the links of the
+//! pipeline have to be enabled with [`MediaDevice::setup_link`] before streaming.
+//!
+//! Media devices are also used to allocate [`Request`]s, which bundle buffers and control values so
+//! that the driver applies them together, to a specific frame. Requests are used like this:
+//!
+//! 1. Allocate a [`Request`] with [`MediaDevice::alloc_request`].
+//! 2. Store control values in it via [`Request::write_controls`].
+//! 3. Add a buffer to it via [`WriteStream::enqueue_in_request`] or
+//!    [`ReadStream::enqueue_in_request`].
+//! 4. Submit it to the driver with [`Request::queue`].
+//! 5. Wait for the driver to process it with [`Request::wait`], then dequeue the buffer as usual.
+//! 6. Call [`Request::reinit`] to reuse it for the next frame.
+//!
+//! Not all drivers support requests. Queues that do report [`BufCap::SUPPORTS_REQUESTS`].
+//!
+//! [`CapabilityFlags::IO_MC`]: crate::CapabilityFlags::IO_MC
+//! [`WriteStream::enqueue_in_request`]: crate::stream::WriteStream::enqueue_in_request
+//! [`ReadStream::enqueue_in_request`]: crate::stream::ReadStream::enqueue_in_request
+//! [`BufCap::SUPPORTS_REQUESTS`]: crate::stream::BufCap::SUPPORTS_REQUESTS
+
+mod raw;
+
+use std::{
+    fmt,
+    fs::{self, File},
+    io, mem,
+    os::unix::prelude::*,
+    path::{Path, PathBuf},
+    slice,
+    time::{Duration, Instant},
+};
+
+use bitflags::bitflags;
+
+use crate::byte_array_to_str;
+use crate::controls::ExtControls;
+use crate::stream::poll_until;
+
+/// Returns an iterator over all media controller devices (`/dev/media*`).
+pub fn list() -> io::Result<impl Iterator<Item = io::Result<MediaDevice>>> {
+    Ok(fs::read_dir("/dev")?.flat_map(|file| {
+        let file = match file {
+            Ok(file) => file,
+            Err(e) => return Some(Err(e)),
+        };
+
+        if !file.file_name().as_bytes().starts_with(b"media") {
+            return None;
+        }
+        match file.file_type() {
+            Ok(ty) if ty.is_char_device() => Some(MediaDevice::open(file.path())),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }))
+}
+
+/// A media controller device (`/dev/media*`).
+#[derive(Debug)]
+pub struct MediaDevice {
+    file: File,
+}
+
+impl MediaDevice {
+    /// Opens the media controller device at `path`.
+    ///
+    /// If the path does not refer to a media controller device, an error will be returned.
+    pub fn open<A: AsRef<Path>>(path: A) -> io::Result<Self> {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path.as_ref())?;
+        let this = Self { file };
+        this.info()?;
+        Ok(this)
+    }
+
+    /// Returns the path to the media device.
+    ///
+    /// Like [`Device::path`][crate::Device::path], this relies on `/proc/self/fd`.
+    pub fn path(&self) -> io::Result<PathBuf> {
+        fs::read_link(format!("/proc/self/fd/{}", self.file.as_raw_fd()))
+    }
+
+    /// Queries information about the media device and its driver.
+    pub fn info(&self) -> io::Result<MediaDeviceInfo> {
+        unsafe {
+            let mut raw: raw::DeviceInfo = mem::zeroed();
+            raw::MEDIA_IOC_DEVICE_INFO.ioctl(&self.file, &mut raw)?;
+            Ok(MediaDeviceInfo(raw))
+        }
+    }
+
+    /// Fetches the current [`Topology`] of the media device.
+    ///
+    /// The topology is a snapshot. It can change if entities are added or removed, or when links
+    /// are enabled or disabled, which is indicated by a change of [`Topology::version`].
+    pub fn topology(&self) -> io::Result<Topology> {
+        loop {
+            // The first call only reports the number of graph objects.
+            let mut raw: raw::V2Topology = unsafe { mem::zeroed() };
+            unsafe {
+                raw::MEDIA_IOC_G_TOPOLOGY.ioctl(&self.file, &mut raw)?;
+            }
+            let version = raw.topology_version;
+
+            let mut entities =
+                vec![unsafe { mem::zeroed::<raw::V2Entity>() }; raw.num_entities as usize];
+            let mut interfaces =
+                vec![unsafe { mem::zeroed::<raw::V2Interface>() }; raw.num_interfaces as usize];
+            let mut pads = vec![unsafe { mem::zeroed::<raw::V2Pad>() }; raw.num_pads as usize];
+            let mut links = vec![unsafe { mem::zeroed::<raw::V2Link>() }; raw.num_links as usize];
+            raw.ptr_entities = entities.as_mut_ptr() as u64;
+            raw.ptr_interfaces = interfaces.as_mut_ptr() as u64;
+            raw.ptr_pads = pads.as_mut_ptr() as u64;
+            raw.ptr_links = links.as_mut_ptr() as u64;
+            match unsafe { raw::MEDIA_IOC_G_TOPOLOGY.ioctl(&self.file, &mut raw) } {
+                Ok(_) => {}
+                // The graph has grown since the first call.
+                Err(e) if e.raw_os_error() == Some(libc::ENOSPC) => continue,
+                Err(e) => return Err(e),
+            }
+            if raw.topology_version != version {
+                // The graph has changed since the first call, so the counts may be stale.
+                continue;
+            }
+
+            entities.truncate(raw.num_entities as usize);
+            interfaces.truncate(raw.num_interfaces as usize);
+            pads.truncate(raw.num_pads as usize);
+            links.truncate(raw.num_links as usize);
+            return Ok(Topology {
+                version,
+                entities: entities.iter().map(Entity::from_raw).collect(),
+                interfaces: interfaces.iter().map(Interface::from_raw).collect(),
+                pads: pads.iter().map(Pad::from_raw).collect(),
+                links: links.iter().map(Link::from_raw).collect(),
+            });
+        }
+    }
+
+    /// Enables or disables a data link between two pads.
+    ///
+    /// The current topology is fetched to check that `link` still exists, is a data link, and is
+    /// not [`LinkFlags::IMMUTABLE`]. Links usually cannot be changed while any entity in the
+    /// pipeline is streaming, unless they are [`LinkFlags::DYNAMIC`].
+    ///
+    /// Note that enabling a link may fail if the sink pad already has an enabled link and does not
+    /// support more than one.
+    pub fn setup_link(&self, link: &Link, enabled: bool) -> io::Result<()> {
+        let mut desc = self.topology()?.link_desc(link.id, enabled)?;
+        unsafe {
+            raw::MEDIA_IOC_SETUP_LINK.ioctl(&self.file, &mut desc)?;
+        }
+        Ok(())
+    }
+
+    /// Allocates a new, empty [`Request`].
+    ///
+    /// Returns an error if the driver does not support requests.
+    pub fn alloc_request(&self) -> io::Result<Request> {
+        let mut fd = -1;
+        unsafe {
+            raw::MEDIA_IOC_REQUEST_ALLOC.ioctl(&self.file, &mut fd)?;
+            Ok(Request {
+                fd: OwnedFd::from_raw_fd(fd),
+            })
+        }
+    }
+}
+
+impl AsRawFd for MediaDevice {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for MediaDevice {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+/// Information about a media device, returned by [`MediaDevice::info`].
+pub struct MediaDeviceInfo(raw::DeviceInfo);
+
+impl MediaDeviceInfo {
+    /// Returns the name of the driver.
+    pub fn driver(&self) -> &str {
+        byte_array_to_str(&self.0.driver)
+    }
+
+    /// Returns the name of the device model.
+    pub fn model(&self) -> &str {
+        byte_array_to_str(&self.0.model)
+    }
+
+    /// Returns the serial number of the device, or an empty string if it has none.
+    pub fn serial(&self) -> &str {
+        byte_array_to_str(&self.0.serial)
+    }
+
+    /// Returns the location of the device in the system (like `usb-0000:00:14.0-1`).
+    pub fn bus_info(&self) -> &str {
+        byte_array_to_str(&self.0.bus_info)
+    }
+
+    /// Returns the media API version, formatted like a kernel version (`KERNEL_VERSION`).
+    pub fn media_version(&self) -> u32 {
+        self.0.media_version
+    }
+
+    /// Returns the driver-specific hardware revision.
+    pub fn hw_revision(&self) -> u32 {
+        self.0.hw_revision
+    }
+
+    /// Returns the driver version, formatted like a kernel version (`KERNEL_VERSION`).
+    pub fn driver_version(&self) -> u32 {
+        self.0.driver_version
+    }
+}
+
+impl fmt::Debug for MediaDeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MediaDeviceInfo")
+            .field("driver", &self.driver())
+            .field("model", &self.model())
+            .field("serial", &self.serial())
+            .field("bus_info", &self.bus_info())
+            .field("media_version", &self.media_version())
+            .field("hw_revision", &self.hw_revision())
+            .field("driver_version", &self.driver_version())
+            .finish()
+    }
+}
+
+/// The graph of entities, interfaces, pads and links of a media device.
+///
+/// Returned by [`MediaDevice::topology`]. All graph objects have an ID that is unique within the
+/// media device.
+#[derive(Debug, Clone)]
+pub struct Topology {
+    version: u64,
+    entities: Vec<Entity>,
+    interfaces: Vec<Interface>,
+    pads: Vec<Pad>,
+    links: Vec<Link>,
+}
+
+impl Topology {
+    /// Returns the version of the topology, which changes whenever the graph changes.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn interfaces(&self) -> &[Interface] {
+        &self.interfaces
+    }
+
+    pub fn pads(&self) -> &[Pad] {
+        &self.pads
+    }
+
+    /// Returns all links, including interface and ancillary links.
+    pub fn links(&self) -> &[Link] {
+        &self.links
+    }
+
+    /// Returns the entity with ID `id`.
+    pub fn entity(&self, id: u32) -> Option<&Entity> {
+        self.entities.iter().find(|e| e.id == id)
+    }
+
+    /// Returns the entity called `name`.
+    pub fn entity_by_name(&self, name: &str) -> Option<&Entity> {
+        self.entities.iter().find(|e| e.name == name)
+    }
+
+    /// Returns the interface with ID `id`.
+    pub fn interface(&self, id: u32) -> Option<&Interface> {
+        self.interfaces.iter().find(|i| i.id == id)
+    }
+
+    /// Returns the pad with ID `id`.
+    pub fn pad(&self, id: u32) -> Option<&Pad> {
+        self.pads.iter().find(|p| p.id == id)
+    }
+
+    /// Returns the link with ID `id`.
+    pub fn link(&self, id: u32) -> Option<&Link> {
+        self.links.iter().find(|l| l.id == id)
+    }
+
+    /// Returns the pad of the entity `entity_id` with index `index`.
+    pub fn entity_pad(&self, entity_id: u32, index: u32) -> Option<&Pad> {
+        self.pads
+            .iter()
+            .find(|p| p.entity_id == entity_id && p.index == index)
+    }
+
+    /// Returns the data link from the pad `source_pad_id` to the pad `sink_pad_id`.
+    pub fn find_link(&self, source_pad_id: u32, sink_pad_id: u32) -> Option<&Link> {
+        self.links.iter().find(|l| {
+            l.link_type() == LinkType::DATA
+                && l.source_id == source_pad_id
+                && l.sink_id == sink_pad_id
+        })
+    }
+
+    /// Returns the pads of the entity `entity_id`.
+    pub fn entity_pads(&self, entity_id: u32) -> impl Iterator<Item = &Pad> + '_ {
+        self.pads.iter().filter(move |p| p.entity_id == entity_id)
+    }
+
+    /// Returns the data links from or to a pad of the entity `entity_id`.
+    pub fn entity_links(&self, entity_id: u32) -> impl Iterator<Item = &Link> + '_ {
+        self.links.iter().filter(move |l| {
+            l.link_type() == LinkType::DATA
+                && [l.source_id, l.sink_id]
+                    .iter()
+                    .any(|&pad| self.pad(pad).is_some_and(|p| p.entity_id == entity_id))
+        })
+    }
+
+    /// Returns the interface through which the entity `entity_id` is controlled, if it has one.
+    pub fn entity_interface(&self, entity_id: u32) -> Option<&Interface> {
+        self.links
+            .iter()
+            .find(|l| l.link_type() == LinkType::INTERFACE && l.sink_id == entity_id)
+            .and_then(|l| self.interface(l.source_id))
+    }
+
+    /// Returns the entity that is controlled through the interface `interface_id`.
+    pub fn interface_entity(&self, interface_id: u32) -> Option<&Entity> {
+        self.links
+            .iter()
+            .find(|l| l.link_type() == LinkType::INTERFACE && l.source_id == interface_id)
+            .and_then(|l| self.entity(l.sink_id))
+    }
+
+    /// Returns the entity that is controlled through the device node `devnode`.
+    pub fn devnode_entity(&self, devnode: DevNode) -> Option<&Entity> {
+        let interface = self.interfaces.iter().find(|i| i.devnode == devnode)?;
+        self.interface_entity(interface.id)
+    }
+
+    /// Returns the entity of the V4L2 device (or sub-device) that `device` refers to.
+    ///
+    /// `device` can be a [`Device`][crate::Device], a stream, or any other file descriptor that
+    /// refers to a device node. Returns `None` if the device node does not belong to this media
+    /// device.
+    pub fn device_entity(&self, device: &impl AsRawFd) -> io::Result<Option<&Entity>> {
+        Ok(self.devnode_entity(DevNode::of(device)?))
+    }
+
+    /// Builds the `MEDIA_IOC_SETUP_LINK` argument that enables or disables the link `link_id`.
+    fn link_desc(&self, link_id: u32, enabled: bool) -> io::Result<raw::LinkDesc> {
+        let link = self.link(link_id).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("link {link_id} does not exist"),
+            )
+        })?;
+        if link.link_type() != LinkType::DATA {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("link {link_id} is not a data link"),
+            ));
+        }
+        if link.is_immutable() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("link {link_id} is immutable"),
+            ));
+        }
+
+        let pad_desc = |id| {
+            let pad = self.pad(id).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, format!("pad {id} does not exist"))
+            })?;
+            io::Result::Ok(raw::PadDesc {
+                entity: pad.entity_id,
+                index: pad.index as u16,
+                flags: pad.flags.bits(),
+                reserved: [0; 2],
+            })
+        };
+        // The driver rejects changes to any flag other than `ENABLED`.
+        let mut flags = link.flags & !LinkFlags::ENABLED.bits();
+        if enabled {
+            flags |= LinkFlags::ENABLED.bits();
+        }
+        Ok(raw::LinkDesc {
+            source: pad_desc(link.source_id)?,
+            sink: pad_desc(link.sink_id)?,
+            flags,
+            reserved: [0; 2],
+        })
+    }
+}
+
+/// A hardware or software block in a media device graph.
+#[derive(Debug, Clone)]
+pub struct Entity {
+    id: u32,
+    name: String,
+    function: EntityFunction,
+    flags: EntityFlags,
+}
+
+impl Entity {
+    fn from_raw(raw: &raw::V2Entity) -> Self {
+        Self {
+            id: raw.id,
+            name: byte_array_to_str(&{ raw.name }).to_string(),
+            function: EntityFunction(raw.function),
+            flags: EntityFlags::from_bits_truncate(raw.flags),
+        }
+    }
+
+    #[inline]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns the name of the entity, which is unique within the media device.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the main function of the entity.
+    #[inline]
+    pub fn function(&self) -> EntityFunction {
+        self.function
+    }
+
+    #[inline]
+    pub fn flags(&self) -> EntityFlags {
+        self.flags
+    }
+}
+
+/// An interface through which userspace controls entities of a media device.
+///
+/// All interfaces currently defined by the kernel are device nodes.
+#[derive(Debug, Clone)]
+pub struct Interface {
+    id: u32,
+    interface_type: InterfaceType,
+    devnode: DevNode,
+}
+
+impl Interface {
+    fn from_raw(raw: &raw::V2Interface) -> Self {
+        let devnode = unsafe { raw.u.devnode };
+        Self {
+            id: raw.id,
+            interface_type: InterfaceType(raw.intf_type),
+            devnode: DevNode {
+                major: devnode.major,
+                minor: devnode.minor,
+            },
+        }
+    }
+
+    #[inline]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    #[inline]
+    pub fn interface_type(&self) -> InterfaceType {
+        self.interface_type
+    }
+
+    /// Returns the device number of the interface's device node.
+    #[inline]
+    pub fn devnode(&self) -> DevNode {
+        self.devnode
+    }
+}
+
+/// The device number of a character device node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DevNode {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl DevNode {
+    /// Returns the device number of the device node that `file` refers to.
+    pub fn of(file: &impl AsRawFd) -> io::Result<Self> {
+        let mut stat: libc::stat = unsafe { mem::zeroed() };
+        if unsafe { libc::fstat(file.as_raw_fd(), &mut stat) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        if stat.st_mode & libc::S_IFMT != libc::S_IFCHR {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "file is not a character device",
+            ));
+        }
+        Ok(Self {
+            major: libc::major(stat.st_rdev),
+            minor: libc::minor(stat.st_rdev),
+        })
+    }
+
+    /// Returns the path of the device node in `/dev`, like `/dev/video0`.
+    ///
+    /// This is the same path that [`list`][crate::list] opens the device at. The name is looked up
+    /// in sysfs, which has to be mounted at `/sys`.
+    pub fn path(&self) -> io::Result<PathBuf> {
+        let uevent = fs::read_to_string(self.sysfs_path().join("uevent"))?;
+        match uevent
+            .lines()
+            .find_map(|line| line.strip_prefix("DEVNAME="))
+        {
+            Some(name) => Ok(Path::new("/dev").join(name)),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "no device name found for device {}:{}",
+                    self.major, self.minor
+                ),
+            )),
+        }
+    }
+
+    /// Returns the sysfs directory of the device (`/sys/dev/char/<major>:<minor>`).
+    fn sysfs_path(&self) -> PathBuf {
+        PathBuf::from(format!("/sys/dev/char/{}:{}", self.major, self.minor))
+    }
+}
+
+/// A connection point of an [`Entity`], which can be linked to pads of other entities.
+#[derive(Debug, Clone)]
+pub struct Pad {
+    id: u32,
+    entity_id: u32,
+    index: u32,
+    flags: PadFlags,
+}
+
+impl Pad {
+    fn from_raw(raw: &raw::V2Pad) -> Self {
+        Self {
+            id: raw.id,
+            entity_id: raw.entity_id,
+            index: raw.index,
+            flags: PadFlags::from_bits_truncate(raw.flags),
+        }
+    }
+
+    #[inline]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns the ID of the entity the pad belongs to.
+    #[inline]
+    pub fn entity_id(&self) -> u32 {
+        self.entity_id
+    }
+
+    /// Returns the index of the pad within its entity.
+    ///
+    /// This is the pad number used by the V4L2 sub-device API.
+    #[inline]
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    #[inline]
+    pub fn flags(&self) -> PadFlags {
+        self.flags
+    }
+
+    /// Returns whether data flows into the entity through this pad.
+    #[inline]
+    pub fn is_sink(&self) -> bool {
+        self.flags.contains(PadFlags::SINK)
+    }
+
+    /// Returns whether data flows out of the entity through this pad.
+    #[inline]
+    pub fn is_source(&self) -> bool {
+        self.flags.contains(PadFlags::SOURCE)
+    }
+}
+
+/// A connection in a media device graph.
+///
+/// For [`LinkType::DATA`] links, the source and sink are [`Pad`]s. For [`LinkType::INTERFACE`]
+/// links, the source is an [`Interface`] and the sink is the [`Entity`] it controls. For
+/// [`LinkType::ANCILLARY`] links, both are entities.
+#[derive(Debug, Clone)]
+pub struct Link {
+    id: u32,
+    source_id: u32,
+    sink_id: u32,
+    flags: u32,
+}
+
+impl Link {
+    fn from_raw(raw: &raw::V2Link) -> Self {
+        Self {
+            id: raw.id,
+            source_id: raw.source_id,
+            sink_id: raw.sink_id,
+            flags: raw.flags,
+        }
+    }
+
+    #[inline]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns the ID of the graph object the link starts at.
+    #[inline]
+    pub fn source_id(&self) -> u32 {
+        self.source_id
+    }
+
+    /// Returns the ID of the graph object the link ends at.
+    #[inline]
+    pub fn sink_id(&self) -> u32 {
+        self.sink_id
+    }
+
+    #[inline]
+    pub fn link_type(&self) -> LinkType {
+        LinkType((self.flags & LINK_TYPE_MASK) >> 28)
+    }
+
+    #[inline]
+    pub fn flags(&self) -> LinkFlags {
+        LinkFlags::from_bits_truncate(self.flags)
+    }
+
+    /// Returns whether data can flow through the link.
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.flags().contains(LinkFlags::ENABLED)
+    }
+
+    /// Returns whether the link is always enabled, and cannot be changed with
+    /// [`MediaDevice::setup_link`].
+    #[inline]
+    pub fn is_immutable(&self) -> bool {
+        self.flags().contains(LinkFlags::IMMUTABLE)
+    }
+}
+
+const LINK_TYPE_MASK: u32 = 0xf << 28;
+
+ffi_enum! {
+    /// The main function of an [`Entity`].
+    pub enum EntityFunction: u32 {
+        UNKNOWN                  = 0x00000000,
+        V4L2_SUBDEV_UNKNOWN      = 0x00020000,
+        DTV_DEMOD                = 0x00000001,
+        TS_DEMUX                 = 0x00000002,
+        DTV_CA                   = 0x00000003,
+        DTV_NET_DECAP            = 0x00000004,
+        /// A V4L2 video device node, which DMAs frames to or from memory.
+        IO_V4L                   = 0x00010001,
+        IO_DTV                   = 0x00001001,
+        IO_VBI                   = 0x00001002,
+        IO_SWRADIO               = 0x00001003,
+        CAM_SENSOR               = 0x00020001,
+        FLASH                    = 0x00020002,
+        LENS                     = 0x00020003,
+        ATV_DECODER              = 0x00020004,
+        TUNER                    = 0x00020005,
+        IF_VID_DECODER           = 0x00002001,
+        IF_AUD_DECODER           = 0x00002002,
+        AUDIO_CAPTURE            = 0x00003001,
+        AUDIO_PLAYBACK           = 0x00003002,
+        AUDIO_MIXER              = 0x00003003,
+        PROC_VIDEO_COMPOSER      = 0x00004001,
+        PROC_VIDEO_PIXEL_FORMATTER = 0x00004002,
+        PROC_VIDEO_PIXEL_ENC_CONV = 0x00004003,
+        PROC_VIDEO_LUT           = 0x00004004,
+        PROC_VIDEO_SCALER        = 0x00004005,
+        PROC_VIDEO_STATISTICS    = 0x00004006,
+        PROC_VIDEO_ENCODER       = 0x00004007,
+        PROC_VIDEO_DECODER       = 0x00004008,
+        PROC_VIDEO_ISP           = 0x00004009,
+        VID_MUX                  = 0x00005001,
+        VID_IF_BRIDGE            = 0x00005002,
+        DV_DECODER               = 0x00006001,
+        DV_ENCODER               = 0x00006002,
+    }
+}
+
+ffi_enum! {
+    /// The type of an [`Interface`].
+    pub enum InterfaceType: u32 {
+        DVB_FE             = 0x00000100,
+        DVB_DEMUX          = 0x00000101,
+        DVB_DVR            = 0x00000102,
+        DVB_CA             = 0x00000103,
+        DVB_NET            = 0x00000104,
+        /// A V4L2 video device node (`/dev/video*`).
+        V4L_VIDEO          = 0x00000200,
+        /// A V4L2 VBI device node (`/dev/vbi*`).
+        V4L_VBI            = 0x00000201,
+        /// A V4L2 radio device node (`/dev/radio*`).
+        V4L_RADIO          = 0x00000202,
+        /// A V4L2 sub-device node (`/dev/v4l-subdev*`).
+        V4L_SUBDEV         = 0x00000203,
+        /// A V4L2 software-defined radio device node (`/dev/swradio*`).
+        V4L_SWRADIO        = 0x00000204,
+        /// A V4L2 touch device node (`/dev/v4l-touch*`).
+        V4L_TOUCH          = 0x00000205,
+        ALSA_PCM_CAPTURE   = 0x00000300,
+        ALSA_PCM_PLAYBACK  = 0x00000301,
+        ALSA_CONTROL       = 0x00000302,
+        ALSA_COMPRESS      = 0x00000303,
+        ALSA_RAWMIDI       = 0x00000304,
+        ALSA_HWDEP         = 0x00000305,
+        ALSA_SEQUENCER     = 0x00000306,
+        ALSA_TIMER         = 0x00000307,
+    }
+}
+
+ffi_enum! {
+    /// The type of a [`Link`].
+    pub enum LinkType: u32 {
+        /// A link between two pads, through which data flows.
+        DATA      = 0,
+        /// A link between an interface and the entity it controls.
+        INTERFACE = 1,
+        /// A link between two entities that belong together, like a sensor and its lens.
+        ANCILLARY = 2,
+    }
+}
+
+bitflags! {
+    #[repr(transparent)]
+    pub struct EntityFlags: u32 {
+        /// The entity is the default one of its function.
+        const DEFAULT   = 1 << 0;
+        /// The entity represents a physical connector.
+        const CONNECTOR = 1 << 1;
+    }
+}
+
+bitflags! {
+    #[repr(transparent)]
+    pub struct PadFlags: u32 {
+        const SINK         = 1 << 0;
+        const SOURCE       = 1 << 1;
+        /// The pad has to be connected via an enabled link for the entity to be usable.
+        const MUST_CONNECT = 1 << 2;
+    }
+}
+
+bitflags! {
+    #[repr(transparent)]
+    pub struct LinkFlags: u32 {
+        const ENABLED   = 1 << 0;
+        /// The link is always enabled, and cannot be disabled.
+        const IMMUTABLE = 1 << 1;
+        /// The link can be changed while streaming.
+        const DYNAMIC   = 1 << 2;
+    }
+}
+
+/// A media request, allocated with [`MediaDevice::alloc_request`].
+///
+/// A request collects buffers and control values. Once it is queued with [`Request::queue`], the
+/// driver processes them together, applying the control values to the frame in the request's
+/// buffer. A request can only be queued once; use [`Request::reinit`] to reuse it afterwards.
+#[derive(Debug)]
+pub struct Request {
+    fd: OwnedFd,
+}
+
+impl Request {
+    /// Stores `controls` in the request, to be applied by the `device` when the request is
+    /// processed.
+    ///
+    /// `device` can be anything that refers to the V4L2 device, such as a
+    /// [`Device`][crate::Device] or [`WriteStream`][crate::stream::WriteStream]. Controls cannot
+    /// be added after the request has been queued.
+    pub fn write_controls(
+        &self,
+        device: &impl AsRawFd,
+        controls: &mut ExtControls,
+    ) -> io::Result<()> {
+        controls.write(
+            device.as_raw_fd(),
+            crate::raw::controls::CTRL_WHICH_REQUEST_VAL,
+            self.fd.as_raw_fd(),
+        )
+    }
+
+    /// Queues the request, handing its buffers and controls to the driver.
+    ///
+    /// This fails if the request doesn't contain any buffers.
+    pub fn queue(&self) -> io::Result<()> {
+        unsafe {
+            raw::MEDIA_REQUEST_IOC_QUEUE.ioctl(&self.fd)?;
+        }
+        Ok(())
+    }
+
+    /// Resets the request to its empty state, so that it can be reused.
+    ///
+    /// This fails if the request is queued and hasn't completed yet.
+    pub fn reinit(&self) -> io::Result<()> {
+        unsafe {
+            raw::MEDIA_REQUEST_IOC_REINIT.ioctl(&self.fd)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the driver has finished processing the request.
+    ///
+    /// Requests that haven't been queued are not complete.
+    pub fn is_complete(&self) -> io::Result<bool> {
+        Ok(self.poll_revents(Some(Instant::now()))? & libc::POLLPRI != 0)
+    }
+
+    /// Blocks until the driver has finished processing the request.
+    ///
+    /// Returns an error if the request hasn't been queued.
+    pub fn wait(&self) -> io::Result<()> {
+        self.poll(None)?;
+        Ok(())
+    }
+
+    /// Like [`Request::wait`], but waits at most `timeout` for the request to complete.
+    ///
+    /// If it doesn't complete in time, an error of kind [`io::ErrorKind::TimedOut`] is returned.
+    pub fn wait_timeout(&self, timeout: Duration) -> io::Result<()> {
+        // Timeouts too large to be represented wait indefinitely.
+        if !self.poll(Instant::now().checked_add(timeout))? {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        Ok(())
+    }
+
+    /// Waits until the request completes or `deadline` passes, returning whether it completed.
+    fn poll(&self, deadline: Option<Instant>) -> io::Result<bool> {
+        match self.poll_revents(deadline)? {
+            0 => Ok(false),
+            revents if revents & libc::POLLPRI == 0 => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "request has not been queued",
+            )),
+            _ => Ok(true),
+        }
+    }
+
+    /// Polls the request until `deadline`, returning the events that occurred (or 0 on timeout).
+    ///
+    /// Completed requests signal `POLLPRI`, requests that aren't queued signal `POLLERR`.
+    fn poll_revents(&self, deadline: Option<Instant>) -> io::Result<i16> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLPRI,
+            revents: 0,
+        };
+        if !poll_until(slice::from_mut(&mut pollfd), deadline)? {
+            return Ok(0);
+        }
+        Ok(pollfd.revents)
+    }
+}
+
+impl AsRawFd for Request {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl AsFd for Request {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unqueued_request_is_not_complete() {
+        let Some(request) = list()
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .find_map(|media| media.alloc_request().ok())
+        else {
+            eprintln!("no media device supporting requests found, skipping test");
+            return;
+        };
+        assert!(!request.is_complete().unwrap());
+        let err = request.wait().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn raw_struct_sizes() {
+        assert_eq!(mem::size_of::<raw::DeviceInfo>(), 256);
+        assert_eq!(mem::size_of::<raw::V2Entity>(), 96);
+        assert_eq!(mem::size_of::<raw::V2Interface>(), 112);
+        assert_eq!(mem::size_of::<raw::V2Pad>(), 32);
+        assert_eq!(mem::size_of::<raw::V2Link>(), 40);
+        assert_eq!(mem::size_of::<raw::V2Topology>(), 72);
+        assert_eq!(mem::size_of::<raw::PadDesc>(), 20);
+        assert_eq!(mem::size_of::<raw::LinkDesc>(), 52);
+    }
+
+    /// Builds `sensor:0 -> capture:0`, with `capture` exposed as video device 81:3, and an
+    /// immutable link `sensor:0 -> capture:1`.
+    fn test_topology() -> Topology {
+        let entity = |id, name: &str, function| Entity {
+            id,
+            name: name.to_string(),
+            function,
+            flags: EntityFlags::empty(),
+        };
+        let pad = |id, entity_id, flags| Pad {
+            id,
+            entity_id,
+            index: 0,
+            flags,
+        };
+        let devnode = DevNode {
+            major: 81,
+            minor: 3,
+        };
+        Topology {
+            version: 1,
+            entities: vec![
+                entity(1, "sensor", EntityFunction::CAM_SENSOR),
+                entity(2, "capture", EntityFunction::IO_V4L),
+            ],
+            interfaces: vec![Interface {
+                id: 3,
+                interface_type: InterfaceType::V4L_VIDEO,
+                devnode,
+            }],
+            pads: vec![
+                pad(4, 1, PadFlags::SOURCE),
+                pad(5, 2, PadFlags::SINK),
+                Pad {
+                    index: 1,
+                    ..pad(8, 2, PadFlags::SINK)
+                },
+            ],
+            links: vec![
+                Link {
+                    id: 6,
+                    source_id: 4,
+                    sink_id: 5,
+                    flags: LinkFlags::ENABLED.bits(),
+                },
+                Link {
+                    id: 7,
+                    source_id: 3,
+                    sink_id: 2,
+                    flags: LinkFlags::ENABLED.bits() | LinkType::INTERFACE.0 << 28,
+                },
+                Link {
+                    id: 9,
+                    source_id: 4,
+                    sink_id: 8,
+                    flags: (LinkFlags::ENABLED | LinkFlags::IMMUTABLE).bits(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn topology_lookups() {
+        let topology = test_topology();
+        let devnode = topology.interfaces()[0].devnode();
+
+        assert_eq!(topology.entity_by_name("capture").unwrap().id(), 2);
+        assert_eq!(topology.devnode_entity(devnode).unwrap().name(), "capture");
+        assert_eq!(topology.entity_interface(2).unwrap().devnode(), devnode);
+        assert!(topology.entity_interface(1).is_none());
+        assert_eq!(topology.entity_pads(2).count(), 2);
+        assert_eq!(topology.entity_pad(2, 1).unwrap().id(), 8);
+        let links = topology.entity_links(1).collect::<Vec<_>>();
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].link_type(), LinkType::DATA);
+        assert!(links[0].is_enabled());
+        assert_eq!(topology.find_link(4, 5).unwrap().id(), 6);
+        assert!(topology.find_link(5, 4).is_none());
+        assert_eq!(topology.link(7).unwrap().link_type(), LinkType::INTERFACE);
+        assert_eq!(topology.link(7).unwrap().flags(), LinkFlags::ENABLED);
+    }
+    #[test]
+    fn link_desc() {
+        let topology = test_topology();
+
+        let desc = topology.link_desc(6, false).unwrap();
+        assert_eq!((desc.source.entity, desc.source.index), (1, 0));
+        assert_eq!((desc.sink.entity, desc.sink.index), (2, 0));
+        assert_eq!(desc.flags, 0);
+        let desc = topology.link_desc(6, true).unwrap();
+        assert_eq!(desc.flags, LinkFlags::ENABLED.bits());
+
+        let err = |id| topology.link_desc(id, false).err().unwrap().kind();
+        assert_eq!(err(1), io::ErrorKind::NotFound);
+        assert_eq!(err(7), io::ErrorKind::InvalidInput);
+        assert_eq!(err(9), io::ErrorKind::InvalidInput);
+    }
+}
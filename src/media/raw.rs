@@ -0,0 +1,117 @@
+//! From `linux/media.h`.
+
+use std::ffi::c_int;
+
+use uoctl::{Ioctl, NoArgs, _IO, _IOR, _IOWR};
+
+#[repr(C)]
+pub struct DeviceInfo {
+    pub driver: [u8; 16],
+    pub model: [u8; 32],
+    pub serial: [u8; 40],
+    pub bus_info: [u8; 32],
+    pub media_version: u32,
+    pub hw_revision: u32,
+    pub driver_version: u32,
+    pub reserved: [u32; 31],
+}
+
+#[repr(C)]
+pub struct PadDesc {
+    pub entity: u32,
+    pub index: u16,
+    pub flags: u32,
+    pub reserved: [u32; 2],
+}
+
+#[repr(C)]
+pub struct LinkDesc {
+    pub source: PadDesc,
+    pub sink: PadDesc,
+    pub flags: u32,
+    pub reserved: [u32; 2],
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct V2Entity {
+    pub id: u32,
+    pub name: [u8; 64],
+    pub function: u32,
+    pub flags: u32,
+    pub reserved: [u32; 5],
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct V2IntfDevnode {
+    pub major: u32,
+    pub minor: u32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct V2Interface {
+    pub id: u32,
+    pub intf_type: u32,
+    pub flags: u32,
+    pub reserved: [u32; 9],
+    pub u: V2InterfaceUnion,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub union V2InterfaceUnion {
+    pub devnode: V2IntfDevnode,
+    pub raw: [u32; 16],
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct V2Pad {
+    pub id: u32,
+    pub entity_id: u32,
+    pub flags: u32,
+    pub index: u32,
+    pub reserved: [u32; 4],
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct V2Link {
+    pub id: u32,
+    pub source_id: u32,
+    pub sink_id: u32,
+    pub flags: u32,
+    pub reserved: [u32; 6],
+}
+
+#[repr(C, packed)]
+pub struct V2Topology {
+    pub topology_version: u64,
+
+    pub num_entities: u32,
+    pub reserved1: u32,
+    pub ptr_entities: u64,
+
+    pub num_interfaces: u32,
+    pub reserved2: u32,
+    pub ptr_interfaces: u64,
+
+    pub num_pads: u32,
+    pub reserved3: u32,
+    pub ptr_pads: u64,
+
+    pub num_links: u32,
+    pub reserved4: u32,
+    pub ptr_links: u64,
+}
+
+pub const MEDIA_IOC_DEVICE_INFO: Ioctl<*mut DeviceInfo> = _IOWR(b'|', 0x00);
+// ...
+pub const MEDIA_IOC_SETUP_LINK: Ioctl<*mut LinkDesc> = _IOWR(b'|', 0x03);
+pub const MEDIA_IOC_G_TOPOLOGY: Ioctl<*mut V2Topology> = _IOWR(b'|', 0x04);
+pub const MEDIA_IOC_REQUEST_ALLOC: Ioctl<*mut c_int> = _IOR(b'|', 0x05);
+
+pub const MEDIA_REQUEST_IOC_QUEUE: Ioctl<NoArgs> = _IO(b'|', 0x80);
+pub const MEDIA_REQUEST_IOC_REINIT: Ioctl<NoArgs> = _IO(b'|', 0x81);
@@ -102,6 +102,27 @@ impl PixelFormat {
     /// Images can be decoded with any off-the-shelf JPEG decoder, no preprocessing is needed.
     pub const JPEG: Self = f(b"JPEG");
 
+    /// **`FWHT`**: Fast Walsh Hadamard Transform codec, as used by the `vicodec` test driver.
+    pub const FWHT: Self = f(b"FWHT");
+
+    /// **`SFWH`**: FWHT frames for stateless decoders, which need the frame parameters to be
+    /// passed via the [`FwhtParams`][crate::codec::stateless::FwhtParams] control.
+    pub const FWHT_STATELESS: Self = f(b"SFWH");
+
+    /// **`S264`**: H.264 slices for stateless decoders, without SPS or PPS NAL units.
+    ///
+    /// The parameter sets and slice headers are passed via the controls in
+    /// [`codec::stateless`][crate::codec::stateless] instead.
+    pub const H264_SLICE: Self = f(b"S264");
+
+    /// **`VP8F`**: VP8 frames for stateless decoders, with the frame header parsed into the
+    /// [`Vp8Frame`][crate::codec::stateless::Vp8Frame] control.
+    pub const VP8_FRAME: Self = f(b"VP8F");
+
+    /// **`MG2S`**: MPEG-2 slices for stateless decoders, with the sequence and picture headers
+    /// parsed into the [`codec::stateless`][crate::codec::stateless] controls.
+    pub const MPEG2_SLICE: Self = f(b"MG2S");
+
     /// **`UVCH`**: UVC payload header metadata.
     ///
     /// Data is a stream of [`UvcMetadata`][crate::uvc::UvcMetadata] structures.
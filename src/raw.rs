@@ -220,9 +220,35 @@ pub struct RequestBuffers {
     pub type_: BufType,
     pub memory: Memory,
     pub capabilities: BufCap,
-    pub reserved: [u32; 1],
+    pub flags: u8,
+    pub reserved: [u8; 3],
+}
+
+/// `V4L2_MEMORY_FLAG_NON_COHERENT`: buffers are allocated in non-coherent memory.
+pub const MEMORY_FLAG_NON_COHERENT: u8 = 1 << 0;
+
+#[repr(C)]
+pub struct CreateBuffers {
+    pub index: u32,
+    pub count: u32,
+    pub memory: Memory,
+    pub format: Format,
+    pub capabilities: BufCap,
+    pub flags: u32,
+    pub max_num_buffers: u32,
+    pub reserved: [u32; 5],
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct RemoveBuffers {
+    pub index: u32,
+    pub count: u32,
+    pub type_: BufType,
+    pub reserved: [u32; 13],
 }
 
+#[derive(Clone, Copy)]
 #[repr(C)]
 pub struct Timecode {
     pub type_: TimecodeType,
@@ -240,7 +266,7 @@ pub struct Buffer {
     pub type_: BufType,
     pub bytesused: u32,
     pub flags: BufFlag,
-    pub field: u32,
+    pub field: Field,
     pub timestamp: timeval,
     pub timecode: Timecode,
     pub sequence: u32,
@@ -281,6 +307,16 @@ pub union PlaneMemoryUnion {
     pub fd: i32,
 }
 
+#[repr(C)]
+pub struct ExportBuffer {
+    pub type_: BufType,
+    pub index: u32,
+    pub plane: u32,
+    pub flags: u32,
+    pub fd: i32,
+    pub reserved: [u32; 11],
+}
+
 #[repr(C)]
 pub struct FrmSizeEnum {
     pub index: u32,
@@ -374,6 +410,98 @@ pub struct OutputParm {
     pub reserved: [u32; 4],
 }
 
+#[repr(C)]
+pub struct EventSubscription {
+    pub type_: EventType,
+    pub id: u32,
+    pub flags: EventSubscriptionFlags,
+    pub reserved: [u32; 5],
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Event {
+    pub type_: EventType,
+    pub u: EventUnion,
+    pub pending: u32,
+    pub sequence: u32,
+    pub timestamp: libc::timespec,
+    pub id: u32,
+    pub reserved: [u32; 8],
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub union EventUnion {
+    pub vsync: EventVsync,
+    pub ctrl: EventCtrl,
+    pub frame_sync: EventFrameSync,
+    pub src_change: EventSrcChange,
+    pub motion_det: EventMotionDet,
+    pub data: [u8; 64],
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct EventVsync {
+    /// A `Field`, truncated to `u8`.
+    pub field: u8,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct EventCtrl {
+    pub changes: CtrlChanges,
+    pub type_: CtrlType,
+    pub value: EventCtrlValue,
+    pub flags: ControlFlags,
+    pub minimum: i32,
+    pub maximum: i32,
+    pub step: i32,
+    pub default_value: i32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub union EventCtrlValue {
+    pub value: i32,
+    pub value64: i64,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct EventFrameSync {
+    pub frame_sequence: u32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct EventSrcChange {
+    pub changes: SourceChanges,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct EventMotionDet {
+    pub flags: MotionDetFlags,
+    pub frame_sequence: u32,
+    pub region_mask: u32,
+}
+
+#[repr(C)]
+pub struct EncoderCmd {
+    pub cmd: EncCmd,
+    pub flags: EncCmdFlags,
+    pub raw: [u32; 8],
+}
+
+#[repr(C)]
+pub struct DecoderCmd {
+    pub cmd: DecCmd,
+    pub flags: DecCmdFlags,
+    pub raw: [u32; 16],
+}
+
 pub const VIDIOC_QUERYCAP: Ioctl<*mut Capabilities> = _IOR(b'V', 0);
 pub const VIDIOC_ENUM_FMT: Ioctl<*mut FmtDesc> = _IOWR(b'V', 2);
 pub const VIDIOC_G_FMT: Ioctl<*mut Format> = _IOWR(b'V', 4);
@@ -382,7 +510,7 @@ pub const VIDIOC_REQBUFS: Ioctl<*mut RequestBuffers> = _IOWR(b'V', 8);
 pub const VIDIOC_QUERYBUF: Ioctl<*mut Buffer> = _IOWR(b'V', 9);
 // ...
 pub const VIDIOC_QBUF: Ioctl<*mut Buffer> = _IOWR(b'V', 15);
-// ...
+pub const VIDIOC_EXPBUF: Ioctl<*mut ExportBuffer> = _IOWR(b'V', 16);
 pub const VIDIOC_DQBUF: Ioctl<*mut Buffer> = _IOWR(b'V', 17);
 pub const VIDIOC_STREAMON: Ioctl<*const c_int> = _IOW(b'V', 18);
 pub const VIDIOC_STREAMOFF: Ioctl<*const c_int> = _IOW(b'V', 19);
@@ -398,5 +526,33 @@ pub const VIDIOC_QUERYMENU: Ioctl<*mut QueryMenu> = _IOWR(b'V', 37);
 // ...
 pub const VIDIOC_ENUMOUTPUT: Ioctl<*mut Output> = _IOWR(b'V', 48);
 // ...
+pub const VIDIOC_S_EXT_CTRLS: Ioctl<*mut controls::ExtControls> = _IOWR(b'V', 72);
+// ...
 pub const VIDIOC_ENUM_FRAMESIZES: Ioctl<*mut FrmSizeEnum> = _IOWR(b'V', 74);
 pub const VIDIOC_ENUM_FRAMEINTERVALS: Ioctl<*mut FrmIvalEnum> = _IOWR(b'V', 75);
+// ...
+pub const VIDIOC_ENCODER_CMD: Ioctl<*mut EncoderCmd> = _IOWR(b'V', 77);
+// ...
+pub const VIDIOC_DQEVENT: Ioctl<*mut Event> = _IOR(b'V', 89);
+pub const VIDIOC_SUBSCRIBE_EVENT: Ioctl<*const EventSubscription> = _IOW(b'V', 90);
+pub const VIDIOC_UNSUBSCRIBE_EVENT: Ioctl<*const EventSubscription> = _IOW(b'V', 91);
+pub const VIDIOC_CREATE_BUFS: Ioctl<*mut CreateBuffers> = _IOWR(b'V', 92);
+pub const VIDIOC_PREPARE_BUF: Ioctl<*mut Buffer> = _IOWR(b'V', 93);
+// ...
+pub const VIDIOC_DECODER_CMD: Ioctl<*mut DecoderCmd> = _IOWR(b'V', 96);
+// ...
+pub const VIDIOC_REMOVE_BUFS: Ioctl<*mut RemoveBuffers> = _IOWR(b'V', 104);
+
+// `dma-buf.h`
+
+#[repr(C)]
+pub struct DmaBufSync {
+    pub flags: u64,
+}
+
+pub const DMA_BUF_SYNC_READ: u64 = 0x1;
+pub const DMA_BUF_SYNC_WRITE: u64 = 0x2;
+pub const DMA_BUF_SYNC_START: u64 = 0x0;
+pub const DMA_BUF_SYNC_END: u64 = 0x4;
+
+pub const DMA_BUF_IOCTL_SYNC: Ioctl<*const DmaBufSync> = _IOW(b'b', 0);
@@ -1,3 +1,5 @@
+use std::ffi::c_void;
+
 ffi_enum! {
     pub enum CtrlClass: u32 {
         USER            = 0x00980000,
@@ -22,7 +24,7 @@ ffi_enum! {
     /// This type has associated constants to refer to standard controls with predefined meanings,
     /// but drivers can add their own driver-specific controls as well.
     pub enum Cid: u32 {
-        BRIGHTNESS                  = Self::BASE.0 + 0, // comes first so it shows up in debug output
+        BRIGHTNESS                  = Self::BASE.0, // comes first so it shows up in debug output
         BASE                        = CtrlClass::USER.0 | 0x900,
 
         /// User-class control base ID.
@@ -124,41 +126,75 @@ ffi_enum! {
 
         CAMERA_ORIENTATION          = Self::CAMERA_CLASS_BASE.0 + 34,
         CAMERA_SENSOR_ROTATION      = Self::CAMERA_CLASS_BASE.0 + 35,
+
+        /// Codec-class control base ID.
+        CODEC_CLASS_BASE            = CtrlClass::CODEC.0 | 0x900,
+        CODEC_CLASS                 = CtrlClass::CODEC.0 | 1,
+        MPEG_VIDEO_B_FRAMES         = Self::CODEC_CLASS_BASE.0 + 202,
+        MPEG_VIDEO_GOP_SIZE         = Self::CODEC_CLASS_BASE.0 + 203,
+        MPEG_VIDEO_BITRATE_MODE     = Self::CODEC_CLASS_BASE.0 + 206,
+        MPEG_VIDEO_BITRATE          = Self::CODEC_CLASS_BASE.0 + 207,
+        MPEG_VIDEO_BITRATE_PEAK     = Self::CODEC_CLASS_BASE.0 + 208,
+        MPEG_VIDEO_FRAME_RC_ENABLE  = Self::CODEC_CLASS_BASE.0 + 215,
+        MPEG_VIDEO_HEADER_MODE      = Self::CODEC_CLASS_BASE.0 + 216,
+        /// Button control that makes an encoder encode the next frame as a keyframe.
+        MPEG_VIDEO_FORCE_KEY_FRAME  = Self::CODEC_CLASS_BASE.0 + 229,
+        FWHT_I_FRAME_QP             = Self::CODEC_CLASS_BASE.0 + 290,
+        FWHT_P_FRAME_QP             = Self::CODEC_CLASS_BASE.0 + 291,
+
+        /// Stateless codec control base ID.
+        CODEC_STATELESS_BASE        = CtrlClass::CODEC_STATELESS.0 | 0x900,
+        CODEC_STATELESS_CLASS       = CtrlClass::CODEC_STATELESS.0 | 1,
+        STATELESS_H264_DECODE_MODE  = Self::CODEC_STATELESS_BASE.0,
+        STATELESS_H264_START_CODE   = Self::CODEC_STATELESS_BASE.0 + 1,
+        STATELESS_H264_SPS          = Self::CODEC_STATELESS_BASE.0 + 2,
+        STATELESS_H264_PPS          = Self::CODEC_STATELESS_BASE.0 + 3,
+        STATELESS_H264_SCALING_MATRIX = Self::CODEC_STATELESS_BASE.0 + 4,
+        STATELESS_H264_PRED_WEIGHTS = Self::CODEC_STATELESS_BASE.0 + 5,
+        STATELESS_H264_SLICE_PARAMS = Self::CODEC_STATELESS_BASE.0 + 6,
+        STATELESS_H264_DECODE_PARAMS = Self::CODEC_STATELESS_BASE.0 + 7,
+        STATELESS_FWHT_PARAMS       = Self::CODEC_STATELESS_BASE.0 + 100,
+        STATELESS_VP8_FRAME         = Self::CODEC_STATELESS_BASE.0 + 200,
+        STATELESS_MPEG2_SEQUENCE    = Self::CODEC_STATELESS_BASE.0 + 220,
+        STATELESS_MPEG2_PICTURE     = Self::CODEC_STATELESS_BASE.0 + 221,
+        STATELESS_MPEG2_QUANTISATION = Self::CODEC_STATELESS_BASE.0 + 222,
     }
 }
 
-ffi_enum! {
-    pub enum PowerLineFrequency: u32 {
-        DISABLED  = 0,
-        FREQ_50HZ = 1,
-        FREQ_60HZ = 2,
-        AUTO      = 3,
-    }
+#[repr(C)]
+pub struct Control {
+    pub id: Cid,
+    pub value: i32,
 }
 
-ffi_enum! {
-    pub enum ColorFx: u32 {
-        NONE         = 0,
-        BW           = 1,
-        SEPIA        = 2,
-        NEGATIVE     = 3,
-        EMBOSS       = 4,
-        SKETCH       = 5,
-        SKY_BLUE     = 6,
-        GRASS_GREEN  = 7,
-        SKIN_WHITEN  = 8,
-        VIVID        = 9,
-        AQUA         = 10,
-        ART_FREEZE   = 11,
-        SILHOUETTE   = 12,
-        SOLARIZATION = 13,
-        ANTIQUE      = 14,
-        SET_CBCR     = 15,
-    }
+/// `which` value of [`ExtControls`] that accesses the current control values.
+pub const CTRL_WHICH_CUR_VAL: u32 = 0;
+/// `which` value of [`ExtControls`] that accesses the control values stored in a request.
+pub const CTRL_WHICH_REQUEST_VAL: u32 = 0x0f010000;
+
+#[repr(C, packed)]
+pub struct ExtControl {
+    pub id: Cid,
+    pub size: u32,
+    pub reserved2: [u32; 1],
+    pub value: ExtControlValue,
 }
 
+#[derive(Clone, Copy)]
 #[repr(C)]
-pub struct Control {
-    pub id: Cid,
+pub union ExtControlValue {
     pub value: i32,
+    pub value64: i64,
+    pub ptr: *mut c_void,
+}
+
+#[repr(C)]
+pub struct ExtControls {
+    /// Also `ctrl_class`.
+    pub which: u32,
+    pub count: u32,
+    pub error_idx: u32,
+    pub request_fd: i32,
+    pub reserved: [u32; 1],
+    pub controls: *mut ExtControl,
 }
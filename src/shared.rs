@@ -1,6 +1,7 @@
 //! FFI-compatible types that may also be exposed to Rust code.
 
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
 // This macro enforces that all `bitflags!` types in here are marked
 // `#[repr(transparent)]` and thus FFI-safe.
@@ -13,16 +14,6 @@ macro_rules! bitflags {
     };
 }
 
-ffi_enum! {
-    pub enum TunerType: u32 {
-        RADIO      = 1,
-        ANALOG_TV  = 2,
-        DIGITAL_TV = 3,
-        SDR        = 4,
-        RF         = 5,
-    }
-}
-
 ffi_enum! {
     /// Describes what kind of device an [`Output`][crate::Output] is.
     pub enum OutputType: u32 {
@@ -87,6 +78,7 @@ ffi_enum! {
 }
 
 ffi_enum! {
+    /// Field order of interlaced video, or [`Field::NONE`] for progressive video.
     pub enum Field: u32 {
         /// Lets the driver choose.
         ANY           = 0,
@@ -140,17 +132,20 @@ ffi_enum! {
 }
 
 ffi_enum! {
+    /// Memory type of the buffers of a stream.
     pub enum Memory: u32 {
         /// Buffers are allocated by the driver and `mmap`ped into userspace.
         MMAP    = 1,
         /// Buffers are allocated by userspace and a pointer is passed to the driver.
         USERPTR = 2,
         OVERLAY = 3,
+        /// Buffers are dma-bufs, shared with another device or subsystem.
         DMABUF  = 4,
     }
 }
 
 ffi_enum! {
+    /// Frame rate a [`Timecode`][crate::stream::Timecode] is based on.
     pub enum TimecodeType: u32 {
         T_24FPS = 1,
         T_25FPS = 2,
@@ -401,6 +396,7 @@ bitflags! {
 }
 
 bitflags! {
+    /// Capabilities of a buffer queue.
     pub struct BufCap: u32 {
         const SUPPORTS_MMAP                 = 1 << 0;
         const SUPPORTS_USERPTR              = 1 << 1;
@@ -409,10 +405,13 @@ bitflags! {
         const SUPPORTS_ORPHANED_BUFS        = 1 << 4;
         const SUPPORTS_M2M_HOLD_CAPTURE_BUF = 1 << 5;
         const SUPPORTS_MMAP_CACHE_HINTS     = 1 << 6;
+        const SUPPORTS_MAX_NUM_BUFFERS      = 1 << 7;
+        const SUPPORTS_REMOVE_BUFS          = 1 << 8;
     }
 }
 
 bitflags! {
+    /// Flags of a buffer.
     pub struct BufFlag: u32 {
         const MAPPED               = 0x00000001;
         const QUEUED               = 0x00000002;
@@ -440,6 +439,7 @@ bitflags! {
 }
 
 bitflags! {
+    /// Flags of a [`Timecode`][crate::stream::Timecode].
     pub struct TimecodeFlags: u32 {
         const DROPFRAME            = 0x0001;
         const COLORFRAME           = 0x0002;
@@ -461,8 +461,255 @@ bitflags! {
     }
 }
 
+ffi_enum! {
+    /// Type of a V4L2 event.
+    pub enum EventType: u32 {
+        /// Only valid for unsubscribing: unsubscribes from all events.
+        ALL           = 0,
+        /// Vertical sync of the incoming video signal.
+        VSYNC         = 1,
+        /// The end of the stream was reached.
+        EOS           = 2,
+        /// A control changed. The event ID is the control ID.
+        CTRL          = 3,
+        /// A new frame started to be received.
+        FRAME_SYNC    = 4,
+        /// The source (input) parameters changed. The event ID is the input or pad index.
+        SOURCE_CHANGE = 5,
+        /// The motion detection state changed.
+        MOTION_DET    = 6,
+        /// Start of the range of driver-specific event types.
+        PRIVATE_START = 0x08000000,
+    }
+}
+
+bitflags! {
+    /// Flags used when subscribing to an event.
+    pub struct EventSubscriptionFlags: u32 {
+        /// Immediately send an event with the current state (only for control events).
+        const SEND_INITIAL   = 0x0001;
+        /// Also deliver control events caused by the file descriptor that subscribed to them.
+        const ALLOW_FEEDBACK = 0x0002;
+    }
+}
+
+bitflags! {
+    /// What changed about a control in a control event.
+    pub struct CtrlChanges: u32 {
+        const VALUE      = 0x0001;
+        const FLAGS      = 0x0002;
+        const RANGE      = 0x0004;
+        const DIMENSIONS = 0x0008;
+    }
+}
+
+bitflags! {
+    /// What changed about the source in a source change event.
+    pub struct SourceChanges: u32 {
+        /// The resolution (or another format parameter) changed, and the format has to be
+        /// renegotiated.
+        const RESOLUTION = 0x0001;
+    }
+}
+
+bitflags! {
+    pub struct MotionDetFlags: u32 {
+        const HAVE_FRAME_SEQ = 0x0001;
+    }
+}
+
+ffi_enum! {
+    /// Command sent to a stateful encoder.
+    pub enum EncCmd: u32 {
+        START  = 0,
+        STOP   = 1,
+        PAUSE  = 2,
+        RESUME = 3,
+    }
+}
+
+bitflags! {
+    pub struct EncCmdFlags: u32 {
+        const STOP_AT_GOP_END = 0x0001;
+    }
+}
+
+ffi_enum! {
+    /// Command sent to a stateful decoder.
+    pub enum DecCmd: u32 {
+        START  = 0,
+        STOP   = 1,
+        PAUSE  = 2,
+        RESUME = 3,
+        FLUSH  = 4,
+    }
+}
+
+bitflags! {
+    pub struct DecCmdFlags: u32 {
+        const START_MUTE_AUDIO = 0x0001;
+        const PAUSE_TO_BLACK   = 0x0001;
+        const STOP_TO_BLACK    = 0x0001;
+        const STOP_IMMEDIATELY = 0x0002;
+    }
+}
+
+ffi_enum! {
+    /// Value of the [`Cid::STATELESS_H264_DECODE_MODE`][crate::controls::Cid] menu control.
+    pub enum H264DecodeMode: u32 {
+        /// Every request contains one slice, described by
+        /// [`H264SliceParams`][crate::codec::stateless::H264SliceParams].
+        SLICE_BASED = 0,
+        /// Every request contains all slices of a frame, whose headers are parsed by the hardware.
+        FRAME_BASED = 1,
+    }
+}
+
+ffi_enum! {
+    /// Value of the [`Cid::STATELESS_H264_START_CODE`][crate::controls::Cid] menu control.
+    pub enum H264StartCode: u32 {
+        /// Slices are passed without a start code.
+        NONE    = 0,
+        /// Slices are prefixed with an Annex B start code (`00 00 01`).
+        ANNEX_B = 1,
+    }
+}
+
+bitflags! {
+    pub struct H264SpsConstraintFlags: u8 {
+        const SET0 = 0x01;
+        const SET1 = 0x02;
+        const SET2 = 0x04;
+        const SET3 = 0x08;
+        const SET4 = 0x10;
+        const SET5 = 0x20;
+    }
+}
+
+bitflags! {
+    pub struct H264SpsFlags: u32 {
+        const SEPARATE_COLOUR_PLANE             = 0x01;
+        const QPPRIME_Y_ZERO_TRANSFORM_BYPASS   = 0x02;
+        const DELTA_PIC_ORDER_ALWAYS_ZERO       = 0x04;
+        const GAPS_IN_FRAME_NUM_VALUE_ALLOWED   = 0x08;
+        const FRAME_MBS_ONLY                    = 0x10;
+        const MB_ADAPTIVE_FRAME_FIELD           = 0x20;
+        const DIRECT_8X8_INFERENCE              = 0x40;
+    }
+}
+
+bitflags! {
+    pub struct H264PpsFlags: u16 {
+        const ENTROPY_CODING_MODE                   = 0x0001;
+        const BOTTOM_FIELD_PIC_ORDER_IN_FRAME_PRESENT = 0x0002;
+        const WEIGHTED_PRED                         = 0x0004;
+        const DEBLOCKING_FILTER_CONTROL_PRESENT     = 0x0008;
+        const CONSTRAINED_INTRA_PRED                = 0x0010;
+        const REDUNDANT_PIC_CNT_PRESENT             = 0x0020;
+        const TRANSFORM_8X8_MODE                    = 0x0040;
+        /// A non-flat scaling matrix applies, and is passed via
+        /// [`H264ScalingMatrix`][crate::codec::stateless::H264ScalingMatrix].
+        const SCALING_MATRIX_PRESENT                = 0x0080;
+    }
+}
+
+bitflags! {
+    pub struct H264SliceFlags: u32 {
+        const DIRECT_SPATIAL_MV_PRED = 0x01;
+        const SP_FOR_SWITCH          = 0x02;
+    }
+}
+
+bitflags! {
+    pub struct H264DpbEntryFlags: u32 {
+        const VALID     = 0x01;
+        const ACTIVE    = 0x02;
+        const LONG_TERM = 0x04;
+        const FIELD     = 0x08;
+    }
+}
+
+bitflags! {
+    pub struct H264DecodeParamFlags: u32 {
+        const IDR_PIC      = 0x01;
+        const FIELD_PIC    = 0x02;
+        const BOTTOM_FIELD = 0x04;
+        const PFRAME       = 0x08;
+        const BFRAME       = 0x10;
+    }
+}
+
+bitflags! {
+    pub struct FwhtFlags: u32 {
+        const IS_INTERLACED         = 1 << 0;
+        const IS_BOTTOM_FIRST       = 1 << 1;
+        const IS_ALTERNATE          = 1 << 2;
+        const IS_BOTTOM_FIELD       = 1 << 3;
+        const LUMA_IS_UNCOMPRESSED  = 1 << 4;
+        const CB_IS_UNCOMPRESSED    = 1 << 5;
+        const CR_IS_UNCOMPRESSED    = 1 << 6;
+        const CHROMA_FULL_HEIGHT    = 1 << 7;
+        const CHROMA_FULL_WIDTH     = 1 << 8;
+        const ALPHA_IS_UNCOMPRESSED = 1 << 9;
+        const I_FRAME               = 1 << 10;
+
+        /// Mask of the number of components minus one.
+        const COMPONENTS_NUM_MASK   = 0b111 << 16;
+        const PIXENC_YUV            = 1 << 19;
+        const PIXENC_RGB            = 2 << 19;
+        const PIXENC_HSV            = 3 << 19;
+    }
+}
+
+bitflags! {
+    pub struct Vp8SegmentFlags: u32 {
+        const ENABLED             = 0x01;
+        const UPDATE_MAP          = 0x02;
+        const UPDATE_FEATURE_DATA = 0x04;
+        const DELTA_VALUE_MODE    = 0x08;
+    }
+}
+
+bitflags! {
+    pub struct Vp8LoopFilterFlags: u32 {
+        const ADJ_ENABLE         = 0x01;
+        const DELTA_UPDATE       = 0x02;
+        const FILTER_TYPE_SIMPLE = 0x04;
+    }
+}
+
+bitflags! {
+    pub struct Vp8FrameFlags: u64 {
+        const KEY_FRAME        = 0x01;
+        const EXPERIMENTAL     = 0x02;
+        const SHOW_FRAME       = 0x04;
+        const MB_NO_SKIP_COEFF = 0x08;
+        const SIGN_BIAS_GOLDEN = 0x10;
+        const SIGN_BIAS_ALT    = 0x20;
+    }
+}
+
+bitflags! {
+    pub struct Mpeg2SequenceFlags: u8 {
+        const PROGRESSIVE = 0x01;
+    }
+}
+
+bitflags! {
+    pub struct Mpeg2PictureFlags: u32 {
+        const TOP_FIELD_FIRST = 0x0001;
+        const FRAME_PRED_DCT  = 0x0002;
+        const CONCEALMENT_MV  = 0x0004;
+        const Q_SCALE_TYPE    = 0x0008;
+        const INTRA_VLC       = 0x0010;
+        const ALT_SCAN        = 0x0020;
+        const REPEAT_FIRST    = 0x0040;
+        const PROGRESSIVE     = 0x0080;
+    }
+}
+
 /// A fractional value (`numerator / denominator`).
-#[derive(Clone, Copy, Hash)]
+#[derive(Clone, Copy)]
 #[repr(C)]
 pub struct Fract {
     numerator: u32,
@@ -517,10 +764,19 @@ impl PartialEq for Fract {
 
 impl Eq for Fract {}
 
+impl Hash for Fract {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Equal fractions must hash the same, so hash the reduced form. Fractions reported by
+        // drivers may be `0/0`, so don't divide by zero.
+        let gcd = gcd(self.numerator, self.denominator).max(1);
+        (self.numerator / gcd).hash(state);
+        (self.denominator / gcd).hash(state);
+    }
+}
+
 impl PartialOrd for Fract {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        let [a, b] = same_denom(*self, *other);
-        a.numerator.partial_cmp(&b.numerator)
+        Some(self.cmp(other))
     }
 }
 
@@ -590,4 +846,21 @@ mod tests {
         assert_eq!(y.numerator, 9);
         assert_eq!(y.denominator, 15);
     }
+
+    #[test]
+    fn test_fract_hash_eq() {
+        use std::collections::hash_map::DefaultHasher;
+
+        fn hash(f: Fract) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            f.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let a = Fract::new(1, 2);
+        let b = Fract::new(2, 4);
+        assert_eq!(a, b);
+        assert_eq!(hash(a), hash(b));
+        assert!(Fract::new(1, 3) < a);
+    }
 }
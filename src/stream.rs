@@ -1,60 +1,422 @@
 //! Streaming I/O.
 
+use std::alloc::{self, Layout};
 use std::ffi::c_void;
+use std::fmt;
 use std::fs::File;
-use std::ops::{Deref, DerefMut};
+use std::ops::{Deref, DerefMut, Range};
 use std::os::fd::AsFd;
-use std::os::raw::c_int;
+use std::os::raw::{c_int, c_ulong};
 use std::os::unix::prelude::*;
-use std::{io, slice};
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+use std::{array, io, slice};
 use std::{mem, ptr};
 
+use uoctl::Ioctl;
+
 use crate::buf_type::BufType;
-use crate::raw;
-use crate::shared::{BufFlag, Memory};
+use crate::event::{self, DequeuedEvent, EventSubscriptionFlags, EventType};
+use crate::format::Format;
+use crate::media::Request;
+use crate::raw::controls::{Cid, Control};
+use crate::raw::{self, VIDEO_MAX_PLANES};
+use crate::shared::Field;
+
+pub use crate::shared::{BufCap, BufFlag, Memory, TimecodeFlags, TimecodeType};
+
+#[cfg(feature = "async-io")]
+pub mod async_io;
+mod background;
+mod m2m;
+mod multi;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+
+pub use self::background::{BackgroundCapture, FrameReceiver};
+pub use self::m2m::M2mStream;
+pub use self::multi::MultiStream;
 
 enum AllocType {
-    /// The buffer was `mmap`ped into our address space, use `munmap` to free it.
+    /// The plane was `mmap`ped into our address space, use `munmap` to free it.
     Mmap,
+    /// The plane was allocated by the application and is freed by dropping the [`UserBuffer`].
+    UserPtr(UserBuffer),
+    /// The plane is a dma-buf imported from elsewhere.
+    ///
+    /// If it could be `mmap`ped, `munmap` has to be used to unmap it (the plane's `length` is 0
+    /// otherwise). The file descriptor is closed when dropped.
+    DmaBuf { fd: OwnedFd, size: u32 },
 }
 
-struct Buffer {
-    /// Pointer in our address space where this buffer is mapped or allocated.
+/// A memory plane of a [`Buffer`].
+///
+/// Single-planar buffers always consist of exactly one plane.
+struct Plane {
+    /// Pointer in our address space where this plane is mapped or allocated.
     ptr: *mut c_void,
-    /// Size of the buffer in bytes.
+    /// Size of the plane in bytes, as accessible through `ptr`.
     length: u32,
-    queued: bool,
+    alloc: AllocType,
+    /// dma-buf file descriptor referring to this plane, created via `VIDIOC_EXPBUF` on demand.
+    exported: OnceLock<OwnedFd>,
+}
+
+impl Plane {
+    /// Maps a driver-allocated plane into our address space.
+    fn mmap(fd: c_int, length: u32, offset: u32) -> io::Result<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                length as _,
+                // XXX is PROT_WRITE allowed for `ReadStream`s?
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                offset.into(),
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            ptr,
+            length,
+            alloc: AllocType::Mmap,
+            exported: OnceLock::new(),
+        })
+    }
+
+    /// Returns the size of the plane as known to the driver.
+    ///
+    /// This differs from `length` for imported dma-bufs that could not be mapped.
+    fn size(&self) -> u32 {
+        match self.alloc {
+            AllocType::DmaBuf { size, .. } => size,
+            _ => self.length,
+        }
+    }
+
+    /// Returns the dma-buf file descriptor referring to this plane, if there is one.
+    fn dmabuf_fd(&self) -> Option<BorrowedFd<'_>> {
+        match &self.alloc {
+            AllocType::DmaBuf { fd, .. } => Some(fd.as_fd()),
+            _ => self.exported.get().map(|fd| fd.as_fd()),
+        }
+    }
+
+    /// Brackets CPU access to an imported dma-buf, so that caches are kept coherent.
+    ///
+    /// Does nothing for other types of planes.
+    fn sync_dmabuf(&self, flags: u64) {
+        if let AllocType::DmaBuf { fd, .. } = &self.alloc {
+            if self.length == 0 {
+                return;
+            }
+
+            let sync = raw::DmaBufSync { flags };
+            unsafe {
+                if let Err(e) = raw::DMA_BUF_IOCTL_SYNC.ioctl(fd, &sync) {
+                    log::debug!("`DMA_BUF_IOCTL_SYNC` failed: {e}");
+                }
+            }
+        }
+    }
+
+    unsafe fn data<'a>(&self) -> &'a [u8] {
+        slice::from_raw_parts(self.ptr as *const u8, self.length as usize)
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn data_mut<'a>(&self) -> &'a mut [u8] {
+        slice::from_raw_parts_mut(self.ptr as *mut u8, self.length as usize)
+    }
+}
+
+impl Drop for Plane {
+    fn drop(&mut self) {
+        let mapped = match self.alloc {
+            AllocType::Mmap => true,
+            AllocType::UserPtr(_) => false,
+            // The driver holds its own reference to the dma-buf, so closing our file descriptor is
+            // fine even if it is still queued.
+            AllocType::DmaBuf { .. } => self.length != 0,
+        };
+        if mapped && unsafe { libc::munmap(self.ptr, self.length as _) } == -1 {
+            log::warn!("failed to `munmap` on drop: {}", io::Error::last_os_error());
+        }
+    }
+}
+
+struct Buffer {
+    planes: Vec<Plane>,
+    /// Whether the buffer is currently owned by the driver.
+    queued: AtomicBool,
+    /// Whether the buffer is currently owned by an [`OwnedReadBuffer`].
+    held: AtomicBool,
+}
+
+unsafe impl Send for Buffer {}
+unsafe impl Sync for Buffer {}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        if !*self.queued.get_mut() {
+            return;
+        }
+
+        for plane in self.planes.drain(..) {
+            if let AllocType::UserPtr(_) = plane.alloc {
+                // The driver may still write to this memory, so we must not free it.
+                log::warn!(
+                    "leaking user buffer at {:p} because it is still owned by the driver",
+                    plane.ptr,
+                );
+                mem::forget(plane);
+            }
+        }
+    }
+}
+
+impl Buffer {
+    fn new(planes: Vec<Plane>) -> Self {
+        Self {
+            planes,
+            queued: AtomicBool::new(false),
+            held: AtomicBool::new(false),
+        }
+    }
+
+    /// Fills in the fields of `buf` that `VIDIOC_QBUF` needs for this buffer's memory type.
+    fn fill_qbuf(&self, buf: &mut RawBuffer) {
+        for (i, plane) in self.planes.iter().enumerate() {
+            match plane.alloc {
+                AllocType::Mmap => {}
+                AllocType::UserPtr(ref user) => {
+                    buf.set_plane_userptr(i, user.ptr.as_ptr() as c_ulong, user.len() as u32);
+                }
+                AllocType::DmaBuf { ref fd, size } => {
+                    buf.set_plane_fd(i, fd.as_raw_fd(), size);
+                }
+            }
+        }
+    }
+
+    /// Makes sure that [`Plane::dmabuf_fd`] returns a file descriptor for all planes of this
+    /// buffer, which has index `index`.
+    ///
+    /// Planes that aren't imported dma-bufs are exported the first time this is called.
+    fn ensure_exported(&self, fd: c_int, buf_type: BufType, index: u32) -> io::Result<()> {
+        for (p, plane) in self.planes.iter().enumerate() {
+            if plane.dmabuf_fd().is_none() {
+                let exported = Buffers::export(fd, buf_type, index, p as u32)?;
+                // If another thread exported the plane in the meantime, our fd is just closed.
+                plane.exported.set(exported).ok();
+            }
+        }
+        Ok(())
+    }
+
+    fn sync_dmabuf(&self, flags: u64) {
+        for plane in &self.planes {
+            plane.sync_dmabuf(flags);
+        }
+    }
+
+    /// Determines where the payload of each plane is located, according to the dequeued `buf`.
+    fn payload(&self, buf: &RawBuffer) -> [Payload; VIDEO_MAX_PLANES] {
+        array::from_fn(|i| match self.planes.get(i) {
+            Some(plane) => {
+                let bytesused = (buf.plane_bytesused(i) as usize).min(plane.length as usize);
+                Payload {
+                    bytesused,
+                    data_offset: (buf.plane_data_offset(i) as usize).min(bytesused),
+                }
+            }
+            None => Payload::default(),
+        })
+    }
+
+    /// Creates a [`ReadBufferView`] of this buffer.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must not be enqueued while the returned view exists.
+    unsafe fn read_view(
+        &self,
+        meta: BufferMeta,
+        payload: &[Payload; VIDEO_MAX_PLANES],
+    ) -> ReadBufferView<'_> {
+        ReadBufferView {
+            meta,
+            planes: array::from_fn(|i| match self.planes.get(i) {
+                Some(plane) => ReadPlane {
+                    data: plane.data(),
+                    bytesused: payload[i].bytesused,
+                    data_offset: payload[i].data_offset,
+                    fd: plane.dmabuf_fd(),
+                },
+                None => ReadPlane::default(),
+            }),
+            num_planes: self.planes.len(),
+        }
+    }
+}
+
+/// Location of the payload within a plane of a dequeued buffer.
+#[derive(Clone, Copy, Default)]
+struct Payload {
+    bytesused: usize,
+    data_offset: usize,
+}
+
+/// A `v4l2_buffer`, together with the `v4l2_plane` array used by multi-planar buffer types.
+struct RawBuffer {
+    buf: raw::Buffer,
+    planes: [raw::Plane; VIDEO_MAX_PLANES],
+}
+
+impl RawBuffer {
+    fn new(buf_type: BufType, mem_type: Memory, index: u32) -> Self {
+        let mut this: Self = unsafe { mem::zeroed() };
+        this.buf.type_ = buf_type;
+        this.buf.memory = mem_type;
+        this.buf.index = index;
+        if buf_type.is_multiplanar() {
+            // For multi-planar buffers, `length` is the number of entries in the `planes` array.
+            this.buf.length = VIDEO_MAX_PLANES as u32;
+        }
+        this
+    }
+
+    /// Performs a buffer ioctl on `fd`.
+    ///
+    /// This takes care of pointing the kernel at our `planes` array, which is required for
+    /// multi-planar buffer types.
+    unsafe fn ioctl(&mut self, ioctl: Ioctl<*mut raw::Buffer>, fd: c_int) -> io::Result<()> {
+        if self.buf.type_.is_multiplanar() {
+            self.buf.m.planes = self.planes.as_mut_ptr();
+        }
+        ioctl.ioctl(&fd, &mut self.buf)?;
+        Ok(())
+    }
+
+    fn index(&self) -> u32 {
+        self.buf.index
+    }
+
+    fn num_planes(&self) -> usize {
+        if self.buf.type_.is_multiplanar() {
+            self.buf.length as usize
+        } else {
+            1
+        }
+    }
+
+    /// Returns the `length` of plane `i`.
+    fn plane_length(&self, i: usize) -> u32 {
+        if self.buf.type_.is_multiplanar() {
+            self.planes[i].length
+        } else {
+            self.buf.length
+        }
+    }
+
+    /// Returns the `mmap` offset of plane `i`.
+    fn plane_offset(&self, i: usize) -> u32 {
+        unsafe {
+            if self.buf.type_.is_multiplanar() {
+                self.planes[i].m.mem_offset
+            } else {
+                self.buf.m.offset
+            }
+        }
+    }
+
+    /// Returns the number of bytes occupied by data in plane `i`, including the `data_offset`.
+    fn plane_bytesused(&self, i: usize) -> u32 {
+        if self.buf.type_.is_multiplanar() {
+            self.planes[i].bytesused
+        } else {
+            self.buf.bytesused
+        }
+    }
+
+    /// Returns the offset of the payload in plane `i`.
+    fn plane_data_offset(&self, i: usize) -> u32 {
+        if self.buf.type_.is_multiplanar() {
+            self.planes[i].data_offset
+        } else {
+            0
+        }
+    }
+
+    /// Sets the number of bytes occupied by data in plane `i`.
+    fn set_plane_bytesused(&mut self, i: usize, bytesused: u32) {
+        if self.buf.type_.is_multiplanar() {
+            self.planes[i].bytesused = bytesused;
+        } else {
+            self.buf.bytesused = bytesused;
+        }
+    }
+
+    /// Sets the user pointer and length of plane `i`, for `USERPTR` memory.
+    fn set_plane_userptr(&mut self, i: usize, ptr: c_ulong, length: u32) {
+        if self.buf.type_.is_multiplanar() {
+            self.planes[i].m.userptr = ptr;
+            self.planes[i].length = length;
+        } else {
+            self.buf.m.userptr = ptr;
+            self.buf.length = length;
+        }
+    }
+
+    /// Sets the dma-buf file descriptor and length of plane `i`, for `DMABUF` memory.
+    fn set_plane_fd(&mut self, i: usize, fd: RawFd, length: u32) {
+        if self.buf.type_.is_multiplanar() {
+            self.planes[i].m.fd = fd;
+            self.planes[i].length = length;
+        } else {
+            self.buf.m.fd = fd;
+            self.buf.length = length;
+        }
+    }
 }
 
 /// Owns all buffers allocated or mapped for a device stream.
 struct Buffers {
-    ty: AllocType,
     /// The buffer index equals its index in this vector.
-    buffers: Vec<Buffer>,
+    ///
+    /// Buffers removed via `VIDIOC_REMOVE_BUFS` leave a `None` behind.
+    buffers: Vec<Option<Arc<Buffer>>>,
+    /// `V4L2_MEMORY_FLAG_*` flags the buffers were allocated with.
+    memory_flags: u8,
+    /// Capabilities of the queue, as reported when allocating the buffers.
+    capabilities: BufCap,
 }
 
-unsafe impl Send for Buffers {}
-unsafe impl Sync for Buffers {}
-
 /// Number of buffers we request by default.
-pub(super) const DEFAULT_BUFFER_COUNT: u32 = 2;
+const DEFAULT_BUFFER_COUNT: u32 = 2;
 
 impl Buffers {
-    fn allocate(
+    /// Issues `VIDIOC_REQBUFS`.
+    ///
+    /// The returned `count` is the number of buffers the driver allocated.
+    fn request(
         fd: c_int,
         buf_type: BufType,
         mem_type: Memory,
-        mut buffer_count: u32,
-    ) -> io::Result<Self> {
-        let alloc_type = match mem_type {
-            Memory::MMAP => AllocType::Mmap,
-            _ => unimplemented!("only `mmap` memory type is currently supported"),
-        };
-
+        count: u32,
+        flags: u8,
+    ) -> io::Result<raw::RequestBuffers> {
         let mut req_bufs: raw::RequestBuffers = unsafe { mem::zeroed() };
-        req_bufs.count = buffer_count;
+        req_bufs.count = count;
         req_bufs.type_ = buf_type;
         req_bufs.memory = mem_type;
+        req_bufs.flags = flags;
 
         unsafe {
             raw::VIDIOC_REQBUFS.ioctl(&fd, &mut req_bufs)?;
@@ -62,407 +424,2960 @@ impl Buffers {
 
         log::debug!("{:?}", req_bufs);
 
-        if req_bufs.count < buffer_count {
-            log::trace!("failed to allocate {buffer_count} buffers (driver only allocated {0}), using {0} instead", req_bufs.count);
-            buffer_count = req_bufs.count;
+        Ok(req_bufs)
+    }
+
+    fn query(fd: c_int, buf_type: BufType, mem_type: Memory, index: u32) -> io::Result<RawBuffer> {
+        let mut buf = RawBuffer::new(buf_type, mem_type, index);
+
+        unsafe {
+            buf.ioctl(raw::VIDIOC_QUERYBUF, fd)?;
+        }
+
+        assert_eq!(buf.index(), index);
+        Ok(buf)
+    }
+
+    /// Allocates buffers as described by `config`.
+    ///
+    /// `MMAP` buffers are allocated by the driver and mapped into our address space, `USERPTR`
+    /// buffers are allocated by us, with the sizes the driver requires.
+    fn allocate(fd: c_int, buf_type: BufType, config: &StreamConfig) -> io::Result<Self> {
+        let mem_type = config.memory;
+        if mem_type != Memory::MMAP && mem_type != Memory::USERPTR {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot allocate buffers of memory type {mem_type:?}"),
+            ));
+        }
+
+        let buffer_count = config.resolve_buffer_count(fd, buf_type);
+        let mut flags = 0;
+        if config.non_coherent {
+            flags |= raw::MEMORY_FLAG_NON_COHERENT;
+        }
+
+        let req = Self::request(fd, buf_type, mem_type, buffer_count, flags)?;
+        if req.count != buffer_count {
+            log::debug!(
+                "requested {buffer_count} buffers, driver allocated {} instead",
+                req.count
+            );
+        }
+        if config.non_coherent && req.flags & raw::MEMORY_FLAG_NON_COHERENT == 0 {
+            log::debug!("driver does not support non-coherent buffers, using coherent ones");
         }
 
         // Query the buffer locations and map them into our process.
-        let mut buffers = Vec::with_capacity(buffer_count as usize);
-        for i in 0..buffer_count {
-            let mut buf: raw::Buffer = unsafe { mem::zeroed() };
-            buf.type_ = buf_type;
-            buf.memory = mem_type;
-            buf.index = i;
+        let mut buffers = Self {
+            buffers: Vec::with_capacity(req.count as usize),
+            memory_flags: req.flags,
+            capabilities: req.capabilities,
+        };
+        for i in 0..req.count {
+            let buffer = Self::allocate_buffer(fd, buf_type, mem_type, i)?;
+            buffers.buffers.push(Some(Arc::new(buffer)));
+        }
 
-            unsafe {
-                raw::VIDIOC_QUERYBUF.ioctl(&fd, &mut buf)?;
-            }
+        Ok(buffers)
+    }
 
-            // NB: buffer sizes are usually `PixFormat::size_image(_)` rounded up to whole pages
-            let ptr = unsafe {
-                libc::mmap(
-                    ptr::null_mut(),
-                    buf.length as _,
-                    // XXX is PROT_WRITE allowed for `ReadStream`s?
-                    libc::PROT_READ | libc::PROT_WRITE,
-                    libc::MAP_SHARED,
-                    fd,
-                    buf.m.offset.into(),
-                )
+    /// Maps the driver-allocated buffer `index` into our address space, or allocates user memory
+    /// for it, depending on `mem_type`.
+    fn allocate_buffer(
+        fd: c_int,
+        buf_type: BufType,
+        mem_type: Memory,
+        index: u32,
+    ) -> io::Result<Buffer> {
+        let buf = Self::query(fd, buf_type, mem_type, index)?;
+
+        // Planes allocated so far are freed when `buffer` is dropped on error.
+        let mut buffer = Buffer::new(Vec::with_capacity(buf.num_planes()));
+        for p in 0..buf.num_planes() {
+            // NB: plane sizes are usually `PixFormat::size_image(_)` rounded up to whole pages
+            let length = buf.plane_length(p);
+            let plane = if mem_type == Memory::MMAP {
+                Plane::mmap(fd, length, buf.plane_offset(p))?
+            } else {
+                if length == 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("driver reported size 0 for plane {p} of buffer {index}"),
+                    ));
+                }
+                let user = UserBuffer::new(length as usize);
+                Plane {
+                    ptr: user.ptr.as_ptr().cast(),
+                    length,
+                    alloc: AllocType::UserPtr(user),
+                    exported: OnceLock::new(),
+                }
             };
-            if ptr == libc::MAP_FAILED {
-                return Err(io::Error::last_os_error());
-            }
+            buffer.planes.push(plane);
+        }
 
-            assert_eq!(buf.index, i);
-            assert_eq!(buf.index as usize, buffers.len());
+        Ok(buffer)
+    }
 
-            buffers.push(Buffer {
-                ptr,
-                length: buf.length,
-                queued: false,
-            });
+    /// Allocates `count` additional buffers via `VIDIOC_CREATE_BUFS`, sized for `format`.
+    ///
+    /// Returns the range of indices of the new buffers.
+    fn create(
+        &mut self,
+        fd: c_int,
+        mem_type: Memory,
+        count: u32,
+        format: raw::Format,
+    ) -> io::Result<Range<u32>> {
+        let buf_type = format.type_;
+        if mem_type != Memory::MMAP && mem_type != Memory::USERPTR {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot create buffers for a stream of memory type {mem_type:?}"),
+            ));
         }
 
-        Ok(Self {
-            ty: alloc_type,
-            buffers,
-        })
-    }
-}
+        let mut create: raw::CreateBuffers = unsafe { mem::zeroed() };
+        create.count = count;
+        create.memory = mem_type;
+        create.format = format;
+        create.flags = self.memory_flags.into();
 
-impl Drop for Buffers {
-    fn drop(&mut self) {
-        for buffer in &self.buffers {
-            match self.ty {
-                AllocType::Mmap => unsafe {
-                    if libc::munmap(buffer.ptr, buffer.length as _) == -1 {
-                        log::warn!("failed to `munmap` on drop: {}", io::Error::last_os_error());
+        unsafe {
+            raw::VIDIOC_CREATE_BUFS.ioctl(&fd, &mut create)?;
+        }
+
+        log::debug!(
+            "created {} buffers starting at index {} (requested {count})",
+            create.count,
+            create.index,
+        );
+        self.capabilities = create.capabilities;
+
+        let indices = create.index..create.index + create.count;
+        // Map all buffers before recording any of them, so that a failure doesn't leave the pool
+        // with some of the new buffers missing (which would look like they had been removed).
+        let buffers = indices
+            .clone()
+            .map(|i| Self::allocate_buffer(fd, buf_type, mem_type, i))
+            .collect::<io::Result<Vec<_>>>();
+        let buffers = match buffers {
+            Ok(buffers) => buffers,
+            Err(e) => {
+                if self.capabilities.contains(BufCap::SUPPORTS_REMOVE_BUFS) {
+                    if let Err(e) = Self::remove_bufs(fd, buf_type, indices.clone()) {
+                        log::warn!("failed to free buffers {indices:?}: {e}");
                     }
-                },
+                }
+                return Err(e);
             }
+        };
+
+        if self.buffers.len() < indices.end as usize {
+            self.buffers.resize(indices.end as usize, None);
+        }
+        for (i, buffer) in indices.clone().zip(buffers) {
+            self.buffers[i as usize] = Some(Arc::new(buffer));
         }
+
+        Ok(indices)
     }
-}
 
-/// A stream that reads data from a V4L2 device.
-pub struct ReadStream {
-    file: File,
-    buffers: Buffers,
-    buf_type: BufType,
-    mem_type: Memory,
-}
+    /// Frees the buffers at `indices` via `VIDIOC_REMOVE_BUFS`.
+    ///
+    /// None of the buffers may be queued or held by an [`OwnedReadBuffer`].
+    fn remove(&mut self, fd: c_int, buf_type: BufType, indices: Range<u32>) -> io::Result<()> {
+        for i in indices.clone() {
+            match self.get(i) {
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("buffer {i} does not exist"),
+                    ));
+                }
+                Some(b) if b.queued.load(Ordering::Relaxed) || b.held.load(Ordering::Relaxed) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("buffer {i} is in use and cannot be removed"),
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
 
-impl ReadStream {
-    pub(crate) fn new(
-        file: File,
+        Self::remove_bufs(fd, buf_type, indices.clone())?;
+
+        for i in indices {
+            self.buffers[i as usize] = None;
+        }
+        // Keep the buffer count accurate when removing from the end.
+        while let Some(None) = self.buffers.last() {
+            self.buffers.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Issues `VIDIOC_REMOVE_BUFS`.
+    fn remove_bufs(fd: c_int, buf_type: BufType, indices: Range<u32>) -> io::Result<()> {
+        let mut remove: raw::RemoveBuffers = unsafe { mem::zeroed() };
+        remove.index = indices.start;
+        remove.count = indices.len() as u32;
+        remove.type_ = buf_type;
+
+        unsafe {
+            raw::VIDIOC_REMOVE_BUFS.ioctl(&fd, &mut remove)?;
+        }
+        Ok(())
+    }
+
+    fn get(&self, index: u32) -> Option<&Arc<Buffer>> {
+        self.buffers.get(index as usize)?.as_ref()
+    }
+
+    /// Returns an iterator over all buffers and their indices.
+    fn iter(&self) -> impl Iterator<Item = (u32, &Arc<Buffer>)> {
+        self.buffers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, b)| Some((i as u32, b.as_ref()?)))
+    }
+
+    /// Checks the number of buffers granted by the driver against the number of buffers the
+    /// application provided.
+    fn check_count(
+        fd: c_int,
+        buf_type: BufType,
+        mem_type: Memory,
+        requested: u32,
+    ) -> io::Result<raw::RequestBuffers> {
+        let req = Self::request(fd, buf_type, mem_type, requested, 0)?;
+        let count = req.count;
+        if count > requested {
+            // Release the driver's bookkeeping again before bailing.
+            Self::request(fd, buf_type, mem_type, 0, 0).ok();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "driver requires at least {count} buffers, but only {requested} were provided"
+                ),
+            ));
+        }
+        if count < requested {
+            log::trace!("driver only accepted {count} of {requested} buffers, freeing the rest");
+        }
+        Ok(req)
+    }
+
+    /// Queries buffer `index` and checks that `planes` has the expected number of planes.
+    fn query_planes<T>(
+        fd: c_int,
         buf_type: BufType,
         mem_type: Memory,
-        buffer_count: u32,
+        index: u32,
+        planes: &[T],
+    ) -> io::Result<RawBuffer> {
+        let buf = Self::query(fd, buf_type, mem_type, index)?;
+        if planes.len() != buf.num_planes() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "buffer {index} has {} planes, but the driver requires {}",
+                    planes.len(),
+                    buf.num_planes(),
+                ),
+            ));
+        }
+        Ok(buf)
+    }
+
+    /// Registers application-allocated buffers with the driver for [`Memory::USERPTR`] streaming.
+    ///
+    /// Every buffer is given as a list of its planes.
+    fn userptr(
+        fd: c_int,
+        buf_type: BufType,
+        user_buffers: Vec<Vec<UserBuffer>>,
     ) -> io::Result<Self> {
-        let fd = file.as_raw_fd();
-        let buffers = Buffers::allocate(fd, buf_type, mem_type, buffer_count)?;
+        let req = Self::check_count(fd, buf_type, Memory::USERPTR, user_buffers.len() as u32)?;
+        let count = req.count;
 
-        let mut this = Self {
-            file,
-            buffers,
-            buf_type,
-            mem_type,
+        let mut buffers = Self {
+            buffers: Vec::with_capacity(count as usize),
+            memory_flags: 0,
+            capabilities: req.capabilities,
         };
-        this.enqueue_all()?;
-        this.stream_on()?;
+        for (i, user_planes) in user_buffers.into_iter().take(count as usize).enumerate() {
+            let buf = Self::query_planes(fd, buf_type, Memory::USERPTR, i as u32, &user_planes)?;
+
+            let mut planes = Vec::with_capacity(user_planes.len());
+            for (p, user) in user_planes.into_iter().enumerate() {
+                // For `USERPTR` buffers, `length` is the size the driver needs each plane to have.
+                let required = buf.plane_length(p);
+                if user.len() < required as usize {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "user buffer {i} (plane {p}) is too small (driver requires {required} bytes, buffer has {})",
+                            user.len(),
+                        ),
+                    ));
+                }
 
-        Ok(this)
+                planes.push(Plane {
+                    ptr: user.ptr.as_ptr().cast(),
+                    length: user.len() as u32,
+                    alloc: AllocType::UserPtr(user),
+                    exported: OnceLock::new(),
+                });
+            }
+
+            buffers.buffers.push(Some(Arc::new(Buffer::new(planes))));
+        }
+
+        Ok(buffers)
+    }
+
+    /// Registers dma-buf file descriptors with the driver for [`Memory::DMABUF`] streaming.
+    ///
+    /// Every buffer is given as a list of its planes.
+    fn dmabuf(fd: c_int, buf_type: BufType, dmabufs: Vec<Vec<OwnedFd>>) -> io::Result<Self> {
+        let req = Self::check_count(fd, buf_type, Memory::DMABUF, dmabufs.len() as u32)?;
+        let count = req.count;
+
+        let mut buffers = Self {
+            buffers: Vec::with_capacity(count as usize),
+            memory_flags: 0,
+            capabilities: req.capabilities,
+        };
+        for (i, dmabuf_planes) in dmabufs.into_iter().take(count as usize).enumerate() {
+            let buf = Self::query_planes(fd, buf_type, Memory::DMABUF, i as u32, &dmabuf_planes)?;
+
+            // Planes mapped so far are unmapped when `buffer` is dropped on error.
+            let mut buffer = Buffer::new(Vec::with_capacity(dmabuf_planes.len()));
+            for (p, dmabuf) in dmabuf_planes.into_iter().enumerate() {
+                // The size of a dma-buf can be queried by seeking to its end.
+                let size = unsafe { libc::lseek(dmabuf.as_raw_fd(), 0, libc::SEEK_END) };
+                if size < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let size = u32::try_from(size).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("dma-buf {i} (plane {p}) is too large"),
+                    )
+                })?;
+                let required = buf.plane_length(p);
+                if size < required {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "dma-buf {i} (plane {p}) is too small (driver requires {required} bytes, buffer has {size})",
+                        ),
+                    ));
+                }
+
+                // Not every exporter allows mapping its buffers, so a failure here is not fatal. The
+                // buffer just won't be accessible to the CPU.
+                let ptr = unsafe {
+                    libc::mmap(
+                        ptr::null_mut(),
+                        size as _,
+                        libc::PROT_READ | libc::PROT_WRITE,
+                        libc::MAP_SHARED,
+                        dmabuf.as_raw_fd(),
+                        0,
+                    )
+                };
+                let (ptr, length) = if ptr == libc::MAP_FAILED {
+                    log::debug!(
+                        "failed to `mmap` dma-buf {i} (plane {p}): {}",
+                        io::Error::last_os_error(),
+                    );
+                    (NonNull::<c_void>::dangling().as_ptr(), 0)
+                } else {
+                    (ptr, size)
+                };
+
+                buffer.planes.push(Plane {
+                    ptr,
+                    length,
+                    alloc: AllocType::DmaBuf { fd: dmabuf, size },
+                    exported: OnceLock::new(),
+                });
+            }
+
+            buffers.buffers.push(Some(Arc::new(buffer)));
+        }
+
+        Ok(buffers)
+    }
+
+    /// Exports plane `plane` of buffer `index` as a dma-buf via `VIDIOC_EXPBUF`.
+    fn export(fd: c_int, buf_type: BufType, index: u32, plane: u32) -> io::Result<OwnedFd> {
+        let mut exp: raw::ExportBuffer = unsafe { mem::zeroed() };
+        exp.type_ = buf_type;
+        exp.index = index;
+        exp.plane = plane;
+        exp.flags = (libc::O_RDWR | libc::O_CLOEXEC) as u32;
+
+        unsafe {
+            raw::VIDIOC_EXPBUF.ioctl(&fd, &mut exp)?;
+            Ok(OwnedFd::from_raw_fd(exp.fd))
+        }
+    }
+
+    /// Returns dma-buf file descriptors for the planes of all buffers, in order of their buffer
+    /// index, and `None` for buffers that have been removed.
+    fn export_all(&self, fd: c_int, buf_type: BufType) -> io::Result<Vec<Option<Vec<OwnedFd>>>> {
+        self.buffers
+            .iter()
+            .enumerate()
+            .map(|(i, buffer)| {
+                let Some(buffer) = buffer else {
+                    return Ok(None);
+                };
+                buffer.ensure_exported(fd, buf_type, i as u32)?;
+                buffer
+                    .planes
+                    .iter()
+                    .map(|plane| plane.dmabuf_fd().unwrap().try_clone_to_owned())
+                    .collect::<io::Result<_>>()
+                    .map(Some)
+            })
+            .collect()
+    }
+}
+
+/// A page-aligned memory buffer allocated by the application.
+///
+/// [`UserBuffer`]s are used for `USERPTR` streaming I/O, where the
+/// driver reads from or writes to memory owned by the application instead of buffers it allocated
+/// itself. They can be passed to [`VideoCaptureDevice::into_userptr_stream`] and
+/// [`VideoOutputDevice::into_userptr_stream`].
+///
+/// Dereferences to a byte slice.
+///
+/// [`VideoCaptureDevice::into_userptr_stream`]: crate::VideoCaptureDevice::into_userptr_stream
+/// [`VideoOutputDevice::into_userptr_stream`]: crate::VideoOutputDevice::into_userptr_stream
+pub struct UserBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+    layout: Layout,
+}
+
+unsafe impl Send for UserBuffer {}
+unsafe impl Sync for UserBuffer {}
+
+impl UserBuffer {
+    /// Allocates a zero-initialized, page-aligned buffer of `len` bytes.
+    ///
+    /// The allocation is padded to a multiple of the page size, so that no other data shares a page
+    /// with the buffer.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `len` is 0 or larger than `u32::MAX`, and abort the process if the
+    /// allocation fails.
+    pub fn new(len: usize) -> Self {
+        assert_ne!(len, 0, "cannot allocate an empty `UserBuffer`");
+        assert!(
+            len <= u32::MAX as usize,
+            "`UserBuffer` length exceeds `u32::MAX`"
+        );
+
+        let page_size = page_size();
+        let size = len.div_ceil(page_size) * page_size;
+        let layout = Layout::from_size_align(size, page_size).unwrap();
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let Some(ptr) = NonNull::new(ptr) else {
+            alloc::handle_alloc_error(layout);
+        };
+
+        Self { ptr, len, layout }
+    }
+}
+
+impl Drop for UserBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            alloc::dealloc(self.ptr.as_ptr(), self.layout);
+        }
+    }
+}
+
+impl Deref for UserBuffer {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for UserBuffer {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Sets or clears the `O_NONBLOCK` flag of `fd`.
+#[cfg_attr(not(any(feature = "tokio", feature = "async-io")), allow(dead_code))]
+fn set_nonblocking(fd: c_int, nonblocking: bool) -> io::Result<()> {
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let flags = if nonblocking {
+            flags | libc::O_NONBLOCK
+        } else {
+            flags & !libc::O_NONBLOCK
+        };
+        if libc::fcntl(fd, libc::F_SETFL, flags) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Runtime-independent parts of the async stream wrappers in the `tokio` and `async_io` modules.
+///
+/// Each runtime only implements [`Reactor`] for its I/O source type, everything else is shared.
+#[cfg(any(feature = "tokio", feature = "async-io"))]
+mod reactor {
+    use std::future::poll_fn;
+    use std::io;
+    use std::task::{ready, Context, Poll};
+
+    use super::{Frame, OwnedReadBuffer, ReadBufferView, ReadStream, WriteBufferView, WriteStream};
+
+    /// The readiness an operation waits for.
+    #[derive(Clone, Copy)]
+    pub(super) enum Interest {
+        Readable,
+        Writable,
+    }
+
+    /// A stream registered with the reactor of an async runtime.
+    pub(super) trait Reactor {
+        type Stream;
+
+        fn stream(&self) -> &Self::Stream;
+
+        fn stream_mut(&mut self) -> &mut Self::Stream;
+
+        /// Calls `op` until it no longer fails with [`io::ErrorKind::WouldBlock`], waiting for the
+        /// stream to become ready in between.
+        fn poll_io<T>(
+            &mut self,
+            cx: &mut Context<'_>,
+            interest: Interest,
+            op: impl FnMut(&mut Self::Stream) -> io::Result<T>,
+        ) -> Poll<io::Result<T>>;
+    }
+
+    // All operations below are cancellation safe: a buffer is only dequeued in the same poll that
+    // completes the future.
+
+    pub(super) async fn dequeue<T>(
+        r: &mut impl Reactor<Stream = ReadStream>,
+        cb: impl FnOnce(ReadBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let buf = poll_fn(|cx| r.poll_io(cx, Interest::Readable, |s| s.queue.dequeue())).await?;
+        r.stream_mut().finish_dequeue(buf, false, cb)
+    }
+
+    pub(super) async fn dequeue_owned(
+        r: &mut impl Reactor<Stream = ReadStream>,
+    ) -> io::Result<OwnedReadBuffer> {
+        let buf = poll_fn(|cx| r.poll_io(cx, Interest::Readable, |s| s.queue.dequeue())).await?;
+        Ok(OwnedReadBuffer::new(r.stream().queue.clone(), &buf))
+    }
+
+    /// Implements `Stream::poll_next` for the async read streams.
+    ///
+    /// Errors are not transient (dequeueing from a paused stream, or one without queued buffers,
+    /// fails every time), so the stream ends after the first error by setting `finished`.
+    pub(super) fn poll_next_frame(
+        r: &mut impl Reactor<Stream = ReadStream>,
+        finished: &mut bool,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<Frame>>> {
+        if *finished {
+            return Poll::Ready(None);
+        }
+
+        let res =
+            ready!(r.poll_io(cx, Interest::Readable, |s| s.queue.dequeue())).and_then(|buf| {
+                r.stream_mut()
+                    .finish_dequeue(buf, false, |view| Ok(view.to_frame()))
+            });
+        *finished = res.is_err();
+        Poll::Ready(Some(res))
+    }
+
+    pub(super) async fn enqueue<T>(
+        r: &mut impl Reactor<Stream = WriteStream>,
+        cb: impl FnOnce(WriteBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let buf_index = match r.stream().queue.find_unqueued() {
+            Some(i) => i,
+            None => poll_fn(|cx| r.poll_io(cx, Interest::Writable, |s| s.queue.dequeue()))
+                .await?
+                .index(),
+        };
+
+        r.stream_mut().fill_and_enqueue(buf_index, cb)
+    }
+
+    pub(super) async fn drain(r: &mut impl Reactor<Stream = WriteStream>) -> io::Result<()> {
+        while r.stream().queue.any_queued() {
+            poll_fn(|cx| r.poll_io(cx, Interest::Writable, |s| s.queue.dequeue())).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Configures the buffers of a stream.
+///
+/// Passed to the `into_stream_with` methods of the device types, for example
+/// [`VideoCaptureDevice::into_stream_with`]. The `into_stream` methods use the default
+/// configuration, as returned by [`StreamConfig::new`].
+///
+/// [`VideoCaptureDevice::into_stream_with`]: crate::VideoCaptureDevice::into_stream_with
+#[derive(Debug, Clone)]
+pub struct StreamConfig {
+    buffer_count: Option<u32>,
+    memory: Memory,
+    non_coherent: bool,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamConfig {
+    /// Creates the default configuration.
+    ///
+    /// By default, the driver allocates the buffers (using [`Memory::MMAP`]), and enough buffers
+    /// are requested to satisfy the driver's minimum buffer count (but at least 2).
+    pub fn new() -> Self {
+        Self {
+            buffer_count: None,
+            memory: Memory::MMAP,
+            non_coherent: false,
+        }
+    }
+
+    /// Sets the number of buffers to request.
+    ///
+    /// More buffers make it less likely that frames are dropped when the application is slow to
+    /// dequeue them, but increase latency and memory usage.
+    ///
+    /// If the driver reports a minimum number of buffers it needs (via the
+    /// [`Cid::MIN_BUFFERS_FOR_CAPTURE`] or [`Cid::MIN_BUFFERS_FOR_OUTPUT`] controls), at least that
+    /// many buffers are requested. The driver may also adjust the count itself. The number of
+    /// buffers that were actually allocated can be retrieved with [`ReadStream::buffer_count`] or
+    /// [`WriteStream::buffer_count`].
+    pub fn buffer_count(&mut self, count: u32) -> &mut Self {
+        self.buffer_count = Some(count);
+        self
+    }
+
+    /// Sets the memory type of the buffers.
+    ///
+    /// Only [`Memory::MMAP`] (buffers are allocated by the driver) and [`Memory::USERPTR`] (buffers
+    /// are allocated by this library, with the size the driver requires) are supported here.
+    /// Streams that use application-provided buffers or dma-bufs have to be created with the
+    /// `into_userptr_stream` and `into_dmabuf_stream` methods instead.
+    pub fn memory(&mut self, memory: Memory) -> &mut Self {
+        self.memory = memory;
+        self
+    }
+
+    /// Sets whether the driver should allocate non-coherent buffers.
+    ///
+    /// Non-coherent memory does not have to be kept coherent between the CPU and the device at all
+    /// times, which avoids expensive uncached mappings on some platforms. Cache maintenance is then
+    /// performed when buffers are queued and dequeued, and can be skipped where it isn't needed
+    /// with [`ReadStream::set_cache_hints`] and [`WriteStream::set_cache_hints`].
+    ///
+    /// This is only a hint: it only applies to [`Memory::MMAP`] buffers, and drivers that don't
+    /// support it will allocate coherent buffers instead.
+    pub fn non_coherent(&mut self, non_coherent: bool) -> &mut Self {
+        self.non_coherent = non_coherent;
+        self
+    }
+
+    /// Determines the number of buffers to request, taking the driver's minimum into account.
+    fn resolve_buffer_count(&self, fd: c_int, buf_type: BufType) -> u32 {
+        let count = self.buffer_count.unwrap_or(DEFAULT_BUFFER_COUNT);
+        match min_buffers(fd, buf_type) {
+            Some(min) if min > count => {
+                log::debug!(
+                    "driver requires at least {min} buffers, requesting {min} instead of {count}"
+                );
+                min
+            }
+            _ => count,
+        }
+    }
+}
+
+/// Returns the minimum number of buffers the driver requires, if it reports one.
+fn min_buffers(fd: c_int, buf_type: BufType) -> Option<u32> {
+    let id = if buf_type.is_output() {
+        Cid::MIN_BUFFERS_FOR_OUTPUT
+    } else {
+        Cid::MIN_BUFFERS_FOR_CAPTURE
+    };
+    let mut control = Control { id, value: 0 };
+    unsafe {
+        // Most drivers don't implement these controls, so errors are expected.
+        raw::VIDIOC_G_CTRL.ioctl(&fd, &mut control).ok()?;
+    }
+    u32::try_from(control.value).ok()
+}
+
+/// Returns the capabilities of the queue of type `buf_type`, without allocating any buffers.
+///
+/// This issues `VIDIOC_REQBUFS` with a count of 0, which frees any buffers that were allocated
+/// before, so it must not be used on a queue that is in use.
+pub(crate) fn queue_capabilities(fd: c_int, buf_type: BufType) -> io::Result<BufCap> {
+    Ok(Buffers::request(fd, buf_type, Memory::MMAP, 0, 0)?.capabilities)
+}
+
+/// The buffer queue of a device, shared by [`ReadStream`] and [`WriteStream`].
+///
+/// The queue is reference-counted, so that [`OwnedReadBuffer`]s can outlive the stream they were
+/// dequeued from.
+struct Queue {
+    // NB: closing the file first makes the driver release all buffers before we free them
+    file: File,
+    /// Only written to when buffers are created or removed.
+    buffers: RwLock<Buffers>,
+    buf_type: BufType,
+    mem_type: Memory,
+    /// Cache hint [`BufFlag`]s passed to the driver whenever a buffer is queued or prepared.
+    cache_hints: AtomicU32,
+    /// Set once a buffer has been queued in a request via [`ReadStream::enqueue_in_request`].
+    ///
+    /// The driver rejects mixing buffers queued with and without requests, so buffers are no
+    /// longer enqueued automatically afterwards.
+    uses_requests: AtomicBool,
+    /// Whether the queue is streaming.
+    ///
+    /// This lock is held while enqueuing buffers, so that an [`OwnedReadBuffer`] that is dropped
+    /// concurrently with the stream cannot enqueue its buffer after the stream was closed.
+    state: Mutex<StreamState>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamState {
+    /// Streaming is off. This is the initial state, and the state after pausing the stream.
+    Off,
+    /// Streaming is on.
+    On,
+    /// The stream has been dropped, buffers are no longer enqueued.
+    Closed,
+}
+
+impl Queue {
+    fn new(file: File, buffers: Buffers, buf_type: BufType, mem_type: Memory) -> Arc<Self> {
+        Arc::new(Self {
+            file,
+            buffers: RwLock::new(buffers),
+            buf_type,
+            mem_type,
+            cache_hints: AtomicU32::new(0),
+            uses_requests: AtomicBool::new(false),
+            state: Mutex::new(StreamState::Off),
+        })
+    }
+
+    fn fd(&self) -> c_int {
+        self.file.as_raw_fd()
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, StreamState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn buffers(&self) -> RwLockReadGuard<'_, Buffers> {
+        self.buffers.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn buffers_mut(&self) -> RwLockWriteGuard<'_, Buffers> {
+        self.buffers.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns buffer `index`, which must exist.
+    fn buffer(&self, index: u32) -> Arc<Buffer> {
+        match self.buffers().get(index) {
+            Some(buffer) => buffer.clone(),
+            None => panic!("buffer {index} does not exist"),
+        }
+    }
+
+    /// Enqueues buffer `index` again after the application is done with it.
+    ///
+    /// If the queue uses requests, the buffer is only released, so that it can be queued in the
+    /// next request.
+    fn enqueue(&self, index: u32) -> io::Result<()> {
+        if self.uses_requests.load(Ordering::Relaxed) {
+            self.buffer(index).held.store(false, Ordering::Relaxed);
+            return Ok(());
+        }
+        self.enqueue_with(index, |_| {})
+    }
+
+    /// Enqueues buffer `index`, letting `fill` set additional fields of the `v4l2_buffer`.
+    fn enqueue_with(&self, index: u32, fill: impl FnOnce(&mut RawBuffer)) -> io::Result<()> {
+        let state = self.lock_state();
+        if *state == StreamState::Closed {
+            return Ok(());
+        }
+
+        let buffer = self.buffer(index);
+        let mut buf = self.qbuf(index, &buffer);
+        fill(&mut buf);
+        self.enqueue_locked(buf, &buffer)
+    }
+
+    /// Enqueues all buffers that are neither queued nor held by an [`OwnedReadBuffer`].
+    ///
+    /// Does nothing if the queue uses requests.
+    fn enqueue_all(&self) -> io::Result<()> {
+        if self.uses_requests.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let _state = self.lock_state();
+        for (i, buffer) in self.buffers().iter() {
+            if !buffer.queued.load(Ordering::Relaxed) && !buffer.held.load(Ordering::Relaxed) {
+                self.enqueue_locked(self.qbuf(i, buffer), buffer)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Enqueues `buffer`, described by `buf`. The `state` lock must be held by the caller.
+    fn enqueue_locked(&self, mut buf: RawBuffer, buffer: &Buffer) -> io::Result<()> {
+        unsafe {
+            buf.ioctl(raw::VIDIOC_QBUF, self.fd())?;
+        }
+
+        buffer.queued.store(true, Ordering::Relaxed);
+        buffer.held.store(false, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Prepares the `v4l2_buffer` passed to `VIDIOC_QBUF` and `VIDIOC_PREPARE_BUF` for `buffer`.
+    fn qbuf(&self, index: u32, buffer: &Buffer) -> RawBuffer {
+        let mut buf = RawBuffer::new(self.buf_type, self.mem_type, index);
+        buf.buf.flags = BufFlag::from_bits_truncate(self.cache_hints.load(Ordering::Relaxed));
+        buffer.fill_qbuf(&mut buf);
+        buf
+    }
+
+    /// Prepares buffer `index` via `VIDIOC_PREPARE_BUF`, so that queuing it later is cheaper.
+    fn prepare(&self, index: u32) -> io::Result<()> {
+        let buffer = match self.buffers().get(index) {
+            Some(buffer) => buffer.clone(),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("buffer {index} does not exist"),
+                ));
+            }
+        };
+        if buffer.queued.load(Ordering::Relaxed) || buffer.held.load(Ordering::Relaxed) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("buffer {index} is in use and cannot be prepared"),
+            ));
+        }
+
+        let mut buf = self.qbuf(index, &buffer);
+        unsafe {
+            buf.ioctl(raw::VIDIOC_PREPARE_BUF, self.fd())?;
+        }
+
+        Ok(())
+    }
+
+    fn capabilities(&self) -> BufCap {
+        self.buffers().capabilities
+    }
+
+    fn set_cache_hints(&self, hints: BufFlag) -> io::Result<()> {
+        let allowed = BufFlag::NO_CACHE_INVALIDATE | BufFlag::NO_CACHE_CLEAN;
+        if !allowed.contains(hints) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} are not cache hints", hints - allowed),
+            ));
+        }
+        if !hints.is_empty()
+            && !self
+                .capabilities()
+                .contains(BufCap::SUPPORTS_MMAP_CACHE_HINTS)
+        {
+            log::debug!("driver does not support cache hints, they will be ignored");
+        }
+
+        self.cache_hints.store(hints.bits(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn dequeue(&self) -> io::Result<RawBuffer> {
+        if *self.lock_state() != StreamState::On {
+            return Err(io::Error::other("stream is paused"));
+        }
+        if !self.any_queued() {
+            // `VIDIOC_DQBUF` would block forever, since no buffer can become ready.
+            return Err(io::Error::other("no buffers are queued"));
+        }
+
+        let mut buf = RawBuffer::new(self.buf_type, self.mem_type, 0);
+
+        unsafe {
+            buf.ioctl(raw::VIDIOC_DQBUF, self.fd())?;
+        }
+
+        self.buffer(buf.index())
+            .queued
+            .store(false, Ordering::Relaxed);
+
+        Ok(buf)
+    }
+
+    /// Dequeues a buffer, waiting at most `timeout` for one to become available.
+    ///
+    /// If no buffer becomes available in time, an error of kind `kind` is returned.
+    fn dequeue_timeout(&self, timeout: Duration, kind: io::ErrorKind) -> io::Result<RawBuffer> {
+        // `poll` reports an error when no buffers are queued, so we have to check that first.
+        if self.any_queued() && !self.poll(timeout)? {
+            return Err(kind.into());
+        }
+
+        self.dequeue()
+    }
+
+    fn any_queued(&self) -> bool {
+        self.buffers()
+            .iter()
+            .any(|(_, b)| b.queued.load(Ordering::Relaxed))
+    }
+
+    /// Returns the index of a buffer that is neither queued nor held, if there is one.
+    fn find_unqueued(&self) -> Option<u32> {
+        self.buffers()
+            .iter()
+            .find(|(_, b)| !b.queued.load(Ordering::Relaxed) && !b.held.load(Ordering::Relaxed))
+            .map(|(i, _)| i)
+    }
+
+    fn buffer_count(&self) -> usize {
+        self.buffers().iter().count()
+    }
+
+    /// Allocates `count` additional buffers, sized for `format` (or the current format if `None`).
+    fn create_buffers(&self, count: u32, format: Option<raw::Format>) -> io::Result<Range<u32>> {
+        let format = match format {
+            Some(format) => {
+                if format.type_ != self.buf_type {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "format for buffer type {:?} cannot be used for a {:?} stream",
+                            format.type_, self.buf_type,
+                        ),
+                    ));
+                }
+                format
+            }
+            None => unsafe {
+                let mut format = raw::Format {
+                    type_: self.buf_type,
+                    ..mem::zeroed()
+                };
+                raw::VIDIOC_G_FMT.ioctl(&self.file, &mut format)?;
+                format
+            },
+        };
+
+        self.buffers_mut()
+            .create(self.fd(), self.mem_type, count, format)
+    }
+
+    fn remove_buffers(&self, indices: Range<u32>) -> io::Result<()> {
+        if indices.is_empty() {
+            return Ok(());
+        }
+
+        self.buffers_mut().remove(self.fd(), self.buf_type, indices)
+    }
+
+    /// Queries the image size of each plane of the negotiated format (via `VIDIOC_G_FMT`).
+    ///
+    /// Returns zeroes for buffer types whose format doesn't specify an image size.
+    fn image_sizes(&self) -> io::Result<[u32; VIDEO_MAX_PLANES]> {
+        let mut sizes = [0; VIDEO_MAX_PLANES];
+        let mut format = raw::Format {
+            type_: self.buf_type,
+            ..unsafe { mem::zeroed() }
+        };
+        unsafe {
+            raw::VIDIOC_G_FMT.ioctl(&self.file, &mut format)?;
+            match self.buf_type {
+                BufType::VIDEO_CAPTURE | BufType::VIDEO_OUTPUT => {
+                    sizes[0] = format.fmt.pix.sizeimage;
+                }
+                BufType::VIDEO_CAPTURE_MPLANE | BufType::VIDEO_OUTPUT_MPLANE => {
+                    let pix_mp = format.fmt.pix_mp;
+                    for (size, plane) in sizes.iter_mut().zip(&pix_mp.plane_fmt) {
+                        *size = plane.sizeimage;
+                    }
+                }
+                BufType::META_CAPTURE | BufType::META_OUTPUT => {
+                    sizes[0] = format.fmt.meta.buffersize;
+                }
+                _ => {}
+            }
+        }
+        Ok(sizes)
+    }
+
+    /// Waits until a buffer can be dequeued without blocking, or until `timeout` has elapsed.
+    ///
+    /// Returns `false` if the timeout elapsed first. If the driver signals an error condition,
+    /// `true` is returned, so that the following `VIDIOC_DQBUF` can report the error.
+    fn poll(&self, timeout: Duration) -> io::Result<bool> {
+        let mut pollfd = self.pollfd();
+        // Timeouts too large to be represented wait indefinitely.
+        poll_until(
+            slice::from_mut(&mut pollfd),
+            Instant::now().checked_add(timeout),
+        )
+    }
+
+    /// Returns a `pollfd` that waits for a buffer that can be dequeued.
+    fn pollfd(&self) -> libc::pollfd {
+        let events = if self.buf_type.is_output() {
+            libc::POLLOUT
+        } else {
+            libc::POLLIN
+        };
+        libc::pollfd {
+            fd: self.fd(),
+            events,
+            revents: 0,
+        }
+    }
+
+    fn is_streaming(&self) -> bool {
+        *self.lock_state() == StreamState::On
+    }
+
+    /// Starts streaming.
+    ///
+    /// This function can potentially block for a noticeable amount of time.
+    fn stream_on(&self) -> io::Result<()> {
+        let mut state = self.lock_state();
+        unsafe {
+            let buf_type = self.buf_type.0 as c_int;
+            raw::VIDIOC_STREAMON.ioctl(&self.file, &buf_type)?;
+        }
+        *state = StreamState::On;
+
+        Ok(())
+    }
+
+    /// Stops streaming.
+    ///
+    /// This returns all queued buffers to the application, discarding their contents.
+    fn stream_off(&self) -> io::Result<()> {
+        let mut state = self.lock_state();
+        self.stream_off_locked()?;
+        *state = StreamState::Off;
+        Ok(())
+    }
+
+    /// Turns off streaming for good.
+    ///
+    /// Buffers released by [`OwnedReadBuffer`]s afterwards are not enqueued again.
+    fn close(&self) -> io::Result<()> {
+        let mut state = self.lock_state();
+        *state = StreamState::Closed;
+        self.stream_off_locked()
+    }
+
+    fn stream_off_locked(&self) -> io::Result<()> {
+        unsafe {
+            let buf_type = self.buf_type.0 as c_int;
+            raw::VIDIOC_STREAMOFF.ioctl(&self.file, &buf_type)?;
+        }
+
+        for (_, b) in self.buffers().iter() {
+            b.queued.store(false, Ordering::Relaxed);
+        }
+
+        Ok(())
+    }
+
+    fn export_all(&self) -> io::Result<Vec<Option<Vec<OwnedFd>>>> {
+        self.buffers().export_all(self.fd(), self.buf_type)
+    }
+
+    /// Exports all buffers, requiring each of them to consist of a single plane.
+    fn export_single_planar(&self) -> io::Result<Vec<Option<OwnedFd>>> {
+        self.export_all()?
+            .into_iter()
+            .map(|planes| planes.map(single_plane).transpose())
+            .collect()
+    }
+}
+
+/// Returns the only dma-buf of an exported buffer.
+fn single_plane(planes: Vec<OwnedFd>) -> io::Result<OwnedFd> {
+    let count = planes.len();
+    let [fd]: [OwnedFd; 1] = planes.try_into().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot export buffer with {count} planes as a single dma-buf"),
+        )
+    })?;
+    Ok(fd)
+}
+
+/// Waits for any of `fds` to become ready, or until `deadline` has passed (if there is one).
+///
+/// Returns `false` if the deadline passed first.
+pub(crate) fn poll_until(fds: &mut [libc::pollfd], deadline: Option<Instant>) -> io::Result<bool> {
+    loop {
+        let millis = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                // Round up, so that short timeouts don't turn into busy loops.
+                remaining
+                    .as_nanos()
+                    .div_ceil(1_000_000)
+                    .min(c_int::MAX as u128) as c_int
+            }
+            None => -1,
+        };
+
+        match unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, millis) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            0 => return Ok(false),
+            _ => return Ok(true),
+        }
+    }
+}
+
+/// A stream that reads data from a V4L2 device.
+pub struct ReadStream {
+    queue: Arc<Queue>,
+}
+
+impl ReadStream {
+    pub(crate) fn new(file: File, buf_type: BufType, config: &StreamConfig) -> io::Result<Self> {
+        let fd = file.as_raw_fd();
+        let buffers = Buffers::allocate(fd, buf_type, config)?;
+
+        Self::from_buffers(file, buf_type, config.memory, buffers)
+    }
+
+    pub(crate) fn with_user_buffers(
+        file: File,
+        buf_type: BufType,
+        user_buffers: Vec<Vec<UserBuffer>>,
+    ) -> io::Result<Self> {
+        let fd = file.as_raw_fd();
+        let buffers = Buffers::userptr(fd, buf_type, user_buffers)?;
+
+        Self::from_buffers(file, buf_type, Memory::USERPTR, buffers)
+    }
+
+    pub(crate) fn with_dmabufs(
+        file: File,
+        buf_type: BufType,
+        dmabufs: Vec<Vec<OwnedFd>>,
+    ) -> io::Result<Self> {
+        let fd = file.as_raw_fd();
+        let buffers = Buffers::dmabuf(fd, buf_type, dmabufs)?;
+
+        Self::from_buffers(file, buf_type, Memory::DMABUF, buffers)
+    }
+
+    fn from_buffers(
+        file: File,
+        buf_type: BufType,
+        mem_type: Memory,
+        buffers: Buffers,
+    ) -> io::Result<Self> {
+        let this = Self {
+            queue: Queue::new(file, buffers, buf_type, mem_type),
+        };
+        this.queue.enqueue_all()?;
+        this.queue.stream_on()?;
+
+        Ok(this)
+    }
+
+    /// Pauses the stream (via `VIDIOC_STREAMOFF`).
+    ///
+    /// The device stops capturing, and all buffers are returned from the driver, discarding any
+    /// frames that were captured but not yet dequeued. The buffers themselves stay allocated, so
+    /// the stream can be continued cheaply with [`ReadStream::resume`].
+    ///
+    /// While the stream is paused, attempts to dequeue a buffer fail with an error.
+    pub fn pause(&mut self) -> io::Result<()> {
+        self.queue.stream_off()
+    }
+
+    /// Resumes a stream paused with [`ReadStream::pause`] (via `VIDIOC_STREAMON`).
+    ///
+    /// All buffers are enqueued again, except for those currently held as [`OwnedReadBuffer`]s,
+    /// which are enqueued when dropped, and except if the stream uses requests (see
+    /// [`ReadStream::enqueue_in_request`]). Does nothing if the stream isn't paused.
+    pub fn resume(&mut self) -> io::Result<()> {
+        if self.queue.is_streaming() {
+            return Ok(());
+        }
+
+        self.queue.enqueue_all()?;
+        self.queue.stream_on()
+    }
+
+    /// Returns whether the stream is paused.
+    pub fn is_paused(&self) -> bool {
+        !self.queue.is_streaming()
+    }
+
+    /// Returns the number of buffers the driver allocated for this stream.
+    ///
+    /// This can differ from the number requested via [`StreamConfig::buffer_count`], since drivers
+    /// may require more buffers or be unable to allocate as many as requested. Buffers added or
+    /// removed with [`ReadStream::create_buffers`] and [`ReadStream::remove_buffers`] are taken into account.
+    pub fn buffer_count(&self) -> usize {
+        self.queue.buffer_count()
+    }
+
+    /// Allocates `count` additional buffers for this stream (via `VIDIOC_CREATE_BUFS`).
+    ///
+    /// The new buffers are sized for the current format of the stream. Unless the stream is paused, they are
+    /// handed to the driver right away.
+    ///
+    /// Returns the range of indices of the new buffers. The driver may allocate fewer buffers than
+    /// requested. Streams using imported dma-bufs cannot be extended.
+    pub fn create_buffers(&mut self, count: u32) -> io::Result<Range<u32>> {
+        self.create_buffers_impl(count, None)
+    }
+
+    /// Like [`ReadStream::create_buffers`], but sizes the new buffers for `format`.
+    ///
+    /// The format is not applied to the device. This can be used to allocate buffers ahead of an
+    /// upcoming format change (for example, to a higher resolution), so that they are ready when it
+    /// happens. The variant of `format` has to match the buffer type of the stream.
+    pub fn create_buffers_for_format(
+        &mut self,
+        count: u32,
+        format: &Format,
+    ) -> io::Result<Range<u32>> {
+        self.create_buffers_impl(count, Some(format.to_raw()))
+    }
+
+    /// Frees the buffers with the given `indices` (via `VIDIOC_REMOVE_BUFS`).
+    ///
+    /// This requires Linux 6.10 or later, and a driver that supports it.
+    ///
+    /// Buffers that are owned by the driver cannot be removed, and neither can buffers held by an
+    /// [`OwnedReadBuffer`]. Since the driver owns all other buffers while the stream is running,
+    /// the stream has to be paused with [`ReadStream::pause`] first. [`ReadStream::resume`] then
+    /// continues with the remaining buffers.
+    pub fn remove_buffers(&mut self, indices: Range<u32>) -> io::Result<()> {
+        self.queue.remove_buffers(indices)
+    }
+
+    /// Returns the capabilities of the stream's buffer queue, as reported by the driver.
+    pub fn capabilities(&self) -> BufCap {
+        self.queue.capabilities()
+    }
+
+    /// Prepares buffer `index` for being queued (via `VIDIOC_PREPARE_BUF`).
+    ///
+    /// This performs the work the driver would otherwise do when the buffer is queued, like cache
+    /// maintenance or pinning application-allocated memory, ahead of time. Buffers of capture streams are
+    /// queued whenever the stream is running, so this is mostly useful for new buffers added to a
+    /// paused stream with [`ReadStream::create_buffers`].
+    ///
+    /// Buffers owned by the driver cannot be prepared, and preparing a buffer twice fails.
+    pub fn prepare_buffer(&mut self, index: u32) -> io::Result<()> {
+        self.queue.prepare(index)
+    }
+
+    /// Sets cache hints that are passed to the driver whenever a buffer is queued or prepared.
+    ///
+    /// `hints` may contain [`BufFlag::NO_CACHE_INVALIDATE`] and [`BufFlag::NO_CACHE_CLEAN`]; other
+    /// flags are rejected with an error of kind [`io::ErrorKind::InvalidInput`].
+    ///
+    /// [`BufFlag::NO_CACHE_INVALIDATE`] tells the driver that the CPU will not read a buffer after
+    /// it was filled (for example, because it only inspects part of it, or passes it on as a
+    /// dma-buf), so that the CPU caches don't need to be invalidated. [`BufFlag::NO_CACHE_CLEAN`]
+    /// tells the driver that the CPU did not write to the buffer.
+    ///
+    /// Hints are only honored for non-coherent `MMAP` buffers (see [`StreamConfig::non_coherent`])
+    /// of queues that report [`BufCap::SUPPORTS_MMAP_CACHE_HINTS`], and are ignored otherwise.
+    pub fn set_cache_hints(&mut self, hints: BufFlag) -> io::Result<()> {
+        self.queue.set_cache_hints(hints)
+    }
+
+    /// Subscribes to events of type `event_type`.
+    ///
+    /// See [`Device::subscribe_event`][crate::Device::subscribe_event].
+    pub fn subscribe_event(
+        &self,
+        event_type: EventType,
+        id: u32,
+        flags: EventSubscriptionFlags,
+    ) -> io::Result<()> {
+        event::subscribe(self.queue.fd(), event_type, id, flags)
+    }
+
+    /// Unsubscribes from events previously subscribed to with [`ReadStream::subscribe_event`].
+    pub fn unsubscribe_event(&self, event_type: EventType, id: u32) -> io::Result<()> {
+        event::unsubscribe(self.queue.fd(), event_type, id)
+    }
+
+    /// Unsubscribes from all events.
+    pub fn unsubscribe_all_events(&self) -> io::Result<()> {
+        event::unsubscribe(self.queue.fd(), EventType::ALL, 0)
+    }
+
+    /// Waits for a subscribed event to occur, and dequeues it.
+    ///
+    /// This blocks even if the device was opened in non-blocking mode.
+    pub fn dequeue_event(&self) -> io::Result<DequeuedEvent> {
+        event::dequeue(self.queue.fd(), None, io::ErrorKind::TimedOut)
+    }
+
+    /// Dequeues a pending event without blocking.
+    ///
+    /// If no event is pending, an error of kind [`io::ErrorKind::WouldBlock`] is returned.
+    pub fn try_dequeue_event(&self) -> io::Result<DequeuedEvent> {
+        event::dequeue_timeout(self.queue.fd(), Duration::ZERO, io::ErrorKind::WouldBlock)
+    }
+
+    /// Waits at most `timeout` for a subscribed event to occur, and dequeues it.
+    ///
+    /// If no event occurs in time, an error of kind [`io::ErrorKind::TimedOut`] is returned.
+    pub fn dequeue_event_timeout(&self, timeout: Duration) -> io::Result<DequeuedEvent> {
+        event::dequeue_timeout(self.queue.fd(), timeout, io::ErrorKind::TimedOut)
+    }
+
+    fn create_buffers_impl(
+        &mut self,
+        count: u32,
+        format: Option<raw::Format>,
+    ) -> io::Result<Range<u32>> {
+        let indices = self.queue.create_buffers(count, format)?;
+        if self.queue.is_streaming() {
+            self.queue.enqueue_all()?;
+        }
+        Ok(indices)
+    }
+
+    /// Exports all buffers of this stream as dma-buf file descriptors (via `VIDIOC_EXPBUF`).
+    ///
+    /// The returned file descriptors are in order of their buffer index, so the dma-buf filled by a
+    /// call to [`ReadStream::dequeue`] is the one at [`ReadBufferView::index`].
+    ///
+    /// For streams that use imported dma-bufs, duplicates of the imported file descriptors are
+    /// returned. Streams using application-allocated buffers cannot be exported. Streams whose
+    /// buffers consist of more than one plane have to use [`ReadStream::export_planes`] instead.
+    ///
+    /// Indices of buffers that have been freed with [`ReadStream::remove_buffers`] are `None`.
+    pub fn export_buffers(&mut self) -> io::Result<Vec<Option<OwnedFd>>> {
+        self.queue.export_single_planar()
+    }
+
+    /// Exports every plane of every buffer of this stream as a dma-buf file descriptor.
+    ///
+    /// The outer [`Vec`] is indexed by the buffer index, the inner one by the plane index. Indices
+    /// of buffers that have been freed with [`ReadStream::remove_buffers`] are `None`.
+    pub fn export_planes(&mut self) -> io::Result<Vec<Option<Vec<OwnedFd>>>> {
+        self.queue.export_all()
+    }
+
+    /// Dequeues a buffer, passes it to `cb`, then enqueues it again.
+    ///
+    /// This blocks until the driver has filled a buffer. If the device was opened in non-blocking
+    /// mode (see [`OpenOptions::nonblocking`]), an error of kind [`io::ErrorKind::WouldBlock`] is
+    /// returned instead.
+    ///
+    /// If `cb` returns an error, this function will still try to enqueue the buffer again. If that
+    /// fails, the error that occurred during enqueuing will be returned, if it succeeds, the error
+    /// returned by `cb` will be returned.
+    ///
+    /// [`OpenOptions::nonblocking`]: crate::OpenOptions::nonblocking
+    pub fn dequeue<T>(
+        &mut self,
+        cb: impl FnOnce(ReadBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        self.dequeue_impl(false, cb)
+    }
+
+    /// Dequeues a buffer without blocking, passes it to `cb`, then enqueues it again.
+    ///
+    /// If no filled buffer is available, an error of kind [`io::ErrorKind::WouldBlock`] is returned
+    /// and `cb` is not called.
+    ///
+    /// Errors are otherwise handled like in [`ReadStream::dequeue`].
+    pub fn try_dequeue<T>(
+        &mut self,
+        cb: impl FnOnce(ReadBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let buf = self
+            .queue
+            .dequeue_timeout(Duration::ZERO, io::ErrorKind::WouldBlock)?;
+        self.finish_dequeue(buf, false, cb)
+    }
+
+    /// Waits at most `timeout` for a filled buffer, passes it to `cb`, then enqueues it again.
+    ///
+    /// If no buffer is filled within `timeout` (for example, because the device has stalled or was
+    /// unplugged), an error of kind [`io::ErrorKind::TimedOut`] is returned and `cb` is not called.
+    ///
+    /// Errors are otherwise handled like in [`ReadStream::dequeue`].
+    pub fn dequeue_timeout<T>(
+        &mut self,
+        timeout: Duration,
+        cb: impl FnOnce(ReadBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let buf = self
+            .queue
+            .dequeue_timeout(timeout, io::ErrorKind::TimedOut)?;
+        self.finish_dequeue(buf, false, cb)
+    }
+
+    /// Dequeues a buffer, passes its dma-buf file descriptor to `cb`, then enqueues it again.
+    ///
+    /// This allows forwarding the filled buffer to another device or process without copying it.
+    /// The file descriptor refers to the same dma-buf as the one at [`ReadBufferView::index`] in
+    /// the list returned by [`ReadStream::export_buffers`]. If the buffer hasn't been exported yet,
+    /// this will export it first. For buffers with multiple planes, the file descriptor of the
+    /// first plane is passed to `cb`, and the others are available via [`ReadPlane::dmabuf_fd`].
+    ///
+    /// Note that the buffer will be reused by the driver as soon as `cb` returns, so any consumer
+    /// of the dma-buf must be done with it by then.
+    ///
+    /// Errors are handled like in [`ReadStream::dequeue`].
+    pub fn dequeue_exported<T>(
+        &mut self,
+        cb: impl FnOnce(BorrowedFd<'_>, ReadBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        self.dequeue_impl(true, |view| cb(view.planes[0].fd.unwrap(), view))
+    }
+
+    fn dequeue_impl<T>(
+        &mut self,
+        export: bool,
+        cb: impl FnOnce(ReadBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let buf = self.queue.dequeue()?;
+        self.finish_dequeue(buf, export, cb)
+    }
+
+    /// Passes the already dequeued buffer `buf` to `cb`, then enqueues it again.
+    fn finish_dequeue<T>(
+        &mut self,
+        buf: RawBuffer,
+        export: bool,
+        cb: impl FnOnce(ReadBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let buffer = self.queue.buffer(buf.index());
+        if export {
+            if let Err(e) =
+                buffer.ensure_exported(self.queue.fd(), self.queue.buf_type, buf.index())
+            {
+                self.queue.enqueue(buf.index())?;
+                return Err(e);
+            }
+        }
+
+        let payload = buffer.payload(&buf);
+        let view = unsafe { buffer.read_view(BufferMeta::new(&buf.buf), &payload) };
+
+        buffer.sync_dmabuf(raw::DMA_BUF_SYNC_START | raw::DMA_BUF_SYNC_READ);
+        let res = cb(view);
+        buffer.sync_dmabuf(raw::DMA_BUF_SYNC_END | raw::DMA_BUF_SYNC_READ);
+        // XXX not sure if we should short-circuit here
+
+        self.queue.enqueue(buf.index())?;
+
+        res
+    }
+
+    /// Dequeues a buffer and returns ownership of it to the application.
+    ///
+    /// Unlike [`ReadStream::dequeue`], this does not enqueue the buffer again right away. Instead,
+    /// the buffer is enqueued when the returned [`OwnedReadBuffer`] is dropped. Meanwhile, the
+    /// driver keeps filling the remaining buffers, so several buffers can be held at once (for
+    /// example, for temporal filtering, or to process them on other threads).
+    ///
+    /// A stream needs more buffers than the application holds at any time, or it will stall.
+    /// Dequeuing from a stream whose buffers are all held by the application returns an error
+    /// instead of blocking forever.
+    pub fn dequeue_owned(&mut self) -> io::Result<OwnedReadBuffer> {
+        let buf = self.queue.dequeue()?;
+        Ok(OwnedReadBuffer::new(self.queue.clone(), &buf))
+    }
+
+    /// Adds a free buffer to `request`, and returns its index.
+    ///
+    /// The buffer is filled once the request is queued with [`Request::queue`], with the controls
+    /// stored in the request applied to it. It can then be dequeued as usual.
+    ///
+    /// The driver does not allow mixing buffers queued with and without requests, so the stream
+    /// has to be paused with [`ReadStream::pause`] before the first call to this method. From then
+    /// on, buffers are no longer enqueued automatically: buffers the application is done with
+    /// (and all buffers of a paused stream) are kept free for this method, and
+    /// [`ReadStream::resume`] only restarts streaming.
+    ///
+    /// Returns an error of kind [`io::ErrorKind::Unsupported`] if the queue does not support
+    /// requests (see [`BufCap::SUPPORTS_REQUESTS`]), and an error of kind
+    /// [`io::ErrorKind::WouldBlock`] if all buffers are queued or held by the application.
+    pub fn enqueue_in_request(&mut self, request: &Request) -> io::Result<u32> {
+        if !self
+            .queue
+            .capabilities()
+            .contains(BufCap::SUPPORTS_REQUESTS)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "queue does not support requests",
+            ));
+        }
+        if !self.queue.uses_requests.load(Ordering::Relaxed) {
+            if self.queue.any_queued() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the stream has to be paused before buffers can be queued in requests",
+                ));
+            }
+            self.queue.uses_requests.store(true, Ordering::Relaxed);
+        }
+
+        let Some(buf_index) = self.queue.find_unqueued() else {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "all buffers are in use",
+            ));
+        };
+        self.queue.enqueue_with(buf_index, |buf| {
+            buf.buf.flags |= BufFlag::REQUEST_FD;
+            buf.buf.tail.request_fd = request.as_raw_fd();
+        })?;
+        Ok(buf_index)
+    }
+
+    /// Tests whether the next call to [`ReadStream::dequeue`] will block.
+    ///
+    /// If this returns `false`, a filled buffer is already available and the next call to
+    /// [`ReadStream::dequeue`] will not block, but finish immediately. If this returns `true`,
+    /// the next call will block until the next buffer is available.
+    pub fn will_block(&self) -> io::Result<bool> {
+        Ok(!self.queue.poll(Duration::ZERO)?)
+    }
+
+    /// Returns an iterator over owned copies of the captured frames.
+    ///
+    /// Each call to [`Iterator::next`] dequeues a buffer like [`ReadStream::dequeue`] does, copies
+    /// it into a [`Frame`], and enqueues it again. By default, the iterator never ends; use the
+    /// methods of [`Frames`] to stop it after a number of frames, after some time, or when the
+    /// driver flags a buffer as erroneous.
+    ///
+    /// To avoid copying the frames, use [`ReadStream::dequeue`] or [`ReadStream::dequeue_owned`]
+    /// instead.
+    pub fn frames(&mut self) -> Frames<'_> {
+        Frames {
+            stream: self,
+            remaining: None,
+            deadline: None,
+            stop_on_error_flag: false,
+            done: false,
+        }
+    }
+}
+
+impl Drop for ReadStream {
+    fn drop(&mut self) {
+        // Turn off the stream to dequeue all buffers.
+        // This must be done before `Buffers` can be dropped safely, at least for userptr I/O.
+        self.queue.close().ok();
+    }
+}
+
+impl AsRawFd for ReadStream {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.queue.file.as_raw_fd()
+    }
+}
+
+impl AsFd for ReadStream {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        unsafe { BorrowedFd::borrow_raw(self.as_raw_fd()) }
+    }
+}
+
+/// An iterator over the frames captured by a [`ReadStream`].
+///
+/// Returned by [`ReadStream::frames`]. Yields owned copies of the captured frames until one of the
+/// configured stop conditions is met.
+///
+/// Errors returned by the driver are yielded once, after which the iterator ends.
+pub struct Frames<'a> {
+    stream: &'a mut ReadStream,
+    remaining: Option<u64>,
+    deadline: Option<Instant>,
+    stop_on_error_flag: bool,
+    done: bool,
+}
+
+impl Frames<'_> {
+    /// Stops the iterator after `count` frames have been captured.
+    pub fn limit(mut self, count: u64) -> Self {
+        self.remaining = Some(count);
+        self
+    }
+
+    /// Stops the iterator once `duration` has elapsed, measured from the call to this method.
+    ///
+    /// The iterator does not wait for a frame past that point, so that it ends on time even if the
+    /// device stops delivering frames.
+    pub fn for_duration(mut self, duration: Duration) -> Self {
+        // Durations too long to be represented are treated as infinite.
+        self.deadline = Instant::now().checked_add(duration);
+        self
+    }
+
+    /// Sets whether the iterator stops at a buffer that the driver flagged as erroneous.
+    ///
+    /// If `true`, such a buffer is not yielded. Instead, an error of kind
+    /// [`io::ErrorKind::InvalidData`] is yielded, and the iterator ends. If `false` (the default),
+    /// these buffers are yielded like any other frame, and [`Frame::is_error`] returns `true`.
+    pub fn stop_on_error_flag(mut self, stop: bool) -> Self {
+        self.stop_on_error_flag = stop;
+        self
+    }
+}
+
+impl Iterator for Frames<'_> {
+    type Item = io::Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.remaining == Some(0) {
+            return None;
+        }
+
+        let res = match self.deadline {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                if timeout.is_zero() {
+                    self.done = true;
+                    return None;
+                }
+                self.stream
+                    .dequeue_timeout(timeout, |view| Ok(view.to_frame()))
+            }
+            None => self.stream.dequeue(|view| Ok(view.to_frame())),
+        };
+
+        match res {
+            Ok(frame) if self.stop_on_error_flag && frame.is_error() => {
+                self.done = true;
+                Some(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("driver flagged buffer {} as erroneous", frame.index()),
+                )))
+            }
+            Ok(frame) => {
+                if let Some(remaining) = &mut self.remaining {
+                    *remaining -= 1;
+                }
+                Some(Ok(frame))
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut && self.deadline.is_some() => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Immutable view into a dequeued (filled) read buffer.
+///
+/// Dereferences to a byte slice containing the data of the buffer's first plane (which, for
+/// single-planar buffers, is the only plane).
+pub struct ReadBufferView<'a> {
+    meta: BufferMeta,
+    planes: [ReadPlane<'a>; VIDEO_MAX_PLANES],
+    num_planes: usize,
+}
+
+impl<'a> ReadBufferView<'a> {
+    /// Returns the index of the buffer that was dequeued.
+    ///
+    /// For streams using imported dma-bufs, this is the index of the dma-buf file descriptor in the
+    /// list passed to [`VideoCaptureDevice::into_dmabuf_stream`], which allows forwarding the
+    /// dma-buf that was just filled to another device.
+    ///
+    /// [`VideoCaptureDevice::into_dmabuf_stream`]: crate::VideoCaptureDevice::into_dmabuf_stream
+    #[inline]
+    pub fn index(&self) -> u32 {
+        self.meta.index
+    }
+
+    /// Returns whether the error flag for this buffer is set.
+    ///
+    /// If this returns `true`, the application should expect data corruption in the buffer data.
+    #[inline]
+    pub fn is_error(&self) -> bool {
+        self.meta.flags.contains(BufFlag::ERROR)
+    }
+
+    /// Returns the time at which the frame was captured.
+    ///
+    /// What this time is relative to is described by [`ReadBufferView::timestamp_type`], and
+    /// which moment during capture it refers to is described by
+    /// [`ReadBufferView::timestamp_source`].
+    #[inline]
+    pub fn timestamp(&self) -> Duration {
+        timeval_to_duration(self.meta.timestamp)
+    }
+
+    /// Returns the clock that [`ReadBufferView::timestamp`] is based on.
+    #[inline]
+    pub fn timestamp_type(&self) -> TimestampType {
+        TimestampType::from_flags(self.meta.flags)
+    }
+
+    /// Returns the point in time during capture that [`ReadBufferView::timestamp`] refers to.
+    #[inline]
+    pub fn timestamp_source(&self) -> TimestampSource {
+        TimestampSource::from_flags(self.meta.flags)
+    }
+
+    /// Returns the frame sequence number.
+    ///
+    /// The driver increments the sequence number for every frame it captures, including frames
+    /// that had to be dropped because no buffer was available. A gap in the sequence numbers of
+    /// consecutive buffers therefore means that frames were lost.
+    ///
+    /// When capturing [`Field::ALTERNATE`] video, the top and bottom fields share a sequence
+    /// number.
+    #[inline]
+    pub fn sequence(&self) -> u32 {
+        self.meta.sequence
+    }
+
+    /// Returns the field order of the video data in this buffer.
+    #[inline]
+    pub fn field(&self) -> Field {
+        self.meta.field
+    }
+
+    /// Returns the buffer's [`Timecode`], if the driver provided one.
+    #[inline]
+    pub fn timecode(&self) -> Option<Timecode> {
+        self.meta.timecode()
+    }
+
+    /// Returns the type of the compressed frame in this buffer, if the driver reported one.
+    ///
+    /// Encoders set this on the buffers they produce.
+    #[inline]
+    pub fn frame_type(&self) -> Option<FrameType> {
+        FrameType::from_flags(self.meta.flags)
+    }
+
+    /// Returns whether this is the last buffer the driver will produce.
+    ///
+    /// Codecs set this on the last buffer they produce after being drained or stopped. Dequeueing
+    /// further buffers will fail with `EPIPE` until the codec is restarted.
+    #[inline]
+    pub fn is_last(&self) -> bool {
+        self.meta.flags.contains(BufFlag::LAST)
+    }
+
+    /// Returns the planes of this buffer.
+    ///
+    /// Single-planar buffers always have exactly one plane. Multi-planar buffers have as many
+    /// planes as the negotiated [`PixFormatMplane`] specifies.
+    ///
+    /// [`PixFormatMplane`]: crate::format::PixFormatMplane
+    #[inline]
+    pub fn planes(&self) -> &[ReadPlane<'a>] {
+        &self.planes[..self.num_planes]
+    }
+
+    /// Returns a reference to the *entire* backing buffer of the first plane.
+    ///
+    /// [`ReadBufferView`] dereferences to the *used* portion of the buffer. For fixed-size
+    /// (uncompressed) image formats, the *used* portion is typically equal to the entire buffer,
+    /// but for compressed formats like MJPEG, the backing buffer, which can be access with this
+    /// method, is usually a lot larger than the actual data of interest.
+    ///
+    /// Normally, this method does not need to be used, as only the used portion of the buffer is
+    /// needed.
+    #[inline]
+    pub fn raw_buffer(&self) -> &'a [u8] {
+        self.planes[0].data
+    }
+
+    /// Copies the payload and metadata of this buffer into an owned [`Frame`].
+    pub fn to_frame(&self) -> Frame {
+        Frame {
+            meta: self.meta,
+            planes: self.planes().iter().map(|plane| plane.to_vec()).collect(),
+        }
+    }
+}
+
+impl Deref for ReadBufferView<'_> {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.planes[0]
+    }
+}
+
+/// A dequeued (filled) read buffer owned by the application.
+///
+/// Returned by [`ReadStream::dequeue_owned`]. The driver will not write to the buffer until the
+/// [`OwnedReadBuffer`] is dropped, which enqueues it again. Since it does not borrow the
+/// [`ReadStream`], it can be sent to other threads, and it may even outlive the stream (in which
+/// case the buffer is simply freed when dropped).
+///
+/// Dereferences to a byte slice containing the data of the buffer's first plane (which, for
+/// single-planar buffers, is the only plane). Metadata and the other planes are accessible through
+/// [`OwnedReadBuffer::view`].
+pub struct OwnedReadBuffer {
+    queue: Arc<Queue>,
+    buffer: Arc<Buffer>,
+    meta: BufferMeta,
+    payload: [Payload; VIDEO_MAX_PLANES],
+}
+
+impl OwnedReadBuffer {
+    fn new(queue: Arc<Queue>, buf: &RawBuffer) -> Self {
+        let buffer = queue.buffer(buf.index());
+        buffer.held.store(true, Ordering::Relaxed);
+        let payload = buffer.payload(buf);
+        buffer.sync_dmabuf(raw::DMA_BUF_SYNC_START | raw::DMA_BUF_SYNC_READ);
+        Self {
+            meta: BufferMeta::new(&buf.buf),
+            payload,
+            buffer,
+            queue,
+        }
+    }
+
+    fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    /// Returns a [`ReadBufferView`] of this buffer, which provides access to its metadata and
+    /// planes.
+    pub fn view(&self) -> ReadBufferView<'_> {
+        // Safety: the buffer is only enqueued again when `self` is dropped.
+        unsafe { self.buffer().read_view(self.meta, &self.payload) }
+    }
+}
+
+impl Deref for OwnedReadBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        let payload = self.payload[0];
+        let data = unsafe { self.buffer().planes[0].data() };
+        &data[payload.data_offset..payload.bytesused]
+    }
+}
+
+impl Drop for OwnedReadBuffer {
+    fn drop(&mut self) {
+        self.buffer()
+            .sync_dmabuf(raw::DMA_BUF_SYNC_END | raw::DMA_BUF_SYNC_READ);
+        if let Err(e) = self.queue.enqueue(self.meta.index) {
+            log::warn!("failed to enqueue buffer {} on drop: {e}", self.meta.index);
+        }
+    }
+}
+
+impl fmt::Debug for OwnedReadBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OwnedReadBuffer")
+            .field("index", &self.meta.index)
+            .field("sequence", &self.meta.sequence)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// Metadata the driver returns along with a dequeued buffer.
+#[derive(Clone, Copy)]
+struct BufferMeta {
+    index: u32,
+    flags: BufFlag,
+    field: Field,
+    timestamp: libc::timeval,
+    timecode: raw::Timecode,
+    sequence: u32,
+}
+
+impl BufferMeta {
+    fn new(buf: &raw::Buffer) -> Self {
+        Self {
+            index: buf.index,
+            flags: buf.flags,
+            field: buf.field,
+            timestamp: buf.timestamp,
+            timecode: buf.timecode,
+            sequence: buf.sequence,
+        }
+    }
+
+    fn timecode(&self) -> Option<Timecode> {
+        if self.flags.contains(BufFlag::TIMECODE) {
+            Some(Timecode(self.timecode))
+        } else {
+            None
+        }
+    }
+}
+
+/// An owned copy of a captured frame.
+///
+/// A [`Frame`] is created by [`ReadBufferView::to_frame`]. Unlike a [`ReadBufferView`], it does not
+/// borrow from the stream, so it can be kept around while the stream continues capturing.
+///
+/// Dereferences to the payload of the frame's first plane (which, for single-planar buffers, is the
+/// only plane).
+pub struct Frame {
+    meta: BufferMeta,
+    planes: Vec<Vec<u8>>,
+}
+
+impl Frame {
+    /// Returns the index of the buffer this frame was copied from.
+    #[inline]
+    pub fn index(&self) -> u32 {
+        self.meta.index
+    }
+
+    /// Returns whether the error flag was set on the buffer this frame was copied from.
+    ///
+    /// If this returns `true`, the application should expect data corruption in the frame data.
+    #[inline]
+    pub fn is_error(&self) -> bool {
+        self.meta.flags.contains(BufFlag::ERROR)
+    }
+
+    /// Returns the time at which the frame was captured.
+    ///
+    /// See [`ReadBufferView::timestamp`].
+    #[inline]
+    pub fn timestamp(&self) -> Duration {
+        timeval_to_duration(self.meta.timestamp)
+    }
+
+    /// Returns the clock that [`Frame::timestamp`] is based on.
+    #[inline]
+    pub fn timestamp_type(&self) -> TimestampType {
+        TimestampType::from_flags(self.meta.flags)
+    }
+
+    /// Returns the point in time during capture that [`Frame::timestamp`] refers to.
+    #[inline]
+    pub fn timestamp_source(&self) -> TimestampSource {
+        TimestampSource::from_flags(self.meta.flags)
+    }
+
+    /// Returns the frame sequence number.
+    ///
+    /// See [`ReadBufferView::sequence`].
+    #[inline]
+    pub fn sequence(&self) -> u32 {
+        self.meta.sequence
+    }
+
+    /// Returns the field order of the video data in this frame.
+    #[inline]
+    pub fn field(&self) -> Field {
+        self.meta.field
+    }
+
+    /// Returns the frame's [`Timecode`], if the driver provided one.
+    #[inline]
+    pub fn timecode(&self) -> Option<Timecode> {
+        self.meta.timecode()
+    }
+
+    /// Returns the type of the compressed frame, if the driver reported one.
+    ///
+    /// See [`ReadBufferView::frame_type`].
+    #[inline]
+    pub fn frame_type(&self) -> Option<FrameType> {
+        FrameType::from_flags(self.meta.flags)
+    }
+
+    /// Returns whether this frame was copied from the last buffer the driver will produce.
+    ///
+    /// See [`ReadBufferView::is_last`].
+    #[inline]
+    pub fn is_last(&self) -> bool {
+        self.meta.flags.contains(BufFlag::LAST)
+    }
+
+    /// Returns the payload of each plane of this frame.
+    #[inline]
+    pub fn planes(&self) -> &[Vec<u8>] {
+        &self.planes
+    }
+
+    /// Consumes the frame and returns the payload of each of its planes.
+    #[inline]
+    pub fn into_planes(self) -> Vec<Vec<u8>> {
+        self.planes
+    }
+}
+
+impl Deref for Frame {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.planes[0]
+    }
+}
+
+impl fmt::Debug for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Frame")
+            .field("index", &self.index())
+            .field("sequence", &self.sequence())
+            .field("timestamp", &self.timestamp())
+            .field("field", &self.field())
+            .field(
+                "planes",
+                &self.planes.iter().map(Vec::len).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+/// Describes what clock a buffer timestamp is based on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TimestampType {
+    /// The driver did not specify the timestamp's clock.
+    Unknown,
+    /// The timestamp was taken from the `CLOCK_MONOTONIC` clock.
+    ///
+    /// It can be compared with times obtained via `clock_gettime(CLOCK_MONOTONIC, ...)`.
+    Monotonic,
+    /// The timestamp was copied from the corresponding output buffer.
+    ///
+    /// This is used by memory-to-memory devices, which pass timestamps through unchanged.
+    Copy,
+}
+
+impl TimestampType {
+    fn from_flags(flags: BufFlag) -> Self {
+        match flags & BufFlag::TIMESTAMP_MASK {
+            BufFlag::TIMESTAMP_MONOTONIC => Self::Monotonic,
+            BufFlag::TIMESTAMP_COPY => Self::Copy,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Describes which moment during capture a buffer timestamp refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampSource {
+    /// The timestamp was taken when the last pixel of the frame was received (End Of Frame).
+    EndOfFrame,
+    /// The timestamp was taken when the exposure of the frame started (Start Of Exposure).
+    StartOfExposure,
+}
+
+impl TimestampSource {
+    fn from_flags(flags: BufFlag) -> Self {
+        if (flags & BufFlag::TIMESTAMP_SRC_MASK) == BufFlag::TIMESTAMP_SRC_SOE {
+            Self::StartOfExposure
+        } else {
+            Self::EndOfFrame
+        }
+    }
+}
+
+/// Type of a compressed frame, as reported by an encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    /// A keyframe (I-frame), which can be decoded on its own.
+    Key,
+    /// A predicted frame (P-frame), which refers to earlier frames.
+    Predicted,
+    /// A bidirectionally predicted frame (B-frame), which may refer to earlier and later frames.
+    Bidirectional,
+}
+
+impl FrameType {
+    fn from_flags(flags: BufFlag) -> Option<Self> {
+        if flags.contains(BufFlag::KEYFRAME) {
+            Some(Self::Key)
+        } else if flags.contains(BufFlag::PFRAME) {
+            Some(Self::Predicted)
+        } else if flags.contains(BufFlag::BFRAME) {
+            Some(Self::Bidirectional)
+        } else {
+            None
+        }
+    }
+}
+
+fn timeval_to_duration(tv: libc::timeval) -> Duration {
+    Duration::new(
+        tv.tv_sec.max(0) as u64,
+        tv.tv_usec.clamp(0, 999_999) as u32 * 1000,
+    )
+}
+
+/// An SMPTE timecode attached to a buffer.
+#[derive(Clone, Copy)]
+pub struct Timecode(raw::Timecode);
+
+impl Timecode {
+    /// Creates a timecode with the given time, and no flags or user bits.
+    pub fn new(
+        timecode_type: TimecodeType,
+        hours: u8,
+        minutes: u8,
+        seconds: u8,
+        frames: u8,
+    ) -> Self {
+        Self(raw::Timecode {
+            type_: timecode_type,
+            flags: TimecodeFlags::empty(),
+            frames,
+            seconds,
+            minutes,
+            hours,
+            userbits: [0; 4],
+        })
+    }
+
+    /// Sets the timecode flags.
+    #[inline]
+    pub fn set_flags(&mut self, flags: TimecodeFlags) {
+        self.0.flags = flags;
+    }
+
+    /// Sets the user bits, whose format is given by [`TimecodeFlags::USERBITS_MASK`].
+    #[inline]
+    pub fn set_user_bits(&mut self, user_bits: [u8; 4]) {
+        self.0.userbits = user_bits;
+    }
+
+    /// Returns the frame rate the timecode is based on.
+    #[inline]
+    pub fn timecode_type(&self) -> TimecodeType {
+        self.0.type_
+    }
+
+    #[inline]
+    pub fn flags(&self) -> TimecodeFlags {
+        self.0.flags
+    }
+
+    #[inline]
+    pub fn hours(&self) -> u8 {
+        self.0.hours
+    }
+
+    #[inline]
+    pub fn minutes(&self) -> u8 {
+        self.0.minutes
+    }
+
+    #[inline]
+    pub fn seconds(&self) -> u8 {
+        self.0.seconds
+    }
+
+    /// Returns the frame count within the current second.
+    #[inline]
+    pub fn frames(&self) -> u8 {
+        self.0.frames
+    }
+
+    /// Returns the user bits, whose format is given by [`TimecodeFlags::USERBITS_MASK`].
+    #[inline]
+    pub fn user_bits(&self) -> [u8; 4] {
+        self.0.userbits
+    }
+}
+
+impl fmt::Debug for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Timecode")
+            .field("type", &self.0.type_)
+            .field("flags", &self.0.flags)
+            .field("hours", &self.0.hours)
+            .field("minutes", &self.0.minutes)
+            .field("seconds", &self.0.seconds)
+            .field("frames", &self.0.frames)
+            .field("userbits", &self.0.userbits)
+            .finish()
+    }
+}
+
+/// A single memory plane of a [`ReadBufferView`].
+///
+/// Dereferences to the plane's payload, which starts at [`ReadPlane::data_offset`] and ends at
+/// [`ReadPlane::bytes_used`].
+#[derive(Default)]
+pub struct ReadPlane<'a> {
+    data: &'a [u8],
+    bytesused: usize,
+    data_offset: usize,
+    fd: Option<BorrowedFd<'a>>,
+}
+
+impl<'a> ReadPlane<'a> {
+    /// Returns the number of bytes of the plane that are occupied by data.
+    ///
+    /// This includes the [`ReadPlane::data_offset`], if there is one.
+    #[inline]
+    pub fn bytes_used(&self) -> usize {
+        self.bytesused
+    }
+
+    /// Returns the offset in bytes at which the payload starts.
+    ///
+    /// Drivers can use the space before the payload to store metadata (like headers). For
+    /// single-planar buffers, this is always 0.
+    #[inline]
+    pub fn data_offset(&self) -> usize {
+        self.data_offset
+    }
+
+    /// Returns a reference to the *entire* backing buffer of this plane.
+    #[inline]
+    pub fn raw_buffer(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the dma-buf file descriptor referring to this plane, if there is one.
+    ///
+    /// This is available for streams using imported dma-bufs, and for buffers that have been
+    /// exported with [`ReadStream::export_buffers`], [`ReadStream::export_planes`], or
+    /// [`ReadStream::dequeue_exported`].
+    #[inline]
+    pub fn dmabuf_fd(&self) -> Option<BorrowedFd<'a>> {
+        self.fd
+    }
+}
+
+impl Deref for ReadPlane<'_> {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.data[self.data_offset..self.bytesused]
+    }
+}
+
+/// A stream that writes to a V4L2 device.
+pub struct WriteStream {
+    queue: Arc<Queue>,
+    /// Image size of each plane of the negotiated format, or 0 if unknown.
+    ///
+    /// Used as the default `bytesused`, since buffers are usually larger than the image.
+    image_sizes: [u32; VIDEO_MAX_PLANES],
+}
+
+impl WriteStream {
+    pub(crate) fn new(file: File, buf_type: BufType, config: &StreamConfig) -> io::Result<Self> {
+        let fd = file.as_raw_fd();
+        let buffers = Buffers::allocate(fd, buf_type, config)?;
+
+        Self::from_buffers(file, buf_type, config.memory, buffers)
+    }
+
+    pub(crate) fn with_user_buffers(
+        file: File,
+        buf_type: BufType,
+        user_buffers: Vec<Vec<UserBuffer>>,
+    ) -> io::Result<Self> {
+        let fd = file.as_raw_fd();
+        let buffers = Buffers::userptr(fd, buf_type, user_buffers)?;
+
+        Self::from_buffers(file, buf_type, Memory::USERPTR, buffers)
+    }
+
+    pub(crate) fn with_dmabufs(
+        file: File,
+        buf_type: BufType,
+        dmabufs: Vec<Vec<OwnedFd>>,
+    ) -> io::Result<Self> {
+        let fd = file.as_raw_fd();
+        let buffers = Buffers::dmabuf(fd, buf_type, dmabufs)?;
+
+        Self::from_buffers(file, buf_type, Memory::DMABUF, buffers)
+    }
+
+    fn from_buffers(
+        file: File,
+        buf_type: BufType,
+        mem_type: Memory,
+        buffers: Buffers,
+    ) -> io::Result<Self> {
+        let queue = Queue::new(file, buffers, buf_type, mem_type);
+        let image_sizes = queue.image_sizes().unwrap_or_else(|e| {
+            log::debug!("failed to query the image size of {buf_type:?}: {e}");
+            [0; VIDEO_MAX_PLANES]
+        });
+        let this = Self { queue, image_sizes };
+        // Output devices won't consume any buffers until streaming is turned on. Queued buffers
+        // are then processed as they come in.
+        this.queue.stream_on()?;
+
+        Ok(this)
+    }
+
+    /// Exports all buffers of this stream as dma-buf file descriptors (via `VIDIOC_EXPBUF`).
+    ///
+    /// The returned file descriptors are in order of their buffer index, so the dma-buf that is
+    /// about to be enqueued by [`WriteStream::enqueue`] is the one at [`WriteBufferView::index`].
+    ///
+    /// For streams that use imported dma-bufs, duplicates of the imported file descriptors are
+    /// returned. Streams using application-allocated buffers cannot be exported. Streams whose
+    /// buffers consist of more than one plane have to use [`WriteStream::export_planes`] instead.
+    ///
+    /// Indices of buffers that have been freed with [`WriteStream::remove_buffers`] are `None`.
+    pub fn export_buffers(&mut self) -> io::Result<Vec<Option<OwnedFd>>> {
+        self.queue.export_single_planar()
+    }
+
+    /// Exports every plane of every buffer of this stream as a dma-buf file descriptor.
+    ///
+    /// The outer [`Vec`] is indexed by the buffer index, the inner one by the plane index. Indices
+    /// of buffers that have been freed with [`WriteStream::remove_buffers`] are `None`.
+    pub fn export_planes(&mut self) -> io::Result<Vec<Option<Vec<OwnedFd>>>> {
+        self.queue.export_all()
+    }
+
+    /// Pauses the stream (via `VIDIOC_STREAMOFF`).
+    ///
+    /// The device stops outputting, and all buffers are returned from the driver. Frames that were
+    /// enqueued but not yet output are discarded. The buffers themselves stay allocated, so the
+    /// stream can be continued cheaply with [`WriteStream::resume`].
+    ///
+    /// Buffers can still be filled and enqueued while the stream is paused. They will be output
+    /// once it is resumed.
+    pub fn pause(&mut self) -> io::Result<()> {
+        self.queue.stream_off()
+    }
+
+    /// Resumes a stream paused with [`WriteStream::pause`] (via `VIDIOC_STREAMON`).
+    ///
+    /// Does nothing if the stream isn't paused.
+    pub fn resume(&mut self) -> io::Result<()> {
+        if self.queue.is_streaming() {
+            return Ok(());
+        }
+
+        self.queue.stream_on()
+    }
+
+    /// Returns whether the stream is paused.
+    pub fn is_paused(&self) -> bool {
+        !self.queue.is_streaming()
+    }
+
+    /// Returns the number of buffers the driver allocated for this stream.
+    ///
+    /// This can differ from the number requested via [`StreamConfig::buffer_count`], since drivers
+    /// may require more buffers or be unable to allocate as many as requested. Buffers added or
+    /// removed with [`WriteStream::create_buffers`] and [`WriteStream::remove_buffers`] are taken into account.
+    pub fn buffer_count(&self) -> usize {
+        self.queue.buffer_count()
+    }
+
+    /// Allocates `count` additional buffers for this stream (via `VIDIOC_CREATE_BUFS`).
+    ///
+    /// The new buffers are sized for the current format of the stream. They are used by the following calls to
+    /// [`WriteStream::enqueue`].
+    ///
+    /// Returns the range of indices of the new buffers. The driver may allocate fewer buffers than
+    /// requested. Streams using imported dma-bufs cannot be extended.
+    pub fn create_buffers(&mut self, count: u32) -> io::Result<Range<u32>> {
+        self.queue.create_buffers(count, None)
+    }
+
+    /// Like [`WriteStream::create_buffers`], but sizes the new buffers for `format`.
+    ///
+    /// The format is not applied to the device. This can be used to allocate buffers ahead of an
+    /// upcoming format change (for example, to a higher resolution), so that they are ready when it
+    /// happens. The variant of `format` has to match the buffer type of the stream.
+    pub fn create_buffers_for_format(
+        &mut self,
+        count: u32,
+        format: &Format,
+    ) -> io::Result<Range<u32>> {
+        self.queue.create_buffers(count, Some(format.to_raw()))
+    }
+
+    /// Frees the buffers with the given `indices` (via `VIDIOC_REMOVE_BUFS`).
+    ///
+    /// This requires Linux 6.10 or later, and a driver that supports it.
+    ///
+    /// Buffers that are owned by the driver (that is, buffers that were enqueued and not yet
+    /// output) cannot be removed. Pausing the stream with [`WriteStream::pause`] returns all
+    /// buffers to the application.
+    pub fn remove_buffers(&mut self, indices: Range<u32>) -> io::Result<()> {
+        self.queue.remove_buffers(indices)
+    }
+
+    /// Returns the capabilities of the stream's buffer queue, as reported by the driver.
+    pub fn capabilities(&self) -> BufCap {
+        self.queue.capabilities()
+    }
+
+    /// Prepares buffer `index` for being queued (via `VIDIOC_PREPARE_BUF`).
+    ///
+    /// This performs the work the driver would otherwise do when the buffer is queued, like cache
+    /// maintenance or pinning application-allocated memory, ahead of time. This can reduce the latency of
+    /// the following [`WriteStream::enqueue`] call for that buffer.
+    ///
+    /// Note that the CPU caches of non-coherent buffers (see [`StreamConfig::non_coherent`]) are
+    /// written back while preparing. Data written to such a buffer afterwards may not reach the
+    /// device, so they should only be prepared if their contents are reused unchanged.
+    ///
+    /// Buffers owned by the driver cannot be prepared, and preparing a buffer twice fails.
+    pub fn prepare_buffer(&mut self, index: u32) -> io::Result<()> {
+        self.queue.prepare(index)
+    }
+
+    /// Sets cache hints that are passed to the driver whenever a buffer is queued or prepared.
+    ///
+    /// `hints` may contain [`BufFlag::NO_CACHE_INVALIDATE`] and [`BufFlag::NO_CACHE_CLEAN`]; other
+    /// flags are rejected with an error of kind [`io::ErrorKind::InvalidInput`].
+    ///
+    /// [`BufFlag::NO_CACHE_CLEAN`] tells the driver that the CPU did not write to a buffer (for
+    /// example, because its contents are reused unchanged), so that the CPU caches don't need to
+    /// be written back. [`BufFlag::NO_CACHE_INVALIDATE`] tells the driver that the CPU will not
+    /// read from the buffer.
+    ///
+    /// Hints are only honored for non-coherent `MMAP` buffers (see [`StreamConfig::non_coherent`])
+    /// of queues that report [`BufCap::SUPPORTS_MMAP_CACHE_HINTS`], and are ignored otherwise.
+    pub fn set_cache_hints(&mut self, hints: BufFlag) -> io::Result<()> {
+        self.queue.set_cache_hints(hints)
+    }
+
+    /// Subscribes to events of type `event_type`.
+    ///
+    /// See [`Device::subscribe_event`][crate::Device::subscribe_event].
+    pub fn subscribe_event(
+        &self,
+        event_type: EventType,
+        id: u32,
+        flags: EventSubscriptionFlags,
+    ) -> io::Result<()> {
+        event::subscribe(self.queue.fd(), event_type, id, flags)
+    }
+
+    /// Unsubscribes from events previously subscribed to with [`WriteStream::subscribe_event`].
+    pub fn unsubscribe_event(&self, event_type: EventType, id: u32) -> io::Result<()> {
+        event::unsubscribe(self.queue.fd(), event_type, id)
+    }
+
+    /// Unsubscribes from all events.
+    pub fn unsubscribe_all_events(&self) -> io::Result<()> {
+        event::unsubscribe(self.queue.fd(), EventType::ALL, 0)
+    }
+
+    /// Waits for a subscribed event to occur, and dequeues it.
+    ///
+    /// This blocks even if the device was opened in non-blocking mode.
+    pub fn dequeue_event(&self) -> io::Result<DequeuedEvent> {
+        event::dequeue(self.queue.fd(), None, io::ErrorKind::TimedOut)
     }
 
-    fn enqueue(&mut self, index: u32) -> io::Result<()> {
-        let mut buf: raw::Buffer = unsafe { mem::zeroed() };
-        buf.type_ = self.buf_type;
-        buf.memory = self.mem_type;
-        buf.index = index;
+    /// Dequeues a pending event without blocking.
+    ///
+    /// If no event is pending, an error of kind [`io::ErrorKind::WouldBlock`] is returned.
+    pub fn try_dequeue_event(&self) -> io::Result<DequeuedEvent> {
+        event::dequeue_timeout(self.queue.fd(), Duration::ZERO, io::ErrorKind::WouldBlock)
+    }
 
-        unsafe {
-            raw::VIDIOC_QBUF.ioctl(self, &mut buf)?;
-        }
+    /// Waits at most `timeout` for a subscribed event to occur, and dequeues it.
+    ///
+    /// If no event occurs in time, an error of kind [`io::ErrorKind::TimedOut`] is returned.
+    pub fn dequeue_event_timeout(&self, timeout: Duration) -> io::Result<DequeuedEvent> {
+        event::dequeue_timeout(self.queue.fd(), timeout, io::ErrorKind::TimedOut)
+    }
 
-        self.buffers.buffers[index as usize].queued = true;
+    /// Passes a non-queued buffer to `cb` to fill it with data, then enqueues it for outputting.
+    ///
+    /// If no unqueued buffer is available, one is dequeued first (which may block until one is
+    /// available). If the device was opened in non-blocking mode (see
+    /// [`OpenOptions::nonblocking`]), an error of kind [`io::ErrorKind::WouldBlock`] is returned
+    /// instead of blocking.
+    ///
+    /// [`OpenOptions::nonblocking`]: crate::OpenOptions::nonblocking
+    pub fn enqueue<T>(
+        &mut self,
+        cb: impl FnOnce(WriteBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let buf_index = self.unqueued_buffer(Queue::dequeue)?;
+        self.fill_and_enqueue(buf_index, cb)
+    }
 
-        Ok(())
+    /// Like [`WriteStream::enqueue`], but never blocks.
+    ///
+    /// If all buffers are still in use by the driver, an error of kind
+    /// [`io::ErrorKind::WouldBlock`] is returned and `cb` is not called.
+    pub fn try_enqueue<T>(
+        &mut self,
+        cb: impl FnOnce(WriteBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let buf_index = self.unqueued_buffer(|queue| {
+            queue.dequeue_timeout(Duration::ZERO, io::ErrorKind::WouldBlock)
+        })?;
+        self.fill_and_enqueue(buf_index, cb)
     }
 
-    fn enqueue_all(&mut self) -> io::Result<()> {
-        for i in 0..self.buffers.buffers.len() {
-            if !self.buffers.buffers[i].queued {
-                self.enqueue(i as u32)?;
-            }
-        }
-        Ok(())
+    /// Like [`WriteStream::enqueue`], but waits at most `timeout` for the driver to release a
+    /// buffer.
+    ///
+    /// If no buffer becomes available in time, an error of kind [`io::ErrorKind::TimedOut`] is
+    /// returned and `cb` is not called.
+    pub fn enqueue_timeout<T>(
+        &mut self,
+        timeout: Duration,
+        cb: impl FnOnce(WriteBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let buf_index =
+            self.unqueued_buffer(|queue| queue.dequeue_timeout(timeout, io::ErrorKind::TimedOut))?;
+        self.fill_and_enqueue(buf_index, cb)
     }
 
-    /// Starts streaming.
+    /// Like [`WriteStream::enqueue`], but adds the buffer to `request` instead of handing it to
+    /// the driver right away.
     ///
-    /// This function can potentially block for a noticeable amount of time.
-    fn stream_on(&mut self) -> io::Result<()> {
-        unsafe {
-            let buf_type = self.buf_type.0 as c_int;
-            raw::VIDIOC_STREAMON.ioctl(self, &buf_type)?;
+    /// The buffer is processed once the request is queued with [`Request::queue`], together with
+    /// any controls stored in the request. Once a queue has been used with requests, all buffers
+    /// have to be enqueued in requests until streaming is stopped.
+    ///
+    /// Returns an error of kind [`io::ErrorKind::Unsupported`] if the queue does not support
+    /// requests (see [`BufCap::SUPPORTS_REQUESTS`]).
+    pub fn enqueue_in_request<T>(
+        &mut self,
+        request: &Request,
+        cb: impl FnOnce(WriteBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        if !self
+            .queue
+            .capabilities()
+            .contains(BufCap::SUPPORTS_REQUESTS)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "queue does not support requests",
+            ));
         }
 
-        Ok(())
+        let buf_index = self.unqueued_buffer(Queue::dequeue)?;
+        self.fill_and_enqueue_impl(buf_index, Some(request.as_raw_fd()), cb)
     }
 
-    // XXX to publicly expose this, we have to handle the fact that it dequeues all buffers
-    fn stream_off(&mut self) -> io::Result<()> {
-        unsafe {
-            let buf_type = self.buf_type.0 as c_int;
-            raw::VIDIOC_STREAMOFF.ioctl(self, &buf_type)?;
-        }
+    /// Blocks until the driver is done with all enqueued buffers.
+    ///
+    /// For output devices, this means that every frame enqueued so far has been output. This is
+    /// useful for making sure that the end of a clip has been output before pausing or dropping
+    /// the stream, which would discard the pending frames.
+    ///
+    /// Unlike the other methods of [`WriteStream`], this also blocks if the device was opened in
+    /// non-blocking mode. Buffers that were enqueued while the stream is paused will never be
+    /// output, so this returns an error in that case.
+    pub fn drain(&mut self) -> io::Result<()> {
+        self.drain_until(None)
+    }
 
-        for b in &mut self.buffers.buffers {
-            b.queued = false;
+    /// Like [`WriteStream::drain`], but waits at most `timeout` for the driver.
+    ///
+    /// If there are still enqueued buffers after `timeout` has elapsed, an error of kind
+    /// [`io::ErrorKind::TimedOut`] is returned.
+    pub fn drain_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.drain_until(Some(deadline)),
+            None => self.drain_until(None),
         }
+    }
 
+    fn drain_until(&mut self, deadline: Option<Instant>) -> io::Result<()> {
+        while self.queue.any_queued() {
+            let timeout = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => Duration::MAX,
+            };
+            self.queue
+                .dequeue_timeout(timeout, io::ErrorKind::TimedOut)?;
+        }
         Ok(())
     }
 
-    /// Dequeues a buffer, passes it to `cb`, then enqueues it again.
+    /// Outputs all enqueued buffers, then stops the stream.
     ///
-    /// If `cb` returns an error, this function will still try to enqueue the buffer again. If that
-    /// fails, the error that occurred during enqueuing will be returned, if it succeeds, the error
-    /// returned by `cb` will be returned.
-    pub fn dequeue<T>(
-        &mut self,
-        cb: impl FnOnce(ReadBufferView<'_>) -> io::Result<T>,
-    ) -> io::Result<T> {
-        let mut buf: raw::Buffer = unsafe { mem::zeroed() };
-        buf.type_ = self.buf_type;
-        buf.memory = self.mem_type;
+    /// Dropping a [`WriteStream`] stops the stream right away, discarding frames that haven't
+    /// been output yet, and ignores errors. This method instead waits for all enqueued frames via
+    /// [`WriteStream::drain`], and reports any error that occurs while shutting down.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.drain()?;
+        self.queue.close()
+    }
 
-        unsafe {
-            raw::VIDIOC_DQBUF.ioctl(self, &mut buf)?;
+    /// Returns the index of a buffer that isn't queued, using `dequeue` to obtain one from the
+    /// driver if necessary.
+    fn unqueued_buffer(
+        &mut self,
+        dequeue: impl FnOnce(&Queue) -> io::Result<RawBuffer>,
+    ) -> io::Result<u32> {
+        match self.queue.find_unqueued() {
+            Some(i) => Ok(i),
+            // All buffers are enqueued with the driver. Dequeue one.
+            None => Ok(dequeue(&self.queue)?.index()),
         }
+    }
 
-        let buffer = &mut self.buffers.buffers[buf.index as usize];
-        buffer.queued = false;
-        let data =
-            unsafe { slice::from_raw_parts(buffer.ptr as *const u8, buffer.length as usize) };
-        let view = ReadBufferView {
-            flags: buf.flags,
-            data,
-            bytesused: buf.bytesused as usize,
-        };
-
-        let res = cb(view);
-        // XXX not sure if we should short-circuit here
+    /// Returns the index of a buffer that isn't queued, dequeuing one if that can be done without
+    /// blocking.
+    pub(crate) fn try_unqueued_buffer(&mut self) -> io::Result<Option<u32>> {
+        let res = self.unqueued_buffer(|queue| {
+            queue.dequeue_timeout(Duration::ZERO, io::ErrorKind::WouldBlock)
+        });
+        match res {
+            Ok(index) => Ok(Some(index)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
 
-        self.enqueue(buf.index)?;
+    /// Dequeues a buffer the driver is done with, if that can be done without blocking.
+    pub(crate) fn try_reclaim(&mut self) -> io::Result<()> {
+        if !self.queue.any_queued() {
+            return Ok(());
+        }
+        match self
+            .queue
+            .dequeue_timeout(Duration::ZERO, io::ErrorKind::WouldBlock)
+        {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
 
-        res
+    /// Returns whether any buffers are currently owned by the driver.
+    pub(crate) fn any_queued(&self) -> bool {
+        self.queue.any_queued()
     }
 
-    /// Tests whether the next call to [`ReadStream::dequeue`] will block.
-    ///
-    /// If this returns `false`, a filled buffer is already available and the next call to
-    /// [`ReadStream::dequeue`] will not block, but finish immediately. If this returns `true`,
-    /// the next call will block until the next buffer is available.
-    pub fn will_block(&self) -> io::Result<bool> {
-        for i in 0..self.buffers.buffers.len() {
-            let mut buf: raw::Buffer = unsafe { mem::zeroed() };
-            buf.type_ = self.buf_type;
-            buf.memory = self.mem_type;
-            buf.index = i as u32;
+    /// Passes the unqueued buffer `buf_index` to `cb`, then enqueues it.
+    pub(crate) fn fill_and_enqueue<T>(
+        &mut self,
+        buf_index: u32,
+        cb: impl FnOnce(WriteBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        self.fill_and_enqueue_impl(buf_index, None, cb)
+    }
 
-            unsafe {
-                raw::VIDIOC_QUERYBUF.ioctl(self, &mut buf)?;
+    /// Like [`WriteStream::fill_and_enqueue`], but adds the buffer to the request `request_fd`
+    /// if one is given.
+    fn fill_and_enqueue_impl<T>(
+        &mut self,
+        buf_index: u32,
+        request_fd: Option<RawFd>,
+        cb: impl FnOnce(WriteBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let buffer = self.queue.buffer(buf_index);
+        assert!(!buffer.queued.load(Ordering::Relaxed));
+
+        let mut meta = WriteMeta::new();
+        let mut bytesused = [0; VIDEO_MAX_PLANES];
+        let mut planes = buffer
+            .planes
+            .iter()
+            .zip(&mut bytesused)
+            .zip(self.image_sizes)
+            .map(|((plane, bytesused), image_size)| {
+                // By default, the image of the negotiated format is output. The plane is usually
+                // larger, since `mmap`ped buffers are rounded up to whole pages.
+                let size = plane.size() as usize;
+                *bytesused = match image_size {
+                    0 => size,
+                    image_size => size.min(image_size as usize),
+                };
+                WritePlane {
+                    data: unsafe { plane.data_mut() },
+                    size,
+                    bytesused: Some(bytesused),
+                }
+            });
+        let view = WriteBufferView {
+            index: buf_index,
+            planes: array::from_fn(|_| planes.next().unwrap_or_default()),
+            num_planes: buffer.planes.len(),
+            meta: &mut meta,
+        };
+        buffer.sync_dmabuf(raw::DMA_BUF_SYNC_START | raw::DMA_BUF_SYNC_WRITE);
+        let res = cb(view);
+        buffer.sync_dmabuf(raw::DMA_BUF_SYNC_END | raw::DMA_BUF_SYNC_WRITE);
+        // If `cb` or `VIDIOC_QBUF` fails, the buffer stays unqueued and is used for the next call.
+        let val = res?;
+        self.queue.enqueue_with(buf_index, |buf| {
+            for (i, bytesused) in bytesused.iter().enumerate().take(buffer.planes.len()) {
+                buf.set_plane_bytesused(i, *bytesused as u32);
             }
-
-            if buf.flags.contains(BufFlag::DONE) {
-                // A buffer is marked `DONE`, so it will be returned immediately when calling
-                // `dequeue`.
-                return Ok(false);
+            meta.fill(&mut buf.buf);
+            if let Some(fd) = request_fd {
+                buf.buf.flags |= BufFlag::REQUEST_FD;
+                buf.buf.tail.request_fd = fd;
             }
-        }
-
-        Ok(true)
+        })?;
+        Ok(val)
     }
 }
 
-impl Drop for ReadStream {
+impl Drop for WriteStream {
     fn drop(&mut self) {
-        // Turn off the stream to dequeue all buffers.
-        // This must be done before `Buffers` can be dropped safely, at least for userptr I/O.
-        self.stream_off().ok();
+        // Turn off the stream to dequeue all buffers, so that `Buffers` can be dropped safely.
+        // This does nothing if the stream was already closed by `WriteStream::finish`.
+        self.queue.close().ok();
     }
 }
 
-impl AsRawFd for ReadStream {
+impl AsRawFd for WriteStream {
     #[inline]
     fn as_raw_fd(&self) -> RawFd {
-        self.file.as_raw_fd()
+        self.queue.file.as_raw_fd()
     }
 }
 
-impl AsFd for ReadStream {
+impl AsFd for WriteStream {
     #[inline]
     fn as_fd(&self) -> BorrowedFd<'_> {
         unsafe { BorrowedFd::borrow_raw(self.as_raw_fd()) }
     }
 }
 
-/// Immutable view into a dequeued (filled) read buffer.
+/// Mutable view into an unqueued write buffer.
 ///
-/// Dereferences to a byte slice.
-pub struct ReadBufferView<'a> {
+/// Dereferences to a byte slice containing the buffer's first plane (which, for single-planar
+/// buffers, is the only plane).
+pub struct WriteBufferView<'a> {
+    index: u32,
+    planes: [WritePlane<'a>; VIDEO_MAX_PLANES],
+    num_planes: usize,
+    meta: &'a mut WriteMeta,
+}
+
+/// Metadata set through a [`WriteBufferView`], applied when the buffer is enqueued.
+struct WriteMeta {
     flags: BufFlag,
-    data: &'a [u8],
-    bytesused: usize,
+    field: Field,
+    timestamp: Duration,
+    timecode: Option<raw::Timecode>,
 }
 
-impl<'a> ReadBufferView<'a> {
-    /// Returns whether the error flag for this buffer is set.
+impl WriteMeta {
+    fn new() -> Self {
+        Self {
+            flags: BufFlag::empty(),
+            field: Field::ANY,
+            timestamp: Duration::ZERO,
+            timecode: None,
+        }
+    }
+
+    fn fill(&self, buf: &mut raw::Buffer) {
+        buf.flags |= self.flags;
+        buf.field = self.field;
+        buf.timestamp = libc::timeval {
+            tv_sec: self.timestamp.as_secs() as _,
+            tv_usec: self.timestamp.subsec_micros() as _,
+        };
+        if let Some(timecode) = self.timecode {
+            buf.flags |= BufFlag::TIMECODE;
+            buf.timecode = timecode;
+        }
+    }
+}
+
+impl<'a> WriteBufferView<'a> {
+    /// Returns the index of the buffer that is about to be enqueued.
     ///
-    /// If this returns `true`, the application should expect data corruption in the buffer data.
+    /// For streams using imported dma-bufs, this is the index of the dma-buf file descriptor in the
+    /// list passed to [`VideoOutputDevice::into_dmabuf_stream`].
+    ///
+    /// [`VideoOutputDevice::into_dmabuf_stream`]: crate::VideoOutputDevice::into_dmabuf_stream
     #[inline]
-    pub fn is_error(&self) -> bool {
-        self.flags.contains(BufFlag::ERROR)
+    pub fn index(&self) -> u32 {
+        self.index
     }
 
-    /// Returns a reference to the *entire* backing buffer.
+    /// Returns the planes of this buffer.
     ///
-    /// [`ReadBufferView`] dereferences to the *used* portion of the buffer. For fixed-size
-    /// (uncompressed) image formats, the *used* portion is typically equal to the entire buffer,
-    /// but for compressed formats like MJPEG, the backing buffer, which can be access with this
-    /// method, is usually a lot larger than the actual data of interest.
+    /// Single-planar buffers always have exactly one plane. Multi-planar buffers have as many
+    /// planes as the negotiated [`PixFormatMplane`] specifies.
     ///
-    /// Normally, this method does not need to be used, as only the used portion of the buffer is
-    /// needed.
+    /// [`PixFormatMplane`]: crate::format::PixFormatMplane
     #[inline]
-    pub fn raw_buffer(&self) -> &'a [u8] {
-        self.data
+    pub fn planes_mut(&mut self) -> &mut [WritePlane<'a>] {
+        &mut self.planes[..self.num_planes]
     }
-}
-
-impl Deref for ReadBufferView<'_> {
-    type Target = [u8];
 
+    /// Sets the number of bytes of the first plane that contain data.
+    ///
+    /// Shorthand for calling [`WritePlane::set_bytes_used`] on the first plane, which, for
+    /// single-planar buffers, is the only plane.
     #[inline]
-    fn deref(&self) -> &Self::Target {
-        &self.data[..self.bytesused]
+    pub fn set_bytes_used(&mut self, bytes_used: usize) {
+        self.planes[0].set_bytes_used(bytes_used);
     }
-}
-
-/// A stream that writes to a V4L2 device.
-pub struct WriteStream {
-    file: File,
-    buffers: Buffers,
-    next_unqueued_buffer: Option<usize>,
-    buf_type: BufType,
-    mem_type: Memory,
-}
 
-impl WriteStream {
-    pub(crate) fn new(
-        file: File,
-        buf_type: BufType,
-        mem_type: Memory,
-        buffer_count: u32,
-    ) -> io::Result<Self> {
-        let fd = file.as_raw_fd();
-        let buffers = Buffers::allocate(fd, buf_type, mem_type, buffer_count)?;
-
-        Ok(Self {
-            file,
-            buffers,
-            next_unqueued_buffer: Some(0),
-            buf_type,
-            mem_type,
-        })
+    /// Sets the timestamp of the buffer.
+    ///
+    /// Output devices may use it to schedule the frame, and memory-to-memory devices copy it to the
+    /// corresponding capture buffer. Defaults to zero.
+    #[inline]
+    pub fn set_timestamp(&mut self, timestamp: Duration) {
+        self.meta.timestamp = timestamp;
     }
 
-    fn enqueue_buffer(&mut self, index: u32) -> io::Result<()> {
-        let mut buf: raw::Buffer = unsafe { mem::zeroed() };
-        buf.type_ = self.buf_type;
-        buf.memory = self.mem_type;
-        buf.index = index;
-
-        unsafe {
-            raw::VIDIOC_QBUF.ioctl(self, &mut buf)?;
-        }
-
-        self.buffers.buffers[index as usize].queued = true;
+    /// Sets the field order of the buffer's contents.
+    ///
+    /// Defaults to [`Field::ANY`], which lets the driver pick the field order of the current format.
+    #[inline]
+    pub fn set_field(&mut self, field: Field) {
+        self.meta.field = field;
+    }
 
-        Ok(())
+    /// Attaches an SMPTE timecode to the buffer.
+    #[inline]
+    pub fn set_timecode(&mut self, timecode: Timecode) {
+        self.meta.timecode = Some(timecode.0);
     }
 
-    /// Passes a non-queued buffer to `cb` to fill it with data, then enqueues it for outputting.
+    /// Sets flags describing the buffer's contents.
     ///
-    /// If no unqueued buffer is available, one is dequeued first (which may block until one is
-    /// available).
-    pub fn enqueue<T>(
-        &mut self,
-        cb: impl FnOnce(WriteBufferView<'_>) -> io::Result<T>,
-    ) -> io::Result<T> {
-        let buf_index = match self.next_unqueued_buffer {
-            Some(i) => i,
-            None => {
-                // All buffers are enqueued with the driver. Dequeue one.
-                let mut buf: raw::Buffer = unsafe { mem::zeroed() };
-                buf.type_ = self.buf_type;
-                buf.memory = self.mem_type;
-
-                unsafe {
-                    raw::VIDIOC_DQBUF.ioctl(self, &mut buf)?;
-                }
-
-                let buf_index = buf.index as usize;
-                self.buffers.buffers[buf_index].queued = false;
-                buf_index
-            }
-        };
-
-        let buffer = &mut self.buffers.buffers[buf_index];
-        assert!(!buffer.queued);
-
-        let data =
-            unsafe { slice::from_raw_parts_mut(buffer.ptr as *mut u8, buffer.length as usize) };
-        let view = WriteBufferView { data };
-        match cb(view) {
-            Ok(val) => match self.enqueue_buffer(buf_index as u32) {
-                Ok(()) => {
-                    match self.next_unqueued_buffer {
-                        Some(i) => {
-                            if i + 1 == self.buffers.buffers.len() {
-                                // Out of buffers we know are unqueued.
-                                self.next_unqueued_buffer = None;
-                            } else {
-                                // Next buffer was never enqueued, so use that next.
-                                self.next_unqueued_buffer = Some(i + 1);
-                            }
-                        }
-                        None => {
-                            // Do nothing, next call will dequeue.
-                        }
-                    }
-                    Ok(val)
-                }
-                Err(e) => {
-                    // `buf_index` is definitely unqueued now.
-                    self.next_unqueued_buffer = Some(buf_index);
-                    Err(e)
-                }
-            },
-            Err(e) => {
-                // `buf_index` is definitely unqueued now.
-                self.next_unqueued_buffer = Some(buf_index);
-                Err(e)
-            }
-        }
+    /// Only [`BufFlag::KEYFRAME`], [`BufFlag::PFRAME`] and [`BufFlag::BFRAME`] (the frame type of
+    /// compressed data), and [`BufFlag::LAST`] (the last buffer of a stream) can be set this way.
+    /// Other flags are managed by this library and ignored.
+    #[inline]
+    pub fn set_flags(&mut self, flags: BufFlag) {
+        let allowed = BufFlag::KEYFRAME | BufFlag::PFRAME | BufFlag::BFRAME | BufFlag::LAST;
+        self.meta.flags = flags & allowed;
     }
 }
 
-impl AsRawFd for WriteStream {
+impl Deref for WriteBufferView<'_> {
+    type Target = [u8];
+
     #[inline]
-    fn as_raw_fd(&self) -> RawFd {
-        self.file.as_raw_fd()
+    fn deref(&self) -> &Self::Target {
+        &self.planes[0]
     }
 }
 
-impl AsFd for WriteStream {
+impl DerefMut for WriteBufferView<'_> {
     #[inline]
-    fn as_fd(&self) -> BorrowedFd<'_> {
-        unsafe { BorrowedFd::borrow_raw(self.as_raw_fd()) }
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.planes[0]
     }
 }
 
-/// Mutable view into an unqueued write buffer.
+/// A single memory plane of a [`WriteBufferView`].
 ///
 /// Dereferences to a byte slice.
-pub struct WriteBufferView<'a> {
+#[derive(Default)]
+pub struct WritePlane<'a> {
     data: &'a mut [u8],
+    /// Size of the plane, which may exceed the size of `data` for dma-bufs that can't be mapped.
+    size: usize,
+    bytesused: Option<&'a mut usize>,
 }
 
-impl Deref for WriteBufferView<'_> {
+impl WritePlane<'_> {
+    /// Returns the number of bytes of the plane that will be output.
+    ///
+    /// Defaults to the image size of the negotiated format ([`PixFormat::size_image`]), or to the
+    /// size of the whole plane if the format doesn't specify one.
+    ///
+    /// [`PixFormat::size_image`]: crate::format::PixFormat::size_image
+    #[inline]
+    pub fn bytes_used(&self) -> usize {
+        self.bytesused.as_deref().copied().unwrap_or(0)
+    }
+
+    /// Sets the number of bytes of the plane that contain data.
+    ///
+    /// This has to be set when the size of the data varies, like for compressed formats.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `bytes_used` exceeds the size of the plane (which may be larger than
+    /// the image size of the format).
+    #[inline]
+    pub fn set_bytes_used(&mut self, bytes_used: usize) {
+        assert!(
+            bytes_used <= self.size,
+            "`bytes_used` exceeds plane size ({bytes_used} > {})",
+            self.size,
+        );
+        if let Some(bytesused) = &mut self.bytesused {
+            **bytesused = bytes_used;
+        }
+    }
+}
+
+impl Deref for WritePlane<'_> {
     type Target = [u8];
 
     #[inline]
@@ -471,7 +3386,7 @@ impl Deref for WriteBufferView<'_> {
     }
 }
 
-impl DerefMut for WriteBufferView<'_> {
+impl DerefMut for WritePlane<'_> {
     #[inline]
     fn deref_mut(&mut self) -> &mut Self::Target {
         self.data
@@ -482,6 +3397,107 @@ impl DerefMut for WriteBufferView<'_> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn user_buffer_is_page_aligned() {
+        let buf = UserBuffer::new(1);
+        assert_eq!(buf.len(), 1);
+        assert_eq!(buf.ptr.as_ptr() as usize % page_size(), 0);
+        assert_eq!(buf.layout.size(), page_size());
+        assert!(buf.iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn timestamp_flags() {
+        let flags = BufFlag::DONE | BufFlag::TIMESTAMP_MONOTONIC | BufFlag::TIMESTAMP_SRC_SOE;
+        assert_eq!(TimestampType::from_flags(flags), TimestampType::Monotonic);
+        assert_eq!(
+            TimestampSource::from_flags(flags),
+            TimestampSource::StartOfExposure
+        );
+
+        let flags = BufFlag::TIMESTAMP_COPY;
+        assert_eq!(TimestampType::from_flags(flags), TimestampType::Copy);
+        assert_eq!(
+            TimestampSource::from_flags(flags),
+            TimestampSource::EndOfFrame
+        );
+
+        let flags = BufFlag::empty();
+        assert_eq!(TimestampType::from_flags(flags), TimestampType::Unknown);
+    }
+
+    #[test]
+    fn write_meta() {
+        let mut buf: raw::Buffer = unsafe { mem::zeroed() };
+        buf.flags = BufFlag::NO_CACHE_CLEAN;
+
+        let mut meta = WriteMeta::new();
+        meta.flags = BufFlag::KEYFRAME;
+        meta.timestamp = Duration::new(3, 500_000_999);
+        meta.timecode = Some(Timecode::new(TimecodeType::T_25FPS, 1, 2, 3, 4).0);
+        meta.fill(&mut buf);
+
+        assert_eq!(
+            buf.flags,
+            BufFlag::NO_CACHE_CLEAN | BufFlag::KEYFRAME | BufFlag::TIMECODE
+        );
+        assert_eq!(
+            timeval_to_duration(buf.timestamp),
+            Duration::new(3, 500_000_000)
+        );
+        assert_eq!(buf.timecode.minutes, 2);
+    }
+
+    #[test]
+    fn nonblocking_flag() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let [rd, wr] = fds.map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+        let flags = || unsafe { libc::fcntl(rd.as_raw_fd(), libc::F_GETFL) };
+
+        set_nonblocking(rd.as_raw_fd(), true).unwrap();
+        assert_ne!(flags() & libc::O_NONBLOCK, 0);
+        set_nonblocking(rd.as_raw_fd(), false).unwrap();
+        assert_eq!(flags() & libc::O_NONBLOCK, 0);
+        drop(wr);
+    }
+
+    #[test]
+    fn export_skips_removed_buffers() {
+        let dmabuf = OwnedFd::from(File::open("/dev/null").unwrap());
+        let plane = Plane {
+            ptr: NonNull::<c_void>::dangling().as_ptr(),
+            length: 0,
+            alloc: AllocType::DmaBuf {
+                fd: dmabuf,
+                size: 4096,
+            },
+            exported: OnceLock::new(),
+        };
+        let buffers = Buffers {
+            buffers: vec![None, Some(Arc::new(Buffer::new(vec![plane])))],
+            memory_flags: 0,
+            capabilities: BufCap::empty(),
+        };
+
+        // Imported dma-bufs don't need `VIDIOC_EXPBUF`, so no device is required.
+        let exported = buffers.export_all(-1, BufType::VIDEO_CAPTURE).unwrap();
+        assert_eq!(exported.len(), 2);
+        assert!(exported[0].is_none());
+        let planes = exported.into_iter().nth(1).unwrap().unwrap();
+        assert_eq!(planes.len(), 1);
+        single_plane(planes).unwrap();
+        assert!(single_plane(Vec::new()).is_err());
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn buffer_ioctl_struct_sizes() {
+        // The sizes are encoded in the ioctl request codes, so they have to match the kernel's.
+        assert_eq!(mem::size_of::<raw::CreateBuffers>(), 256);
+        assert_eq!(mem::size_of::<raw::RemoveBuffers>(), 64);
+    }
+
     #[test]
     fn stream_types_are_send_sync() {
         fn assert<T: Send + Sync>() {}
@@ -490,5 +3506,74 @@ mod tests {
         assert::<ReadStream>();
         assert::<WriteBufferView<'_>>();
         assert::<ReadBufferView<'_>>();
+        assert::<Frame>();
+        assert::<OwnedReadBuffer>();
+        assert::<UserBuffer>();
+    }
+
+    /// Returns a `vivid` device with the given capabilities, if the `vivid` module is loaded.
+    fn vivid_device(caps: crate::CapabilityFlags) -> Option<crate::Device> {
+        crate::list().ok()?.filter_map(Result::ok).find(|device| {
+            device
+                .capabilities()
+                .is_ok_and(|c| c.driver() == "vivid" && c.device_capabilities().contains(caps))
+        })
+    }
+
+    #[test]
+    fn output_stream_consumes_buffers() {
+        use crate::format::{PixFormat, PixelFormat};
+        use std::{sync::mpsc, thread, time::Duration};
+
+        let Some(device) = vivid_device(crate::CapabilityFlags::VIDEO_OUTPUT) else {
+            eprintln!("no `vivid` output device found, skipping test");
+            return;
+        };
+        let output = device
+            .video_output(PixFormat::new(640, 480, PixelFormat::RGB32))
+            .unwrap();
+        let mut stream = output.into_stream().unwrap();
+
+        // Enqueueing more buffers than were allocated only works if the driver hands some back,
+        // which it only does once streaming is on.
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for _ in 0..8 {
+                stream
+                    .enqueue(|mut buf| {
+                        buf.fill(0);
+                        Ok(())
+                    })
+                    .unwrap();
+            }
+            sender.send(()).unwrap();
+        });
+        receiver
+            .recv_timeout(Duration::from_secs(5))
+            .expect("output stream did not consume any buffers");
+    }
+
+    #[test]
+    fn output_bytes_used_defaults_to_image_size() {
+        use crate::format::{PixFormat, PixelFormat};
+
+        let Some(device) = vivid_device(crate::CapabilityFlags::VIDEO_OUTPUT) else {
+            eprintln!("no `vivid` output device found, skipping test");
+            return;
+        };
+        // 100x100 RGB3 images are not a multiple of the page size.
+        let output = device
+            .video_output(PixFormat::new(100, 100, PixelFormat::RGB3))
+            .unwrap();
+        let size_image = output.format().size_image() as usize;
+        let mut stream = output.into_stream().unwrap();
+        stream
+            .enqueue(|mut buf| {
+                let plane = &buf.planes_mut()[0];
+                assert_eq!(plane.bytes_used(), size_image);
+                assert!(plane.len() >= size_image);
+                Ok(())
+            })
+            .unwrap();
     }
 }
@@ -0,0 +1,223 @@
+//! Asynchronous streams for [async-io] based runtimes (like `smol` and `async-std`).
+//!
+//! This module is only available when the `async-io` Cargo feature is enabled.
+//!
+//! [async-io]: https://docs.rs/async-io
+
+use std::io;
+use std::os::unix::prelude::*;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use async_io::Async;
+use futures_core::Stream;
+
+use super::reactor::{self, Interest, Reactor};
+use super::{
+    set_nonblocking, Frame, OwnedReadBuffer, ReadBufferView, ReadStream, WriteBufferView,
+    WriteStream,
+};
+
+// SAFETY (for all uses of `Async::get_mut`): the streams never replace or close their file
+// descriptor.
+impl<S: AsFd> Reactor for Async<S> {
+    type Stream = S;
+
+    fn stream(&self) -> &S {
+        self.get_ref()
+    }
+
+    fn stream_mut(&mut self) -> &mut S {
+        unsafe { self.get_mut() }
+    }
+
+    fn poll_io<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        interest: Interest,
+        mut op: impl FnMut(&mut S) -> io::Result<T>,
+    ) -> Poll<io::Result<T>> {
+        loop {
+            match op(unsafe { self.get_mut() }) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => match interest {
+                    Interest::Readable => ready!(self.poll_readable(cx))?,
+                    Interest::Writable => ready!(self.poll_writable(cx))?,
+                },
+                res => return Poll::Ready(res),
+            }
+        }
+    }
+}
+
+/// A [`ReadStream`] driven by the async-io reactor.
+///
+/// Instead of blocking the calling thread until a buffer is filled, [`AsyncReadStream::dequeue`]
+/// waits for the device to become readable. This puts the device file descriptor into non-blocking
+/// mode.
+///
+/// [`AsyncReadStream`] also implements [`Stream`], yielding owned copies of every captured frame.
+/// The [`Stream`] ends after yielding an error (for example, because the stream was paused), until
+/// [`AsyncReadStream::resume`] is called.
+pub struct AsyncReadStream {
+    inner: Async<ReadStream>,
+    finished: bool,
+}
+
+impl AsyncReadStream {
+    /// Registers `stream` with the async-io reactor.
+    pub fn new(stream: ReadStream) -> io::Result<Self> {
+        Ok(Self {
+            inner: Async::new(stream)?,
+            finished: false,
+        })
+    }
+
+    /// Returns a reference to the wrapped [`ReadStream`].
+    #[inline]
+    pub fn get_ref(&self) -> &ReadStream {
+        self.inner.get_ref()
+    }
+
+    /// Pauses the stream. See [`ReadStream::pause`].
+    pub fn pause(&mut self) -> io::Result<()> {
+        self.inner.stream_mut().pause()
+    }
+
+    /// Resumes the stream. See [`ReadStream::resume`].
+    ///
+    /// This also restarts the [`Stream`] implementation if it has ended after an error.
+    pub fn resume(&mut self) -> io::Result<()> {
+        self.inner.stream_mut().resume()?;
+        self.finished = false;
+        Ok(())
+    }
+
+    /// Deregisters the stream from the reactor and returns the wrapped [`ReadStream`].
+    ///
+    /// This puts the device file descriptor back into blocking mode.
+    pub fn into_inner(self) -> io::Result<ReadStream> {
+        let stream = self.inner.into_inner()?;
+        set_nonblocking(stream.as_raw_fd(), false)?;
+        Ok(stream)
+    }
+
+    /// Waits for a filled buffer, passes it to `cb`, then enqueues it again.
+    ///
+    /// Errors are handled like in [`ReadStream::dequeue`].
+    ///
+    /// This method is cancellation safe: if the returned future is dropped before completing, no
+    /// buffer has been dequeued.
+    pub async fn dequeue<T>(
+        &mut self,
+        cb: impl FnOnce(ReadBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        reactor::dequeue(&mut self.inner, cb).await
+    }
+
+    /// Waits for a filled buffer and returns ownership of it to the application.
+    ///
+    /// See [`ReadStream::dequeue_owned`] for details. This method is cancellation safe.
+    pub async fn dequeue_owned(&mut self) -> io::Result<OwnedReadBuffer> {
+        reactor::dequeue_owned(&mut self.inner).await
+    }
+}
+
+impl Stream for AsyncReadStream {
+    type Item = io::Result<Frame>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        reactor::poll_next_frame(&mut this.inner, &mut this.finished, cx)
+    }
+}
+
+impl AsRawFd for AsyncReadStream {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl AsFd for AsyncReadStream {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
+/// A [`WriteStream`] driven by the async-io reactor.
+///
+/// Instead of blocking the calling thread until the driver is done with a buffer,
+/// [`AsyncWriteStream::enqueue`] waits for the device to become writable. This puts the device
+/// file descriptor into non-blocking mode.
+pub struct AsyncWriteStream {
+    inner: Async<WriteStream>,
+}
+
+impl AsyncWriteStream {
+    /// Registers `stream` with the async-io reactor.
+    pub fn new(stream: WriteStream) -> io::Result<Self> {
+        Ok(Self {
+            inner: Async::new(stream)?,
+        })
+    }
+
+    /// Returns a reference to the wrapped [`WriteStream`].
+    #[inline]
+    pub fn get_ref(&self) -> &WriteStream {
+        self.inner.get_ref()
+    }
+
+    /// Pauses the stream. See [`WriteStream::pause`].
+    pub fn pause(&mut self) -> io::Result<()> {
+        self.inner.stream_mut().pause()
+    }
+
+    /// Resumes the stream. See [`WriteStream::resume`].
+    pub fn resume(&mut self) -> io::Result<()> {
+        self.inner.stream_mut().resume()
+    }
+
+    /// Deregisters the stream from the reactor and returns the wrapped [`WriteStream`].
+    ///
+    /// This puts the device file descriptor back into blocking mode.
+    pub fn into_inner(self) -> io::Result<WriteStream> {
+        let stream = self.inner.into_inner()?;
+        set_nonblocking(stream.as_raw_fd(), false)?;
+        Ok(stream)
+    }
+
+    /// Passes a non-queued buffer to `cb` to fill it with data, then enqueues it for outputting.
+    ///
+    /// If no unqueued buffer is available, this waits until the driver is done with one.
+    ///
+    /// This method is cancellation safe: if the returned future is dropped before completing, no
+    /// buffer has been enqueued.
+    pub async fn enqueue<T>(
+        &mut self,
+        cb: impl FnOnce(WriteBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        reactor::enqueue(&mut self.inner, cb).await
+    }
+
+    /// Waits until the driver is done with all enqueued buffers.
+    ///
+    /// See [`WriteStream::drain`] for details. This method is cancellation safe.
+    pub async fn drain(&mut self) -> io::Result<()> {
+        reactor::drain(&mut self.inner).await
+    }
+}
+
+impl AsRawFd for AsyncWriteStream {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl AsFd for AsyncWriteStream {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
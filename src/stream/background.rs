@@ -0,0 +1,372 @@
+//! Capturing on a background thread.
+
+use std::io;
+use std::os::unix::prelude::*;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::{poll_until, Frame, ReadStream};
+
+/// Captures from a [`ReadStream`] on a dedicated thread, keeping only the newest frame.
+///
+/// This is useful for applications that only care about the most recent frame, and might process
+/// frames more slowly than the device captures them. The capture thread copies every frame out of
+/// the stream's buffers, so the driver never runs out of buffers to fill. Each new frame replaces
+/// the previous one; frames that were replaced before anyone looked at them are counted by
+/// [`BackgroundCapture::dropped_frames`].
+///
+/// Frames can be accessed via [`BackgroundCapture::latest`], or via any number of
+/// [`FrameReceiver`]s created with [`BackgroundCapture::receiver`], which can wait for new frames.
+///
+/// Dropping the [`BackgroundCapture`] stops the capture thread and drops the stream. Use
+/// [`BackgroundCapture::stop`] to get the stream back instead.
+pub struct BackgroundCapture {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<io::Result<ReadStream>>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    cond: Condvar,
+    /// `eventfd` used to wake up the capture thread when it should stop.
+    stop: OwnedFd,
+}
+
+struct State {
+    latest: Option<Arc<Frame>>,
+    /// Number of frames published so far.
+    sequence: u64,
+    /// Whether `latest` has been handed out to anyone.
+    taken: bool,
+    dropped: u64,
+    /// Set once the capture thread has exited, with the error that caused it to exit (if any).
+    stopped: Option<Option<(io::ErrorKind, String)>>,
+}
+
+impl Shared {
+    fn new() -> io::Result<Self> {
+        let stop = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if stop == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let stop = unsafe { OwnedFd::from_raw_fd(stop) };
+
+        Ok(Self {
+            state: Mutex::new(State {
+                latest: None,
+                sequence: 0,
+                taken: false,
+                dropped: 0,
+                stopped: None,
+            }),
+            cond: Condvar::new(),
+            stop,
+        })
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // The lock is never held while running user code, so poisoning is impossible.
+        self.state.lock().unwrap()
+    }
+
+    fn take_latest(&self) -> Option<Arc<Frame>> {
+        let mut state = self.state();
+        state.taken = true;
+        state.latest.clone()
+    }
+
+    fn publish(&self, frame: Frame) {
+        let mut state = self.state();
+        if state.latest.is_some() && !state.taken {
+            state.dropped += 1;
+        }
+        state.latest = Some(Arc::new(frame));
+        state.sequence += 1;
+        state.taken = false;
+        drop(state);
+        self.cond.notify_all();
+    }
+
+    fn finish(&self, error: Option<&io::Error>) {
+        let mut state = self.state();
+        state.stopped = Some(error.map(|e| (e.kind(), e.to_string())));
+        drop(state);
+        self.cond.notify_all();
+    }
+
+    fn signal_stop(&self) {
+        let value = 1u64;
+        unsafe {
+            libc::write(
+                self.stop.as_raw_fd(),
+                (&value as *const u64).cast(),
+                size_of::<u64>(),
+            );
+        }
+    }
+}
+
+impl BackgroundCapture {
+    /// Spawns a thread that captures from `stream`.
+    pub fn spawn(stream: ReadStream) -> io::Result<Self> {
+        let shared = Arc::new(Shared::new()?);
+        let thread = thread::Builder::new()
+            .name("linuxvideo capture".into())
+            .spawn({
+                let shared = shared.clone();
+                move || {
+                    let res = capture_loop(&shared, stream);
+                    shared.finish(res.as_ref().err());
+                    res
+                }
+            })?;
+
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// Creates a [`FrameReceiver`] that can be used to wait for new frames from another thread.
+    ///
+    /// The receiver only considers frames captured after this call to be new.
+    pub fn receiver(&self) -> FrameReceiver {
+        FrameReceiver {
+            shared: self.shared.clone(),
+            seen: self.shared.state().sequence,
+        }
+    }
+
+    /// Returns the most recently captured frame, if any.
+    pub fn latest(&self) -> Option<Arc<Frame>> {
+        self.shared.take_latest()
+    }
+
+    /// Returns the number of frames that were replaced by a newer frame before they were accessed.
+    pub fn dropped_frames(&self) -> u64 {
+        self.shared.state().dropped
+    }
+
+    /// Returns whether the capture thread has stopped.
+    ///
+    /// The capture thread only stops on its own when capturing fails. That error is returned by
+    /// [`BackgroundCapture::stop`].
+    pub fn is_stopped(&self) -> bool {
+        self.shared.state().stopped.is_some()
+    }
+
+    /// Stops the capture thread and returns the stream.
+    ///
+    /// If the capture thread stopped because of an error, that error is returned and the stream is
+    /// dropped.
+    pub fn stop(mut self) -> io::Result<ReadStream> {
+        self.join()
+    }
+
+    fn join(&mut self) -> io::Result<ReadStream> {
+        self.shared.signal_stop();
+        let thread = self.thread.take().expect("capture thread already joined");
+        match thread.join() {
+            Ok(res) => res,
+            Err(payload) => std::panic::resume_unwind(payload),
+        }
+    }
+}
+
+impl Drop for BackgroundCapture {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            self.join().ok();
+        }
+    }
+}
+
+fn capture_loop(shared: &Shared, mut stream: ReadStream) -> io::Result<ReadStream> {
+    loop {
+        let mut fds = [
+            stream.queue.pollfd(),
+            libc::pollfd {
+                fd: shared.stop.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        poll_until(&mut fds, None)?;
+        if fds[1].revents != 0 {
+            return Ok(stream);
+        }
+
+        let frame = stream.dequeue(|view| Ok(view.to_frame()))?;
+        shared.publish(frame);
+    }
+}
+
+/// Waits for frames captured by a [`BackgroundCapture`].
+///
+/// Every receiver keeps track of the frames it has seen, so that [`FrameReceiver::recv`] only
+/// returns frames that are newer than the last one it returned. Receivers can be cloned and sent to
+/// other threads.
+#[derive(Clone)]
+pub struct FrameReceiver {
+    shared: Arc<Shared>,
+    /// Sequence number of the last frame returned.
+    seen: u64,
+}
+
+impl FrameReceiver {
+    /// Returns the most recently captured frame, if any.
+    ///
+    /// Unlike [`FrameReceiver::recv`], this does not wait and may return a frame that was already
+    /// returned before.
+    pub fn latest(&self) -> Option<Arc<Frame>> {
+        self.shared.take_latest()
+    }
+
+    /// Waits for a frame that is newer than the last one returned by this method.
+    ///
+    /// If the capture thread has stopped, an error is returned.
+    pub fn recv(&mut self) -> io::Result<Arc<Frame>> {
+        self.recv_until(None)
+    }
+
+    /// Like [`FrameReceiver::recv`], but waits at most `timeout` for a new frame.
+    ///
+    /// If no new frame is captured in time, an error of kind [`io::ErrorKind::TimedOut`] is
+    /// returned.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> io::Result<Arc<Frame>> {
+        // Timeouts too large to be represented wait indefinitely.
+        self.recv_until(Instant::now().checked_add(timeout))
+    }
+
+    fn recv_until(&mut self, deadline: Option<Instant>) -> io::Result<Arc<Frame>> {
+        let mut state = self.shared.state();
+        loop {
+            if state.sequence != self.seen {
+                if let Some(frame) = &state.latest {
+                    let frame = frame.clone();
+                    self.seen = state.sequence;
+                    state.taken = true;
+                    return Ok(frame);
+                }
+            }
+
+            if let Some(error) = &state.stopped {
+                return Err(match error {
+                    Some((kind, msg)) => io::Error::new(*kind, msg.clone()),
+                    None => io::Error::new(io::ErrorKind::BrokenPipe, "capture thread has stopped"),
+                });
+            }
+
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(io::ErrorKind::TimedOut.into());
+                    }
+                    self.shared
+                        .cond
+                        .wait_timeout(state, deadline - now)
+                        .unwrap()
+                        .0
+                }
+                None => self.shared.cond.wait(state).unwrap(),
+            };
+        }
+    }
+
+    /// Returns the number of frames that were replaced by a newer frame before they were accessed.
+    ///
+    /// See [`BackgroundCapture::dropped_frames`].
+    pub fn dropped_frames(&self) -> u64 {
+        self.shared.state().dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use super::super::BufferMeta;
+    use super::*;
+
+    fn frame(index: u32) -> Frame {
+        let mut buf: crate::raw::Buffer = unsafe { mem::zeroed() };
+        buf.index = index;
+        Frame {
+            meta: BufferMeta::new(&buf),
+            planes: Vec::new(),
+        }
+    }
+
+    fn receiver(shared: &Arc<Shared>) -> FrameReceiver {
+        FrameReceiver {
+            shared: shared.clone(),
+            seen: shared.state().sequence,
+        }
+    }
+
+    #[test]
+    fn dropped_frames() {
+        let shared = Arc::new(Shared::new().unwrap());
+        let receiver = receiver(&shared);
+
+        shared.publish(frame(0));
+        shared.publish(frame(1));
+        assert_eq!(receiver.dropped_frames(), 1);
+
+        // Frames that were accessed are not counted as dropped.
+        assert_eq!(receiver.latest().unwrap().index(), 1);
+        shared.publish(frame(2));
+        assert_eq!(receiver.dropped_frames(), 1);
+        shared.publish(frame(3));
+        assert_eq!(receiver.dropped_frames(), 2);
+    }
+
+    #[test]
+    fn recv() {
+        let shared = Arc::new(Shared::new().unwrap());
+        shared.publish(frame(0));
+        let mut receiver = receiver(&shared);
+
+        // Frames published before the receiver was created are not new.
+        let err = receiver
+            .recv_timeout(Duration::from_millis(10))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        shared.publish(frame(1));
+        shared.publish(frame(2));
+        assert_eq!(receiver.recv().unwrap().index(), 2);
+        assert_eq!(receiver.dropped_frames(), 2);
+
+        let publisher = thread::spawn({
+            let shared = shared.clone();
+            move || {
+                thread::sleep(Duration::from_millis(10));
+                shared.publish(frame(3));
+            }
+        });
+        assert_eq!(receiver.recv().unwrap().index(), 3);
+        publisher.join().unwrap();
+    }
+
+    #[test]
+    fn recv_after_stop() {
+        let shared = Arc::new(Shared::new().unwrap());
+        let mut receiver = receiver(&shared);
+
+        // A frame published before stopping is still returned.
+        shared.publish(frame(0));
+        shared.finish(None);
+        assert_eq!(receiver.recv().unwrap().index(), 0);
+        let err = receiver.recv().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+
+        let shared = Arc::new(Shared::new().unwrap());
+        let mut receiver = self::receiver(&shared);
+        shared.finish(Some(&io::ErrorKind::NotFound.into()));
+        let err = receiver.recv_timeout(Duration::from_secs(5)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
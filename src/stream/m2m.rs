@@ -0,0 +1,136 @@
+//! Streaming on memory-to-memory devices.
+
+use std::os::unix::prelude::*;
+use std::{io, slice};
+
+use super::{poll_until, ReadBufferView, ReadStream, WriteBufferView, WriteStream};
+
+/// The pair of streams of a memory-to-memory device.
+///
+/// Frames are passed to the device via the [`WriteStream`] of the OUTPUT queue, and the processed
+/// frames are received via the [`ReadStream`] of the CAPTURE queue. Both streams share the
+/// device's file descriptor.
+///
+/// Created by [`M2mDevice::into_stream`][crate::M2mDevice::into_stream].
+pub struct M2mStream {
+    output: WriteStream,
+    capture: ReadStream,
+}
+
+impl M2mStream {
+    pub(crate) fn new(output: WriteStream, capture: ReadStream) -> Self {
+        Self { output, capture }
+    }
+
+    /// Returns the stream of the OUTPUT queue, which passes frames to the device.
+    #[inline]
+    pub fn output(&self) -> &WriteStream {
+        &self.output
+    }
+
+    /// Returns the stream of the OUTPUT queue, which passes frames to the device.
+    #[inline]
+    pub fn output_mut(&mut self) -> &mut WriteStream {
+        &mut self.output
+    }
+
+    /// Returns the stream of the CAPTURE queue, which receives processed frames from the device.
+    #[inline]
+    pub fn capture(&self) -> &ReadStream {
+        &self.capture
+    }
+
+    /// Returns the stream of the CAPTURE queue, which receives processed frames from the device.
+    #[inline]
+    pub fn capture_mut(&mut self) -> &mut ReadStream {
+        &mut self.capture
+    }
+
+    /// Splits this into the streams of the OUTPUT and CAPTURE queue.
+    ///
+    /// This allows feeding and draining the device from different threads.
+    pub fn into_parts(self) -> (WriteStream, ReadStream) {
+        (self.output, self.capture)
+    }
+
+    /// Passes a single frame through the device.
+    ///
+    /// `fill` is called with an OUTPUT buffer to fill with the source frame, which is then
+    /// enqueued. Afterwards, this waits for the device to produce a CAPTURE buffer, and passes it
+    /// to `read`.
+    ///
+    /// This assumes that the device produces exactly one CAPTURE buffer for every OUTPUT buffer,
+    /// which holds for scalers and format converters like `vim2m`. Codecs may hold on to several
+    /// OUTPUT buffers before producing any output; for those, use [`M2mStream::output_mut`] and
+    /// [`M2mStream::capture_mut`] (or [`M2mStream::into_parts`]) to drive the queues
+    /// independently.
+    ///
+    /// Most memory-to-memory drivers copy the timestamp of the OUTPUT buffer to the corresponding
+    /// CAPTURE buffer, which can be used to match them up (see [`WriteBufferView::set_timestamp`]).
+    pub fn process<T>(
+        &mut self,
+        fill: impl FnOnce(WriteBufferView<'_>) -> io::Result<()>,
+        read: impl FnOnce(ReadBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        self.output.enqueue(fill)?;
+        self.capture.dequeue(read)
+    }
+
+    /// Passes a frame to the device, handling processed frames while waiting for a free OUTPUT
+    /// buffer.
+    ///
+    /// If all OUTPUT buffers are queued, the device may not release any of them until the
+    /// application has dequeued some CAPTURE buffers. So instead of blocking in
+    /// [`WriteStream::enqueue`], this waits for either queue to become ready, passing every
+    /// processed frame to `on_capture` until an OUTPUT buffer is available. That buffer is then
+    /// passed to `fill` and enqueued.
+    ///
+    /// Frames that are processed after `fill` returns are not dequeued; they are passed to
+    /// `on_capture` in a later call, or can be dequeued via [`M2mStream::capture_mut`].
+    pub fn feed<T>(
+        &mut self,
+        fill: impl FnOnce(WriteBufferView<'_>) -> io::Result<T>,
+        mut on_capture: impl FnMut(ReadBufferView<'_>) -> io::Result<()>,
+    ) -> io::Result<T> {
+        let buf_index = loop {
+            if let Some(index) = self.output.queue.find_unqueued() {
+                break index;
+            }
+
+            // Both queues share a file descriptor, which signals `POLLIN` for the CAPTURE queue
+            // and `POLLOUT` for the OUTPUT queue.
+            let mut pollfd = libc::pollfd {
+                fd: self.output.queue.fd(),
+                events: libc::POLLIN | libc::POLLOUT,
+                revents: 0,
+            };
+            poll_until(slice::from_mut(&mut pollfd), None)?;
+            if pollfd.revents & libc::POLLIN != 0 {
+                let buf = self.capture.queue.dequeue()?;
+                self.capture.finish_dequeue(buf, false, &mut on_capture)?;
+                if pollfd.revents & libc::POLLOUT == 0 {
+                    continue;
+                }
+            }
+
+            // This also reports the error if the driver signaled one via `POLLERR`.
+            break self.output.queue.dequeue()?.index();
+        };
+
+        self.output.fill_and_enqueue(buf_index, fill)
+    }
+}
+
+impl AsRawFd for M2mStream {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.capture.as_raw_fd()
+    }
+}
+
+impl AsFd for M2mStream {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.capture.as_fd()
+    }
+}
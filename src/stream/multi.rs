@@ -0,0 +1,257 @@
+//! Capturing from several devices at once.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use super::{poll_until, OwnedReadBuffer, ReadBufferView, ReadStream};
+
+/// Captures from several [`ReadStream`]s on a single thread.
+///
+/// [`MultiStream::dequeue`] waits until any of the streams has captured a frame, and passes it to a
+/// callback together with the index of the stream it came from.
+///
+/// [`MultiStream::dequeue_frameset`] instead collects one frame from every stream, such that the
+/// timestamps of all frames lie within a tolerance window. This can be used to correlate the frames
+/// of several cameras. Since the timestamps of different devices are only comparable if they are
+/// based on the same clock, all streams should use [`TimestampType::Monotonic`] timestamps.
+///
+/// [`TimestampType::Monotonic`]: super::TimestampType::Monotonic
+pub struct MultiStream {
+    streams: Vec<ReadStream>,
+    /// Frames collected for the next frameset, indexed like `streams`.
+    pending: Vec<Option<OwnedReadBuffer>>,
+    /// Index of the stream to check first in the next call to `dequeue`, for fairness.
+    next: usize,
+}
+
+impl MultiStream {
+    /// Creates a [`MultiStream`] capturing from `streams`.
+    ///
+    /// Streams are identified by their index in `streams`.
+    pub fn new(streams: Vec<ReadStream>) -> Self {
+        Self {
+            pending: streams.iter().map(|_| None).collect(),
+            streams,
+            next: 0,
+        }
+    }
+
+    /// Returns the streams captured from.
+    #[inline]
+    pub fn streams(&self) -> &[ReadStream] {
+        &self.streams
+    }
+
+    /// Returns the streams captured from.
+    #[inline]
+    pub fn streams_mut(&mut self) -> &mut [ReadStream] {
+        &mut self.streams
+    }
+
+    /// Returns the wrapped streams.
+    pub fn into_streams(self) -> Vec<ReadStream> {
+        self.streams
+    }
+
+    /// Waits until any stream has captured a frame, and passes it to `cb`.
+    ///
+    /// `cb` is also passed the index of the stream the frame was captured from. If several
+    /// streams have a frame ready, they are served in turn by subsequent calls.
+    ///
+    /// If any stream reports an error (for example, because it is paused), it is returned.
+    pub fn dequeue<T>(
+        &mut self,
+        cb: impl FnOnce(usize, ReadBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        self.dequeue_until(None, cb)
+    }
+
+    /// Like [`MultiStream::dequeue`], but waits at most `timeout` for a frame.
+    ///
+    /// If no frame is captured in time, an error of kind [`io::ErrorKind::TimedOut`] is returned
+    /// and `cb` is not called.
+    pub fn dequeue_timeout<T>(
+        &mut self,
+        timeout: Duration,
+        cb: impl FnOnce(usize, ReadBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        self.dequeue_until(Instant::now().checked_add(timeout), cb)
+    }
+
+    fn dequeue_until<T>(
+        &mut self,
+        deadline: Option<Instant>,
+        cb: impl FnOnce(usize, ReadBufferView<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let ready = self.wait(deadline)?;
+        let count = self.streams.len();
+        let index = (0..count)
+            .map(|i| (self.next + i) % count)
+            .find(|&i| ready[i])
+            .expect("`poll` returned without a ready stream");
+        self.next = (index + 1) % count;
+
+        self.streams[index].dequeue(|view| cb(index, view))
+    }
+
+    /// Waits until every stream has captured a frame, such that the timestamps of all frames lie
+    /// within `tolerance` of each other, and passes the frames to `cb`.
+    ///
+    /// The frames are passed in the order of their streams. Frames that cannot be part of a
+    /// frameset because the other streams have already moved on are dropped.
+    ///
+    /// While waiting, one frame of every stream is kept, so every stream needs at least 2 buffers.
+    pub fn dequeue_frameset<T>(
+        &mut self,
+        tolerance: Duration,
+        cb: impl FnOnce(&[ReadBufferView<'_>]) -> io::Result<T>,
+    ) -> io::Result<T> {
+        self.dequeue_frameset_until(tolerance, None, cb)
+    }
+
+    /// Like [`MultiStream::dequeue_frameset`], but waits at most `timeout` for a frameset.
+    ///
+    /// If no complete frameset is captured in time, an error of kind [`io::ErrorKind::TimedOut`] is
+    /// returned and `cb` is not called. The frames collected so far are kept for the next call.
+    pub fn dequeue_frameset_timeout<T>(
+        &mut self,
+        tolerance: Duration,
+        timeout: Duration,
+        cb: impl FnOnce(&[ReadBufferView<'_>]) -> io::Result<T>,
+    ) -> io::Result<T> {
+        self.dequeue_frameset_until(tolerance, Instant::now().checked_add(timeout), cb)
+    }
+
+    fn dequeue_frameset_until<T>(
+        &mut self,
+        tolerance: Duration,
+        deadline: Option<Instant>,
+        cb: impl FnOnce(&[ReadBufferView<'_>]) -> io::Result<T>,
+    ) -> io::Result<T> {
+        if self.streams.is_empty() {
+            return cb(&[]);
+        }
+
+        while !self.collect_frameset(tolerance) {
+            let ready = self.wait(deadline)?;
+            for (i, ready) in ready.into_iter().enumerate() {
+                if ready {
+                    // Release the older frame first, so that the driver can fill it again.
+                    self.pending[i] = None;
+                    self.pending[i] = Some(self.streams[i].dequeue_owned()?);
+                }
+            }
+        }
+
+        let frames = self
+            .pending
+            .iter_mut()
+            .map(|frame| frame.take().unwrap())
+            .collect::<Vec<_>>();
+        let views = frames.iter().map(|frame| frame.view()).collect::<Vec<_>>();
+        cb(&views)
+    }
+
+    /// Drops pending frames that are too old to be part of a frameset, and returns whether the
+    /// remaining ones form a complete frameset.
+    fn collect_frameset(&mut self, tolerance: Duration) -> bool {
+        let mut timestamps = self
+            .pending
+            .iter()
+            .map(|frame| frame.as_ref().map(|f| f.view().timestamp()))
+            .collect::<Vec<_>>();
+        let complete = match_frameset(&mut timestamps, tolerance);
+        for (frame, timestamp) in self.pending.iter_mut().zip(timestamps) {
+            if timestamp.is_none() {
+                *frame = None;
+            }
+        }
+        complete
+    }
+
+    /// Waits until any of the streams has a frame ready (or reports an error), and returns which
+    /// ones do.
+    fn wait(&self, deadline: Option<Instant>) -> io::Result<Vec<bool>> {
+        if self.streams.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "`MultiStream` has no streams",
+            ));
+        }
+
+        let mut fds = self
+            .streams
+            .iter()
+            .map(|stream| stream.queue.pollfd())
+            .collect::<Vec<_>>();
+        if !poll_until(&mut fds, deadline)? {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        Ok(fds.iter().map(|fd| fd.revents != 0).collect())
+    }
+}
+
+/// Removes the timestamps of frames that are too old to be part of a frameset, and returns whether
+/// the remaining ones form a complete frameset.
+///
+/// `timestamps` contains the timestamp of the pending frame of each stream, or `None` if the
+/// stream has no pending frame.
+fn match_frameset(timestamps: &mut [Option<Duration>], tolerance: Duration) -> bool {
+    let Some(newest) = timestamps.iter().flatten().copied().max() else {
+        return false;
+    };
+
+    // Frames from other streams can only get newer, so these will never find a match.
+    for timestamp in &mut *timestamps {
+        if timestamp.is_some_and(|t| t.checked_add(tolerance).is_some_and(|end| end < newest)) {
+            *timestamp = None;
+        }
+    }
+
+    timestamps.iter().all(Option::is_some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn frameset_matches_within_tolerance() {
+        let mut timestamps = [Some(10 * MS), Some(12 * MS), Some(15 * MS)];
+        assert!(match_frameset(&mut timestamps, 5 * MS));
+        assert_eq!(timestamps, [Some(10 * MS), Some(12 * MS), Some(15 * MS)]);
+    }
+
+    #[test]
+    fn frameset_drops_old_frames() {
+        let mut timestamps = [Some(10 * MS), Some(20 * MS), None];
+        assert!(!match_frameset(&mut timestamps, 5 * MS));
+        assert_eq!(timestamps, [None, Some(20 * MS), None]);
+
+        // A frame exactly at the edge of the window is kept.
+        let mut timestamps = [Some(15 * MS), Some(20 * MS)];
+        assert!(match_frameset(&mut timestamps, 5 * MS));
+        assert_eq!(timestamps, [Some(15 * MS), Some(20 * MS)]);
+    }
+
+    #[test]
+    fn frameset_incomplete() {
+        let mut timestamps = [None, None];
+        assert!(!match_frameset(&mut timestamps, 5 * MS));
+        assert_eq!(timestamps, [None, None]);
+
+        let mut timestamps = [Some(10 * MS), None];
+        assert!(!match_frameset(&mut timestamps, 5 * MS));
+        assert_eq!(timestamps, [Some(10 * MS), None]);
+    }
+
+    #[test]
+    fn frameset_tolerance_overflow() {
+        let mut timestamps = [Some(Duration::ZERO), Some(Duration::MAX)];
+        assert!(match_frameset(&mut timestamps, Duration::MAX));
+        assert_eq!(timestamps, [Some(Duration::ZERO), Some(Duration::MAX)]);
+    }
+}